@@ -0,0 +1,23 @@
+; Built-in ROM properties for rustARI, in Stella's stella.pro format.
+; Entries are keyed by the MD5 of the ROM image. Only the ROMs bundled with
+; rustARI are listed; a stella.pro next to the ROM or in the working
+; directory, such as the one Stella ships, is merged over these at load time.
+
+"Cartridge.MD5" "609fc6978f84e66710009404a6744e74"
+"Cartridge.Name" "Hello"
+"Cartridge.Type" "4K"
+"Display.Format" "NTSC"
+""
+
+"Cartridge.MD5" "6495188dea5da83982e6772539b7cea3"
+"Cartridge.Name" "VSYNC Test"
+"Cartridge.Type" "4K"
+"Display.Format" "NTSC"
+""
+
+"Cartridge.MD5" "72ffbef6504b75e69ee1045af9075f66"
+"Cartridge.Name" "Space Invaders (8bitworkshop)"
+"Cartridge.Type" "4K"
+"Controller.Left" "JOYSTICK"
+"Display.Format" "NTSC"
+""
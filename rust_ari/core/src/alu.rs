@@ -0,0 +1,249 @@
+//! The 6502's arithmetic: addition and subtraction in binary and decimal
+//! mode, comparison, shifts and rotates. Each operation returns its result
+//! together with the flags it affects, and the CPU decides which of them to
+//! store.
+//!
+//! Decimal mode follows the NMOS parts the 2600 uses. Only A and C are
+//! meaningful for valid BCD there; N, V and Z come out of intermediate
+//! results, and invalid BCD digits are corrected the way the hardware does.
+
+/// A result and the flags it produces. `zero` isn't always `value == 0`:
+/// decimal addition sets Z from the binary sum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Output {
+    pub value: u8,
+    pub carry: bool,
+    pub zero: bool,
+    pub negative: bool,
+    /// Only set by addition and subtraction; everything else leaves V alone.
+    pub overflow: bool,
+}
+
+impl Output {
+    /// An output whose Z and N come from the value itself.
+    fn new(value: u8, carry: bool) -> Output {
+        return Output {
+            value,
+            carry,
+            zero: value == 0,
+            negative: value & 0x80 != 0,
+            overflow: false,
+        };
+    }
+}
+
+/// A + B + C.
+pub fn adc(a: u8, b: u8, carry: bool, decimal: bool) -> Output {
+    let c = carry as u16;
+    let binary = a as u16 + b as u16 + c;
+    let overflow = |sum: u16| !(a ^ b) as u16 & (a as u16 ^ sum) & 0x80 != 0;
+    if !decimal {
+        let mut out = Output::new(binary as u8, binary > 0xFF);
+        out.overflow = overflow(binary);
+        return out;
+    }
+
+    // add nibble by nibble, fixing up a digit that passes 9 before the
+    // next one is added. N and V are sampled between fixing the low digit
+    // and the high one, and Z comes from the binary sum.
+    let mut lo = (a & 0x0F) as u16 + (b & 0x0F) as u16 + c;
+    if lo > 0x09 {
+        lo = ((lo + 0x06) & 0x0F) + 0x10;
+    }
+    let mut sum = (a & 0xF0) as u16 + (b & 0xF0) as u16 + lo;
+    let negative = sum & 0x80 != 0;
+    let over = overflow(sum);
+    if sum >= 0xA0 {
+        sum += 0x60;
+    }
+    return Output {
+        value: sum as u8,
+        carry: sum > 0xFF,
+        zero: binary as u8 == 0,
+        negative,
+        overflow: over,
+    };
+}
+
+/// A - B - !C. In decimal mode all the flags are those of the binary
+/// subtraction; only the accumulator is corrected.
+pub fn sbc(a: u8, b: u8, carry: bool, decimal: bool) -> Output {
+    let mut out = adc(a, !b, carry, false);
+    if !decimal {
+        return out;
+    }
+
+    let borrow = 1 - carry as i16;
+    let mut lo = (a & 0x0F) as i16 - (b & 0x0F) as i16 - borrow;
+    if lo < 0 {
+        lo = ((lo - 0x06) & 0x0F) - 0x10;
+    }
+    let mut diff = (a & 0xF0) as i16 - (b & 0xF0) as i16 + lo;
+    if diff < 0 {
+        diff -= 0x60;
+    }
+    out.value = diff as u8;
+    return out;
+}
+
+/// CMP, CPX and CPY: a subtraction that only keeps the flags.
+pub fn compare(reg: u8, value: u8) -> Output {
+    return Output::new(reg.wrapping_sub(value), reg >= value);
+}
+
+pub fn asl(value: u8) -> Output {
+    return Output::new(value << 1, value & 0x80 != 0);
+}
+
+pub fn lsr(value: u8) -> Output {
+    return Output::new(value >> 1, value & 0x01 != 0);
+}
+
+pub fn rol(value: u8, carry: bool) -> Output {
+    return Output::new(value << 1 | carry as u8, value & 0x80 != 0);
+}
+
+pub fn ror(value: u8, carry: bool) -> Output {
+    return Output::new(value >> 1 | (carry as u8) << 7, value & 0x01 != 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every operand pair with both carries in.
+    fn all_inputs() -> impl Iterator<Item = (u8, u8, bool)> {
+        return (0..0x2_0000u32).map(|i| ((i >> 9) as u8, (i >> 1) as u8, i & 1 != 0));
+    }
+
+    /// Decimal ADC as given step by step in Bruce Clark's "Decimal Mode"
+    /// tutorial on 6502.org, sequences 1 and 2.
+    fn reference_decimal_adc(a: u8, b: u8, carry: bool) -> (u8, bool, bool, bool) {
+        let c = carry as i32;
+        let mut al = (a & 0x0F) as i32 + (b & 0x0F) as i32 + c;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+        let mut acc = (a & 0xF0) as i32 + (b & 0xF0) as i32 + al;
+        if acc >= 0xA0 {
+            acc += 0x60;
+        }
+        let signed = (a & 0xF0) as i8 as i32 + (b & 0xF0) as i8 as i32 + al;
+        let negative = signed & 0x80 != 0;
+        let overflow = signed < -128 || signed > 127;
+        return (acc as u8, acc >= 0x100, negative, overflow);
+    }
+
+    /// Decimal SBC from sequence 3 of the same tutorial.
+    fn reference_decimal_sbc(a: u8, b: u8, carry: bool) -> u8 {
+        let mut al = (a & 0x0F) as i32 - (b & 0x0F) as i32 + carry as i32 - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut acc = (a & 0xF0) as i32 - (b & 0xF0) as i32 + al;
+        if acc < 0 {
+            acc -= 0x60;
+        }
+        return acc as u8;
+    }
+
+    #[test]
+    fn test_binary_adc_exhaustive() {
+        for (a, b, carry) in all_inputs() {
+            let out = adc(a, b, carry, false);
+            let sum = a as u16 + b as u16 + carry as u16;
+            let signed = a as i8 as i16 + b as i8 as i16 + carry as i16;
+            assert_eq!(out, Output {
+                value: sum as u8,
+                carry: sum > 0xFF,
+                zero: sum as u8 == 0,
+                negative: sum & 0x80 != 0,
+                overflow: signed < -128 || signed > 127,
+            }, "{:02X} + {:02X} + {}", a, b, carry as u8);
+        }
+    }
+
+    #[test]
+    fn test_binary_sbc_exhaustive() {
+        for (a, b, carry) in all_inputs() {
+            let out = sbc(a, b, carry, false);
+            let borrow = !carry as i16;
+            let diff = a as i16 - b as i16 - borrow;
+            let signed = a as i8 as i16 - b as i8 as i16 - borrow;
+            assert_eq!(out, Output {
+                value: diff as u8,
+                carry: diff >= 0,
+                zero: diff as u8 == 0,
+                negative: diff & 0x80 != 0,
+                overflow: signed < -128 || signed > 127,
+            }, "{:02X} - {:02X} - {}", a, b, borrow);
+        }
+    }
+
+    #[test]
+    fn test_decimal_adc_exhaustive() {
+        for (a, b, carry) in all_inputs() {
+            let out = adc(a, b, carry, true);
+            let (value, carry_out, negative, overflow) = reference_decimal_adc(a, b, carry);
+            let binary = a.wrapping_add(b).wrapping_add(carry as u8);
+            assert_eq!(out, Output {
+                value,
+                carry: carry_out,
+                zero: binary == 0,
+                negative,
+                overflow,
+            }, "{:02X} + {:02X} + {}", a, b, carry as u8);
+        }
+    }
+
+    #[test]
+    fn test_decimal_sbc_exhaustive() {
+        for (a, b, carry) in all_inputs() {
+            let out = sbc(a, b, carry, true);
+            let binary = sbc(a, b, carry, false);
+            assert_eq!(out, Output {
+                value: reference_decimal_sbc(a, b, carry),
+                ..binary
+            }, "{:02X} - {:02X} - {}", a, b, !carry as u8);
+        }
+    }
+
+    #[test]
+    fn test_decimal_quirks() {
+        // 99 + 1 wraps to 00 with carry, but the binary sum 9A isn't zero
+        let out = adc(0x99, 0x01, false, true);
+        assert_eq!((out.value, out.carry, out.zero), (0x00, true, false));
+        // N and V are taken before the high digit is corrected
+        let out = adc(0x79, 0x00, true, true);
+        assert_eq!((out.value, out.negative, out.overflow), (0x80, true, true));
+        // invalid digits are corrected as the hardware does
+        assert_eq!(adc(0x0F, 0x0F, false, true).value, 0x14);
+        assert_eq!(sbc(0x00, 0x01, true, true).value, 0x99);
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(0x40, 0x40), Output::new(0x00, true));
+        assert_eq!(compare(0x10, 0x20), Output::new(0xF0, false));
+    }
+
+    #[test]
+    fn test_shifts_and_rotates_exhaustive() {
+        for value in 0..=0xFFu8 {
+            for &carry in [false, true].iter() {
+                let asl = asl(value);
+                let rol = rol(value, carry);
+                assert_eq!(rol.value, asl.value | carry as u8);
+                assert_eq!(rol.carry, asl.carry);
+                assert_eq!(asl.carry, value & 0x80 != 0);
+                let lsr = lsr(value);
+                let ror = ror(value, carry);
+                assert_eq!(ror.value, lsr.value | (carry as u8) << 7);
+                assert_eq!(ror.carry, lsr.carry);
+                assert_eq!(lsr.negative, false);
+                assert_eq!(ror.negative, carry);
+                assert_eq!(ror.zero, ror.value == 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! TIA sound generation. Each channel is clocked twice per scanline, giving
+//! about 31.4 kHz on NTSC; the divider, 4 bit pulse and 5 bit noise counters
+//! follow the TIA schematics the way Stella models them.
+
+use crate::state::{Reader, Snapshot, StateError, Writer};
+use crate::tia::CLOCKS_PER_LINE;
+use crate::tv::TvStandard;
+
+pub const AUDC0: u8 = 0x15;
+pub const AUDC1: u8 = 0x16;
+pub const AUDF0: u8 = 0x17;
+pub const AUDF1: u8 = 0x18;
+pub const AUDV0: u8 = 0x19;
+pub const AUDV1: u8 = 0x1A;
+
+/// Color clocks within a scanline at which the two audio phases happen.
+pub const PHASE0_CLOCKS: [usize; 2] = [9, 81];
+pub const PHASE1_CLOCKS: [usize; 2] = [37, 149];
+
+/// Audio samples generated per scanline.
+pub const SAMPLES_PER_LINE: usize = 2;
+
+/// Samples per second on `standard`, from its color clock: a sample every
+/// half scanline, e.g. 31399 Hz on NTSC.
+pub fn sample_rate(standard: TvStandard) -> u32 {
+    return (standard.color_clock() * SAMPLES_PER_LINE as u64 / CLOCKS_PER_LINE as u64) as u32;
+}
+
+#[derive(Default, Clone)]
+pub struct AudioChannel {
+    audc: u8,
+    audf: u8,
+    audv: u8,
+    clock_enable: bool,
+    noise_feedback: bool,
+    noise_counter_bit4: bool,
+    pulse_counter_hold: bool,
+    div_counter: u8,
+    pulse_counter: u8,
+    noise_counter: u8,
+}
+
+impl AudioChannel {
+    pub fn set_audc(&mut self, val: u8) {
+        self.audc = val & 0x0F;
+    }
+
+    pub fn set_audf(&mut self, val: u8) {
+        self.audf = val & 0x1F;
+    }
+
+    pub fn set_audv(&mut self, val: u8) {
+        self.audv = val & 0x0F;
+    }
+
+    /// First half of an audio clock: work out the feedback for the counters
+    /// and step the frequency divider.
+    pub fn phase0(&mut self) {
+        if self.clock_enable {
+            self.noise_counter_bit4 = self.noise_counter & 0x01 != 0;
+
+            self.pulse_counter_hold = match self.audc & 0x03 {
+                0x02 => self.noise_counter & 0x1E != 0x02,
+                0x03 => !self.noise_counter_bit4,
+                _ => false,
+            };
+
+            self.noise_feedback = match self.audc & 0x03 {
+                0x00 => {
+                    (self.pulse_counter ^ self.noise_counter) & 0x01 != 0
+                        || !(self.noise_counter != 0 || self.pulse_counter != 0x0A)
+                        || self.audc & 0x0C == 0
+                }
+                _ => {
+                    ((self.noise_counter & 0x04 != 0) ^ (self.noise_counter & 0x01 != 0))
+                        || self.noise_counter == 0
+                }
+            };
+        }
+
+        self.clock_enable = self.div_counter == self.audf;
+        if self.div_counter == self.audf || self.div_counter == 0x1F {
+            self.div_counter = 0;
+        } else {
+            self.div_counter += 1;
+        }
+    }
+
+    /// Second half of an audio clock: shift the counters and return the
+    /// channel's output level, 0-15.
+    pub fn phase1(&mut self) -> u8 {
+        if self.clock_enable {
+            let pulse_feedback = match self.audc >> 2 {
+                0x00 => {
+                    ((self.pulse_counter & 0x02 != 0) ^ (self.pulse_counter & 0x01 != 0))
+                        && self.pulse_counter != 0x0A
+                        && self.audc & 0x03 != 0
+                }
+                0x01 => self.pulse_counter & 0x08 == 0,
+                0x02 => !self.noise_counter_bit4,
+                _ => !(self.pulse_counter & 0x02 != 0 || self.pulse_counter & 0x0E == 0),
+            };
+
+            self.noise_counter >>= 1;
+            if self.noise_feedback {
+                self.noise_counter |= 0x10;
+            }
+
+            if !self.pulse_counter_hold {
+                self.pulse_counter = !(self.pulse_counter >> 1) & 0x07;
+                if pulse_feedback {
+                    self.pulse_counter |= 0x08;
+                }
+            }
+        }
+        return (self.pulse_counter & 0x01) * self.audv;
+    }
+}
+
+/// Both TIA sound channels and the samples they have produced.
+#[derive(Default, Clone)]
+pub struct Audio {
+    pub channels: [AudioChannel; 2],
+    /// Mixed output, one unsigned 8 bit sample per audio clock.
+    pub samples: Vec<u8>,
+}
+
+impl Audio {
+    pub fn new() -> Audio {
+        return Audio::default();
+    }
+
+    /// Handle a write to AUDC0-AUDV1.
+    pub fn write(&mut self, reg: u8, val: u8) {
+        match reg {
+            AUDC0 => self.channels[0].set_audc(val),
+            AUDC1 => self.channels[1].set_audc(val),
+            AUDF0 => self.channels[0].set_audf(val),
+            AUDF1 => self.channels[1].set_audf(val),
+            AUDV0 => self.channels[0].set_audv(val),
+            AUDV1 => self.channels[1].set_audv(val),
+            _ => {}
+        }
+    }
+
+    pub fn phase0(&mut self) {
+        self.channels[0].phase0();
+        self.channels[1].phase0();
+    }
+
+    pub fn phase1(&mut self) {
+        let level = self.channels[0].phase1() as u16 + self.channels[1].phase1() as u16;
+        // two channels at full volume span 0-30; scale that to 0-240
+        self.samples.push((level * 8) as u8);
+    }
+
+    /// Hand over the samples generated so far.
+    pub fn take_samples(&mut self) -> Vec<u8> {
+        return std::mem::take(&mut self.samples);
+    }
+}
+
+/// Scale `samples` by `volume`, from 0 for silence to 1 for as loud as
+/// generated.
+pub fn set_volume(samples: &mut [u8], volume: f32) {
+    let volume = volume.clamp(0.0, 1.0);
+    for sample in samples {
+        *sample = (*sample as f32 * volume).round() as u8;
+    }
+}
+
+/// Converts a stream of samples from one rate to another, interpolating
+/// linearly. It can be fed the stream in pieces.
+#[derive(Clone)]
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    /// Where the next output sample falls after `last`, in units of which
+    /// an input sample spans `to` and an output sample `from`. The first
+    /// falls on the first input sample.
+    phase: u64,
+    last: Option<u8>,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Resampler {
+        return Resampler { from, to, phase: to as u64, last: None };
+    }
+
+    pub fn resample(&mut self, samples: &[u8]) -> Vec<u8> {
+        let (from, to) = (self.from as u64, self.to as u64);
+        let mut out = Vec::with_capacity((samples.len() as u64 * to / from) as usize + 1);
+        for &sample in samples {
+            let last = match self.last {
+                Some(last) => last as u64,
+                None => sample as u64,
+            };
+            while self.phase <= to {
+                let mixed = (last * (to - self.phase) + sample as u64 * self.phase + to / 2) / to;
+                out.push(mixed as u8);
+                self.phase += from;
+            }
+            self.phase -= to;
+            self.last = Some(sample);
+        }
+        return out;
+    }
+}
+
+impl Snapshot for AudioChannel {
+    fn save(&self, out: &mut Writer) {
+        out.u8(self.audc);
+        out.u8(self.audf);
+        out.u8(self.audv);
+        out.bool(self.clock_enable);
+        out.bool(self.noise_feedback);
+        out.bool(self.noise_counter_bit4);
+        out.bool(self.pulse_counter_hold);
+        out.u8(self.div_counter);
+        out.u8(self.pulse_counter);
+        out.u8(self.noise_counter);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        self.audc = input.u8()?;
+        self.audf = input.u8()?;
+        self.audv = input.u8()?;
+        self.clock_enable = input.bool()?;
+        self.noise_feedback = input.bool()?;
+        self.noise_counter_bit4 = input.bool()?;
+        self.pulse_counter_hold = input.bool()?;
+        self.div_counter = input.u8()?;
+        self.pulse_counter = input.u8()?;
+        self.noise_counter = input.u8()?;
+        return Ok(());
+    }
+}
+
+/// The channels only; samples not yet taken are dropped on load.
+impl Snapshot for Audio {
+    fn save(&self, out: &mut Writer) {
+        for channel in &self.channels {
+            channel.save(out);
+        }
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        for channel in &mut self.channels {
+            channel.load(input)?;
+        }
+        self.samples.clear();
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(channel: &mut AudioChannel, clocks: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for _ in 0..clocks {
+            channel.phase0();
+            out.push(channel.phase1());
+        }
+        return out;
+    }
+
+    #[test]
+    fn test_set_volume() {
+        let mut samples = [0, 100, 240];
+        set_volume(&mut samples, 0.5);
+        assert_eq!(samples, [0, 50, 120]);
+        set_volume(&mut samples, 2.0);
+        assert_eq!(samples, [0, 50, 120]);
+    }
+
+    #[test]
+    fn test_resampler() {
+        // the same rate passes samples through
+        let mut same = Resampler::new(100, 100);
+        assert_eq!(same.resample(&[1, 2, 3]), [1, 2, 3]);
+
+        // doubling puts a sample halfway between each pair
+        let mut up = Resampler::new(100, 200);
+        assert_eq!(up.resample(&[0, 100]), [0, 50, 100]);
+        assert_eq!(up.resample(&[200]), [150, 200]);
+
+        // the count follows the ratio, fed whole or in pieces
+        let input: Vec<u8> = (0..=255).collect();
+        let whole = Resampler::new(31399, 44100).resample(&input);
+        let mut pieces = Resampler::new(31399, 44100);
+        let mut split = pieces.resample(&input[..100]);
+        split.extend(pieces.resample(&input[100..]));
+        assert_eq!(whole, split);
+        assert_eq!(whole.len(), 255 * 44100 / 31399 + 1);
+        let down = Resampler::new(44100, 22050).resample(&input);
+        assert_eq!(down.len(), 128);
+        assert_eq!(down[1], 2);
+    }
+
+    #[test]
+    fn test_silent_at_volume_zero() {
+        let mut channel = AudioChannel::default();
+        channel.set_audc(0x04);
+        channel.set_audv(0);
+        assert!(run(&mut channel, 100).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_pure_tone_is_square_wave() {
+        // AUDC 4 divides by 2; with AUDF 0 the output flips every audio clock
+        let mut channel = AudioChannel::default();
+        channel.set_audc(0x04);
+        channel.set_audf(0);
+        channel.set_audv(0x0F);
+        let out = run(&mut channel, 40);
+        let tail = &out[8..];
+        for pair in tail.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+        assert!(tail.iter().all(|&s| s == 0 || s == 0x0F));
+    }
+
+    #[test]
+    fn test_audf_divides_frequency() {
+        let mut channel = AudioChannel::default();
+        channel.set_audc(0x04);
+        channel.set_audf(3);
+        channel.set_audv(0x0F);
+        let out = run(&mut channel, 200);
+        let edges = out.windows(2).filter(|p| p[0] != p[1]).count();
+        // one edge every 4 audio clocks
+        assert!(edges >= 48 && edges <= 50, "{} edges", edges);
+    }
+
+    #[test]
+    fn test_register_writes_and_mixing() {
+        let mut audio = Audio::new();
+        audio.write(AUDC0, 0x04);
+        audio.write(AUDV0, 0x0F);
+        audio.write(AUDC1, 0x04);
+        audio.write(AUDV1, 0x0F);
+        for _ in 0..20 {
+            audio.phase0();
+            audio.phase1();
+        }
+        let samples = audio.take_samples();
+        assert_eq!(samples.len(), 20);
+        assert!(samples.iter().any(|&s| s == 240));
+        assert!(audio.samples.is_empty());
+    }
+}
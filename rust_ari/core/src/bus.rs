@@ -0,0 +1,38 @@
+//! The CPU's view of the rest of the machine.
+
+/// Everything the CPU can read from and write to. The 6507 has only 13
+/// address lines, so the Atari's bus ignores the top three bits; other
+/// buses, like the flat one used to run 6502 test suites, see all 16.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+}
+
+/// 64K of plain RAM with no devices on it.
+pub struct FlatBus {
+    pub memory: Vec<u8>,
+}
+
+impl FlatBus {
+    pub fn new() -> FlatBus {
+        return FlatBus {
+            memory: vec![0; 0x10000],
+        };
+    }
+}
+
+impl Default for FlatBus {
+    fn default() -> FlatBus {
+        return FlatBus::new();
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        return self.memory[addr as usize];
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+    }
+}
@@ -0,0 +1,449 @@
+//! Screenshots and video capture. Frames are RGBA as a `ColorTable` renders
+//! them, one pixel per color clock, before a frontend scales them for
+//! display.
+//!
+//! Screenshots are PNG. Video is an animated GIF, a YUV4MPEG2 stream or an
+//! uncompressed AVI, picked by the file's extension, with the audio written
+//! next to it as an 8 bit mono WAV. GIFs only keep every other frame, since
+//! viewers slow down frames shorter than 2/100 s.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::audio;
+use crate::tv::TvStandard;
+
+/// Write an RGBA image `width` pixels wide as a PNG.
+pub fn write_png(path: &Path, width: usize, rgba: &[u8]) -> io::Result<()> {
+    let height = rgba.len() / 4 / width;
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba[..width * height * 4])?;
+    return Ok(());
+}
+
+/// The 44 byte header of an 8 bit mono WAV holding `len` samples.
+pub fn wav_header(rate: u32, len: u32) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&rate.to_le_bytes());
+    header.extend_from_slice(&rate.to_le_bytes()); // bytes per second
+    header.extend_from_slice(&1u16.to_le_bytes()); // block align
+    header.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&len.to_le_bytes());
+    return header;
+}
+
+#[derive(Display, Debug, Clone, Copy, PartialEq)]
+pub enum VideoFormat {
+    GIF,
+    Y4M,
+    AVI,
+}
+
+impl VideoFormat {
+    /// The format a file's extension asks for.
+    pub fn from_path(path: &Path) -> Option<VideoFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        return match extension.as_str() {
+            "gif" => Some(VideoFormat::GIF),
+            "y4m" => Some(VideoFormat::Y4M),
+            "avi" => Some(VideoFormat::AVI),
+            _ => None,
+        };
+    }
+}
+
+enum Encoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(BufWriter<File>),
+    /// The file and the size of each frame's chunk, for the index.
+    Avi(BufWriter<File>, Vec<u32>),
+}
+
+/// Where the AVI header puts the fields only known at the end.
+const AVI_TOTAL_FRAMES: u64 = 48;
+const AVI_LENGTH: u64 = 140;
+const AVI_MOVI_SIZE: u64 = 216;
+/// The `movi` list's type, which index offsets count from.
+const AVI_MOVI: u32 = 220;
+
+/// A video being written a frame at a time.
+pub struct Video {
+    path: PathBuf,
+    encoder: Encoder,
+    width: usize,
+    height: usize,
+    frame_rate: u64,
+    frames: usize,
+    wav_path: PathBuf,
+    wav: BufWriter<File>,
+    samples: u32,
+    resampler: Option<audio::Resampler>,
+}
+
+impl Video {
+    /// Start a video of `width` by `height` frames at `standard`'s frame
+    /// rate, in the format `path`'s extension asks for. The audio goes to
+    /// the same path with a `.wav` extension, at `sample_rate` if given and
+    /// otherwise at the rate the TIA makes it.
+    pub fn create(
+        path: &Path,
+        width: usize,
+        height: usize,
+        standard: TvStandard,
+        sample_rate: Option<u32>,
+    ) -> io::Result<Video> {
+        let format = VideoFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "video files must end in .gif, .y4m or .avi")
+        })?;
+        let frame_rate = standard.frame_rate();
+        let mut out = BufWriter::new(File::create(path)?);
+        let encoder = match format {
+            VideoFormat::GIF => {
+                let mut encoder = gif::Encoder::new(out, width as u16, height as u16, &[]).map_err(gif_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+                Encoder::Gif(encoder)
+            }
+            VideoFormat::Y4M => {
+                writeln!(out, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, frame_rate)?;
+                Encoder::Y4m(out)
+            }
+            VideoFormat::AVI => {
+                out.write_all(&avi_header(width, height, frame_rate))?;
+                Encoder::Avi(out, Vec::new())
+            }
+        };
+
+        let wav_path = path.with_extension("wav");
+        let mut wav = BufWriter::new(File::create(&wav_path)?);
+        let native = audio::sample_rate(standard);
+        wav.write_all(&wav_header(sample_rate.unwrap_or(native), 0))?;
+        return Ok(Video {
+            path: path.to_path_buf(),
+            encoder,
+            width,
+            height,
+            frame_rate,
+            frames: 0,
+            wav_path,
+            wav,
+            samples: 0,
+            resampler: sample_rate.map(|rate| audio::Resampler::new(native, rate)),
+        });
+    }
+
+    /// Add a frame of RGBA pixels `width` wide. Frames with more lines than
+    /// the video are cut short, and those with fewer are padded with black.
+    pub fn frame(&mut self, rgba: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0; self.width * self.height * 4];
+        let len = rgba.len().min(frame.len());
+        frame[..len].copy_from_slice(&rgba[..len]);
+        let (width, height, frame_rate, frames) = (self.width, self.height, self.frame_rate, self.frames);
+
+        match &mut self.encoder {
+            Encoder::Gif(encoder) => {
+                if frames % 2 == 0 {
+                    // delays in hundredths of a second, rounded so they add up
+                    let time = |frame: usize| (frame as u64 * 100 + frame_rate / 2) / frame_rate;
+                    let delay = time(frames + 2) - time(frames);
+                    let mut gif_frame = gif_frame(width, height, &frame);
+                    gif_frame.delay = delay as u16;
+                    encoder.write_frame(&gif_frame).map_err(gif_error)?;
+                }
+            }
+            Encoder::Y4m(out) => {
+                out.write_all(b"FRAME\n")?;
+                out.write_all(&yuv444(&frame))?;
+            }
+            Encoder::Avi(out, sizes) => {
+                let data = bgr_bottom_up(width, &frame);
+                out.write_all(b"00db")?;
+                out.write_all(&(data.len() as u32).to_le_bytes())?;
+                out.write_all(&data)?;
+                sizes.push(data.len() as u32);
+            }
+        }
+        self.frames += 1;
+        return Ok(());
+    }
+
+    /// Add audio samples, as the TIA produces them.
+    pub fn audio(&mut self, samples: &[u8]) -> io::Result<()> {
+        let resampled;
+        let samples = match &mut self.resampler {
+            Some(resampler) => {
+                resampled = resampler.resample(samples);
+                &resampled
+            }
+            None => samples,
+        };
+        self.wav.write_all(samples)?;
+        self.samples += samples.len() as u32;
+        return Ok(());
+    }
+
+    /// Frames added so far.
+    pub fn frames(&self) -> usize {
+        return self.frames;
+    }
+
+    /// Finish both files, returning their paths.
+    pub fn finish(self) -> io::Result<Vec<PathBuf>> {
+        match self.encoder {
+            Encoder::Gif(encoder) => {
+                encoder.into_inner()?.flush()?;
+            }
+            Encoder::Y4m(mut out) => out.flush()?,
+            Encoder::Avi(mut out, sizes) => {
+                let mut offset = 4u32;
+                let movi_end = AVI_MOVI + 4 + sizes.iter().map(|size| 8 + size).sum::<u32>();
+                out.write_all(b"idx1")?;
+                out.write_all(&(sizes.len() as u32 * 16).to_le_bytes())?;
+                for size in &sizes {
+                    out.write_all(b"00db")?;
+                    out.write_all(&0x10u32.to_le_bytes())?; // key frame
+                    out.write_all(&offset.to_le_bytes())?;
+                    out.write_all(&size.to_le_bytes())?;
+                    offset += 8 + size;
+                }
+                let mut file = out.into_inner()?;
+                let end = file.seek(SeekFrom::End(0))? as u32;
+                let frames = (sizes.len() as u32).to_le_bytes();
+                for &(at, val) in &[
+                    (4, (end - 8).to_le_bytes()),
+                    (AVI_TOTAL_FRAMES, frames),
+                    (AVI_LENGTH, frames),
+                    (AVI_MOVI_SIZE, (movi_end - AVI_MOVI).to_le_bytes()),
+                ] {
+                    file.seek(SeekFrom::Start(at))?;
+                    file.write_all(&val)?;
+                }
+            }
+        }
+
+        let mut wav = self.wav.into_inner()?;
+        wav.seek(SeekFrom::Start(4))?;
+        wav.write_all(&(36 + self.samples).to_le_bytes())?;
+        wav.seek(SeekFrom::Start(40))?;
+        wav.write_all(&self.samples.to_le_bytes())?;
+        return Ok(vec![self.path, self.wav_path]);
+    }
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    return io::Error::other(e.to_string());
+}
+
+/// A GIF frame with a palette of the colors in `rgba`. A frame of the 2600
+/// rarely has more than a few dozen; should it have more than a GIF allows,
+/// it is quantized instead.
+fn gif_frame(width: usize, height: usize, rgba: &[u8]) -> gif::Frame<'static> {
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut indices = Vec::with_capacity(width * height);
+    for pixel in rgba.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match palette.iter().position(|&known| known == color) {
+            Some(index) => index,
+            None if palette.len() < 256 => {
+                palette.push(color);
+                palette.len() - 1
+            }
+            None => return gif::Frame::from_rgba_speed(width as u16, height as u16, &mut rgba.to_vec(), 10),
+        };
+        indices.push(index as u8);
+    }
+    return gif::Frame::from_palette_pixels(width as u16, height as u16, &indices, &palette.concat(), None);
+}
+
+/// Planar Y, U and V at full resolution, with BT.601 studio swing.
+fn yuv444(rgba: &[u8]) -> Vec<u8> {
+    let pixels = rgba.len() / 4;
+    let mut planes = vec![0; pixels * 3];
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+        planes[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        planes[pixels + i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        planes[2 * pixels + i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    return planes;
+}
+
+/// 24 bit BGR rows, bottom row first and each padded to 4 bytes, as a
+/// Windows DIB stores them.
+fn bgr_bottom_up(width: usize, rgba: &[u8]) -> Vec<u8> {
+    let stride = (width * 3 + 3) & !3;
+    let mut data = Vec::with_capacity(stride * rgba.len() / 4 / width);
+    for row in rgba.chunks_exact(width * 4).rev() {
+        for pixel in row.chunks_exact(4) {
+            data.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        data.resize(data.len() + stride - width * 3, 0);
+    }
+    return data;
+}
+
+/// The headers of an AVI with one uncompressed video stream, up to the
+/// start of its `movi` list. The frame count and sizes that depend on it
+/// are filled in by `Video::finish`.
+fn avi_header(width: usize, height: usize, frame_rate: u64) -> Vec<u8> {
+    let frame_size = (((width * 3 + 3) & !3) * height) as u32;
+    let (width, height) = (width as u32, height as u32);
+    let mut out = Vec::new();
+    let u32s = |out: &mut Vec<u8>, vals: &[u32]| {
+        for val in vals {
+            out.extend_from_slice(&val.to_le_bytes());
+        }
+    };
+    out.extend_from_slice(b"RIFF\0\0\0\0AVI LIST");
+    u32s(&mut out, &[192]);
+    out.extend_from_slice(b"hdrlavih");
+    u32s(&mut out, &[56, 1_000_000 / frame_rate as u32, frame_size * frame_rate as u32, 0, 0x10, 0, 0, 1]);
+    u32s(&mut out, &[frame_size, width, height, 0, 0, 0, 0]);
+    out.extend_from_slice(b"LIST");
+    u32s(&mut out, &[116]);
+    out.extend_from_slice(b"strlstrh");
+    u32s(&mut out, &[56]);
+    out.extend_from_slice(b"vidsDIB ");
+    u32s(&mut out, &[0, 0, 0, 1, frame_rate as u32, 0, 0, frame_size, 0xFFFF_FFFF, 0]);
+    u32s(&mut out, &[0, width | height << 16]);
+    out.extend_from_slice(b"strf");
+    u32s(&mut out, &[40, 40, width, height, 1 | 24 << 16, 0, frame_size, 0, 0, 0, 0]);
+    out.extend_from_slice(b"LIST\0\0\0\0movi");
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 by 2 image, red on the top row and blue below.
+    fn image() -> Vec<u8> {
+        let red = [0xFF, 0, 0, 0xFF];
+        let blue = [0, 0, 0xFF, 0xFF];
+        return [red, red, red, red, blue, blue, blue, blue].concat();
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rustari-capture-{}-{}", std::process::id(), name));
+    }
+
+    #[test]
+    fn test_png() {
+        let path = temp("shot.png");
+        write_png(&path, 4, &image()).unwrap();
+        let png = std::fs::read(&path).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR: width, height, 8 bits, RGBA
+        assert_eq!(&png[12..26], b"IHDR\0\0\0\x04\0\0\0\x02\x08\x06");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(VideoFormat::from_path(Path::new("a.GIF")), Some(VideoFormat::GIF));
+        assert_eq!(VideoFormat::from_path(Path::new("a.y4m")), Some(VideoFormat::Y4M));
+        assert_eq!(VideoFormat::from_path(Path::new("a.avi")), Some(VideoFormat::AVI));
+        assert_eq!(VideoFormat::from_path(Path::new("a.mp4")), None);
+        assert!(Video::create(&temp("x.mp4"), 4, 2, TvStandard::NTSC, None).is_err());
+    }
+
+    #[test]
+    fn test_y4m_and_wav() {
+        let path = temp("video.y4m");
+        let mut video = Video::create(&path, 4, 2, TvStandard::NTSC, None).unwrap();
+        video.frame(&image()).unwrap();
+        // a short frame is padded
+        video.frame(&image()[..16]).unwrap();
+        video.audio(&[1, 2, 3]).unwrap();
+        let paths = video.finish().unwrap();
+
+        let y4m = std::fs::read(&paths[0]).unwrap();
+        let header = b"YUV4MPEG2 W4 H2 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&y4m[..header.len()], header);
+        assert_eq!(y4m.len(), header.len() + 2 * (6 + 3 * 8));
+        let frame = &y4m[header.len() + 6..header.len() + 30];
+        // luma of red then blue, then red's U and blue's V
+        assert_eq!((frame[0], frame[4], frame[8], frame[20]), (82, 41, 90, 110));
+        let second = &y4m[header.len() + 30..];
+        assert_eq!(&second[6 + 4..6 + 8], &[16; 4]);
+
+        let wav = std::fs::read(&paths[1]).unwrap();
+        assert_eq!(wav, [wav_header(31399, 3), vec![1, 2, 3]].concat());
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_avi() {
+        let path = temp("video.avi");
+        let half = audio::sample_rate(TvStandard::PAL) / 2;
+        let mut video = Video::create(&path, 4, 2, TvStandard::PAL, Some(half)).unwrap();
+        for _ in 0..3 {
+            video.frame(&image()).unwrap();
+        }
+        video.audio(&[0, 100, 200, 100]).unwrap();
+        let paths = video.finish().unwrap();
+
+        // at half the TIA's rate
+        let wav = std::fs::read(&paths[1]).unwrap();
+        assert_eq!(wav, [wav_header(half, 2), vec![0, 200]].concat());
+
+        let avi = std::fs::read(&paths[0]).unwrap();
+        let u32_at = |at: usize| u32::from_le_bytes([avi[at], avi[at + 1], avi[at + 2], avi[at + 3]]);
+        assert_eq!(&avi[..4], b"RIFF");
+        assert_eq!(u32_at(4) as usize, avi.len() - 8);
+        assert_eq!(u32_at(AVI_TOTAL_FRAMES as usize), 3);
+        assert_eq!(u32_at(AVI_LENGTH as usize), 3);
+        assert_eq!(u32_at(132), 50);
+        // three 24 byte frames, each with an 8 byte chunk header
+        assert_eq!(u32_at(AVI_MOVI_SIZE as usize), 4 + 3 * 32);
+        let first = AVI_MOVI as usize + 4;
+        assert_eq!(&avi[first..first + 8], b"00db\x18\0\0\0");
+        // the bottom row, blue, comes first, as BGR
+        assert_eq!(&avi[first + 8..first + 11], &[0xFF, 0, 0]);
+        assert_eq!(&avi[first + 20..first + 23], &[0, 0, 0xFF]);
+        let idx1 = first + 3 * 32;
+        assert_eq!(&avi[idx1..idx1 + 4], b"idx1");
+        assert_eq!(avi.len(), idx1 + 8 + 3 * 16);
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_gif() {
+        let path = temp("video.gif");
+        let mut video = Video::create(&path, 4, 2, TvStandard::NTSC, None).unwrap();
+        for _ in 0..5 {
+            video.frame(&image()).unwrap();
+        }
+        assert_eq!(video.frames(), 5);
+        let paths = video.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&paths[0]).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (4, 2));
+        // every other frame is kept, with delays adding up to 1/60 s steps
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(&frame.buffer[..], &image()[..]);
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [3, 4, 3]);
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Cartridge ROM, bank switching and on-cart RAM.
+
+use crate::error::Error;
+use crate::properties::{BankType, Properties, PropertiesDb};
+use crate::state::{Reader, Snapshot, StateError, Writer};
+
+const SLICE: usize = 0x400;
+
+/// A ROM image mapped into the 4K cartridge window at 0x1000-0x1FFF, which
+/// is split into four 1K slices so every supported scheme can be expressed
+/// as a choice of ROM offset per slice.
+#[derive(Clone)]
+pub struct Cartridge {
+    pub properties: Properties,
+    bank_type: BankType,
+    rom: Vec<u8>,
+    /// ROM offset mapped into each 1K slice of the window.
+    slices: [usize; 4],
+    /// Extra RAM on SC and FA carts; empty for everything else.
+    ram: Vec<u8>,
+    /// FE carts switch on the access after one to 0x01FE.
+    fe_pending: bool,
+}
+
+impl Cartridge {
+    /// Map a ROM using the bank type from its properties, detecting it from
+    /// the ROM size when that is AUTO. The ROM must be at least the bank
+    /// type's `min_size`; `load` checks that first.
+    pub fn new(rom: Vec<u8>, properties: Properties) -> Cartridge {
+        let bank_type = Cartridge::resolve(&rom, &properties);
+        let ram = match bank_type {
+            BankType::F8SC | BankType::F6SC | BankType::F4SC => vec![0; 128],
+            BankType::FA => vec![0; 256],
+            _ => Vec::new(),
+        };
+        let mut cart = Cartridge {
+            properties,
+            bank_type,
+            rom,
+            slices: [0, SLICE, 2 * SLICE, 3 * SLICE],
+            ram,
+            fe_pending: false,
+        };
+        cart.reset();
+        return cart;
+    }
+
+    /// Map a ROM that hasn't been checked, such as one read from a file.
+    pub fn load(rom: Vec<u8>, properties: Properties) -> Result<Cartridge, Error> {
+        let bank_type = Cartridge::resolve(&rom, &properties);
+        if rom.is_empty() || rom.len() < bank_type.min_size() {
+            return Err(Error::RomSize { size: rom.len(), bank_type });
+        }
+        return Ok(Cartridge::new(rom, properties));
+    }
+
+    fn resolve(rom: &[u8], properties: &Properties) -> BankType {
+        return match properties.bank_type {
+            BankType::AUTO => BankType::detect(rom),
+            other => other,
+        };
+    }
+
+    /// Map a ROM with its properties from the built-in database.
+    pub fn from_rom(rom: Vec<u8>) -> Cartridge {
+        let properties = PropertiesDb::builtin().lookup(&rom);
+        return Cartridge::new(rom, properties);
+    }
+
+    pub fn bank_type(&self) -> BankType {
+        return self.bank_type;
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        return &self.rom;
+    }
+
+    /// The banks mapped into the window, from its bottom up: one 4K bank
+    /// for most schemes, four 1K slices for E0 and two 2K halves for 3F.
+    pub fn banks(&self) -> Vec<usize> {
+        let size = match self.bank_type {
+            BankType::E0 => SLICE,
+            BankType::X3F => 2 * SLICE,
+            _ => 4 * SLICE,
+        };
+        return self.slices.iter().step_by(size / SLICE).map(|&offset| offset / size).collect();
+    }
+
+    /// Select the banks a console sees at power on: the last 4K bank for the
+    /// F-series and FA, banks 4-6 for E0 and the first bank otherwise.
+    pub fn reset(&mut self) {
+        self.fe_pending = false;
+        match self.bank_type {
+            BankType::K2 => self.slices = [0, SLICE, 0, SLICE],
+            BankType::E0 => {
+                for slice in 0..3 {
+                    self.map_slice(slice, 4 + slice);
+                }
+                self.map_slice(3, 7);
+            }
+            BankType::X3F => {
+                let last = self.rom.len() / (2 * SLICE) - 1;
+                self.map_2k(0, 0);
+                self.map_2k(1, last);
+            }
+            BankType::FE | BankType::K4 => self.map_4k(0),
+            _ => self.map_4k(self.rom.len() / (4 * SLICE) - 1),
+        }
+    }
+
+    /// Read from the cartridge window; `addr` may be any mirror of it.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let addr = addr as usize & 0x0FFF;
+        if let Some(ram_addr) = self.ram_read_port(addr) {
+            return self.ram[ram_addr];
+        }
+        self.hotspot(addr);
+        return self.rom_byte(addr);
+    }
+
+    /// Read what the CPU would see at `addr` without triggering bank
+    /// switching, for debuggers and disassemblers.
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr = addr as usize & 0x0FFF;
+        if let Some(ram_addr) = self.ram_read_port(addr) {
+            return self.ram[ram_addr];
+        }
+        return self.rom_byte(addr);
+    }
+
+    /// Write to the cartridge window. Writes only reach on-cart RAM or
+    /// trigger bank switching.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        let addr = addr as usize & 0x0FFF;
+        let ram_size = self.ram.len();
+        if ram_size > 0 && addr < ram_size {
+            self.ram[addr] = val;
+            return;
+        }
+        self.hotspot(addr);
+    }
+
+    /// See every access on the bus, including those outside the cartridge
+    /// window, for the schemes that switch on them.
+    pub fn snoop(&mut self, addr: u16, val: u8, write: bool) {
+        let addr = addr as usize & 0x1FFF;
+        match self.bank_type {
+            BankType::FE => {
+                if self.fe_pending {
+                    self.fe_pending = false;
+                    self.map_4k(if val & 0b0010_0000 != 0 { 0 } else { 1 });
+                }
+                if addr == 0x01FE {
+                    self.fe_pending = true;
+                }
+            }
+            BankType::X3F if write && addr <= 0x3F => {
+                let banks = self.rom.len() / (2 * SLICE);
+                self.map_2k(0, val as usize % banks);
+            }
+            _ => {}
+        }
+    }
+
+    fn ram_read_port(&self, addr: usize) -> Option<usize> {
+        let size = self.ram.len();
+        if size > 0 && addr >= size && addr < 2 * size {
+            return Some(addr - size);
+        }
+        return None;
+    }
+
+    fn hotspot(&mut self, addr: usize) {
+        let (first, banks) = match self.bank_type {
+            BankType::F8 | BankType::F8SC => (0xFF8, 2),
+            BankType::F6 | BankType::F6SC => (0xFF6, 4),
+            BankType::F4 | BankType::F4SC => (0xFF4, 8),
+            BankType::FA => (0xFF8, 3),
+            BankType::E0 => {
+                if (0xFE0..0xFF8).contains(&addr) {
+                    self.map_slice((addr - 0xFE0) / 8, addr & 0x07);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if addr >= first && addr < first + banks {
+            self.map_4k(addr - first);
+        }
+    }
+
+    fn map_4k(&mut self, bank: usize) {
+        for slice in 0..4 {
+            self.slices[slice] = bank * 4 * SLICE + slice * SLICE;
+        }
+    }
+
+    fn map_2k(&mut self, half: usize, bank: usize) {
+        self.slices[2 * half] = bank * 2 * SLICE;
+        self.slices[2 * half + 1] = bank * 2 * SLICE + SLICE;
+    }
+
+    fn map_slice(&mut self, slice: usize, bank: usize) {
+        self.slices[slice] = bank * SLICE;
+    }
+
+    fn rom_byte(&self, addr: usize) -> u8 {
+        let offset = self.slices[addr / SLICE] + (addr % SLICE);
+        return self.rom[offset % self.rom.len()];
+    }
+}
+
+/// The banks mapped in and on-cart RAM. The ROM is identified by the
+/// state's header and isn't saved.
+impl Snapshot for Cartridge {
+    fn save(&self, out: &mut Writer) {
+        out.u8(self.bank_type as u8);
+        for &offset in &self.slices {
+            out.usize(offset);
+        }
+        out.bytes(&self.ram);
+        out.bool(self.fe_pending);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        if input.u8()? != self.bank_type as u8 {
+            return Err(StateError::Invalid("bank switching type"));
+        }
+        for offset in &mut self.slices {
+            *offset = input.usize()?;
+        }
+        input.fill(&mut self.ram)?;
+        self.fe_pending = input.bool()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ROM whose every byte holds the number of the 1K block it's in.
+    fn numbered_rom(size: usize) -> Vec<u8> {
+        return (0..size).map(|i| (i / SLICE) as u8).collect();
+    }
+
+    fn cart(size: usize, bank_type: BankType) -> Cartridge {
+        let mut properties = Properties::new("");
+        properties.bank_type = bank_type;
+        return Cartridge::new(numbered_rom(size), properties);
+    }
+
+    #[test]
+    fn test_2k_mirrored() {
+        let mut cart = cart(0x800, BankType::AUTO);
+        assert_eq!(cart.bank_type(), BankType::K2);
+        assert_eq!(cart.read(0x1400), 1);
+        assert_eq!(cart.read(0x1800), 0);
+        assert_eq!(cart.read(0xFC00), 1);
+    }
+
+    #[test]
+    fn test_f8_switching() {
+        let mut cart = cart(0x2000, BankType::AUTO);
+        assert_eq!(cart.bank_type(), BankType::F8);
+        // starts in the last bank
+        assert_eq!(cart.read(0x1000), 4);
+        assert_eq!(cart.banks(), [1]);
+        cart.read(0x1FF8);
+        assert_eq!(cart.read(0x1000), 0);
+        assert_eq!(cart.banks(), [0]);
+        cart.write(0x1FF9, 0);
+        assert_eq!(cart.read(0x1C00), 7);
+    }
+
+    #[test]
+    fn test_f6sc_ram() {
+        let mut cart = cart(0x4000, BankType::F6SC);
+        cart.write(0x1005, 0x42);
+        assert_eq!(cart.read(0x1085), 0x42);
+        cart.read(0x1FF7);
+        assert_eq!(cart.read(0x1200), 4);
+        assert_eq!(cart.read(0x1085), 0x42);
+    }
+
+    #[test]
+    fn test_e0_slices() {
+        let mut cart = cart(0x2000, BankType::E0);
+        assert_eq!(cart.read(0x1000), 4);
+        assert_eq!(cart.read(0x1C00), 7);
+        cart.read(0x1FE2);
+        cart.read(0x1FF1);
+        assert_eq!(cart.read(0x1000), 2);
+        assert_eq!(cart.read(0x1800), 1);
+        assert_eq!(cart.banks(), [2, 5, 1, 7]);
+    }
+
+    #[test]
+    fn test_3f_switches_on_tia_writes() {
+        let mut cart = cart(0x2000, BankType::X3F);
+        assert_eq!(cart.read(0x1000), 0);
+        assert_eq!(cart.read(0x1800), 6);
+        cart.snoop(0x003F, 2, true);
+        assert_eq!(cart.read(0x1000), 4);
+        assert_eq!(cart.read(0x1800), 6);
+        assert_eq!(cart.banks(), [2, 3]);
+        cart.snoop(0x003F, 1, false);
+        assert_eq!(cart.read(0x1000), 4);
+    }
+
+    #[test]
+    fn test_fe_switches_after_stack_access() {
+        let mut cart = cart(0x2000, BankType::FE);
+        assert_eq!(cart.read(0x1000), 0);
+        cart.snoop(0x01FE, 0, false);
+        cart.snoop(0x01FF, 0xD0, false);
+        assert_eq!(cart.read(0x1000), 4);
+        cart.snoop(0x01FE, 0, true);
+        cart.snoop(0x01FF, 0xF0, true);
+        assert_eq!(cart.read(0x1000), 0);
+    }
+
+    #[test]
+    fn test_load_checks_size() {
+        let mut properties = Properties::new("");
+        assert!(matches!(
+            Cartridge::load(Vec::new(), properties.clone()),
+            Err(Error::RomSize { size: 0, .. })
+        ));
+        properties.bank_type = BankType::F6;
+        assert!(matches!(
+            Cartridge::load(numbered_rom(0x2000), properties.clone()),
+            Err(Error::RomSize { size: 0x2000, bank_type: BankType::F6 })
+        ));
+        assert!(Cartridge::load(numbered_rom(0x4000), properties).is_ok());
+        // unbanked ROMs smaller than the window are mirrored
+        let mut cart = Cartridge::load(numbered_rom(0x400), Properties::new("")).unwrap();
+        assert_eq!(cart.read(0x1C00), 0);
+    }
+}
@@ -0,0 +1,377 @@
+//! The user's settings, kept in a TOML file: defaults for every ROM at the
+//! top, key bindings under `[keys]` and settings for single ROMs under
+//! `[rom.<md5>]`, which take precedence over the defaults.
+//!
+//! ```toml
+//! tv = "auto"
+//! scale = 3
+//! phosphor = 0.5
+//! save_dir = "states"
+//!
+//! [keys]
+//! fire = "LControl"
+//!
+//! [rom.72ffbef6504b75e69ee1045af9075f66]
+//! tv = "pal"
+//! ```
+//!
+//! Only the part of TOML these need is read: tables, and keys set to
+//! strings, numbers or booleans.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::properties::{BankType, Controller, DisplayFormat, Overrides, ParseError};
+use crate::viewport::Scaling;
+
+/// The name of the config file in the user's config directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Value {
+    fn parse(text: &str) -> Result<Value, String> {
+        if let Some(quoted) = text.strip_prefix('"') {
+            let mut out = String::new();
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if chars.as_str().is_empty() => return Ok(Value::String(out)),
+                    '"' => return Err("text after a string".to_string()),
+                    '\\' => match chars.next() {
+                        Some('"') => out.push('"'),
+                        Some('\\') => out.push('\\'),
+                        Some('n') => out.push('\n'),
+                        Some('t') => out.push('\t'),
+                        other => return Err(format!("unknown escape \\{}", other.map(String::from).unwrap_or_default())),
+                    },
+                    c => out.push(c),
+                }
+            }
+            return Err("unterminated string".to_string());
+        }
+        // literal strings take backslashes as they are, as in Windows paths
+        if let Some(quoted) = text.strip_prefix('\'') {
+            return match quoted.strip_suffix('\'') {
+                Some(literal) if !literal.contains('\'') => Ok(Value::String(literal.to_string())),
+                _ => Err("unterminated string".to_string()),
+            };
+        }
+        return match text {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => {
+                let number = text.replace('_', "");
+                if let Ok(integer) = number.parse() {
+                    Ok(Value::Integer(integer))
+                } else {
+                    number.parse().map(Value::Float).map_err(|_| format!("not a value: {}", text))
+                }
+            }
+        };
+    }
+
+    fn string(&self) -> Result<&str, String> {
+        return match self {
+            Value::String(text) => Ok(text),
+            _ => Err("expected a string".to_string()),
+        };
+    }
+
+    fn boolean(&self) -> Result<bool, String> {
+        return match self {
+            Value::Boolean(value) => Ok(*value),
+            _ => Err("expected true or false".to_string()),
+        };
+    }
+
+    /// A number from 0 to 1.
+    fn fraction(&self) -> Result<f32, String> {
+        let value = match self {
+            Value::Integer(integer) => *integer as f64,
+            Value::Float(float) => *float,
+            _ => return Err("expected a number".to_string()),
+        };
+        if !(0.0..=1.0).contains(&value) {
+            return Err("expected a number from 0 to 1".to_string());
+        }
+        return Ok(value as f32);
+    }
+}
+
+/// Parse a property value the way `stella.pro` spells it, in any case.
+fn property<T: FromStr>(value: &Value) -> Result<T, String> {
+    let text = value.string()?;
+    return text.to_uppercase().parse().map_err(|_| format!("unknown value: {}", text));
+}
+
+/// Settings from the config file, a ROM's section of it or the command
+/// line. Those left out fall through to the next place they can come from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Settings {
+    pub palette: Option<PathBuf>,
+    pub tv: Option<DisplayFormat>,
+    pub cart: Option<BankType>,
+    /// Left and right.
+    pub controllers: [Option<Controller>; 2],
+    pub scale: Option<u32>,
+    pub scaling: Option<Scaling>,
+    pub overscan: Option<bool>,
+    pub fullscreen: Option<bool>,
+    pub phosphor: Option<f32>,
+    pub scanlines: Option<f32>,
+    pub composite: Option<bool>,
+    /// How loud the audio written is, from 0 to 1.
+    pub volume: Option<f32>,
+    /// Where save states go, rather than next to the ROM.
+    pub save_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Set the setting named `key`.
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "palette" => self.palette = Some(PathBuf::from(value.string()?)),
+            "tv" => self.tv = Some(property(value)?),
+            "cart" => self.cart = Some(property(value)?),
+            "left" => self.controllers[0] = Some(property(value)?),
+            "right" => self.controllers[1] = Some(property(value)?),
+            "scale" => match value {
+                Value::Integer(scale) if *scale > 0 && *scale <= u32::MAX as i64 => self.scale = Some(*scale as u32),
+                _ => return Err("expected a whole number from 1".to_string()),
+            },
+            "scaling" => self.scaling = Some(property(value)?),
+            "overscan" => self.overscan = Some(value.boolean()?),
+            "fullscreen" => self.fullscreen = Some(value.boolean()?),
+            "phosphor" => self.phosphor = Some(value.fraction()?),
+            "scanlines" => self.scanlines = Some(value.fraction()?),
+            "composite" => self.composite = Some(value.boolean()?),
+            "volume" => self.volume = Some(value.fraction()?),
+            "save_dir" => self.save_dir = Some(PathBuf::from(value.string()?)),
+            _ => return Err("unknown setting".to_string()),
+        }
+        return Ok(());
+    }
+
+    /// Take every setting `other` has over ours.
+    pub fn merge(&mut self, other: &Settings) {
+        self.palette = other.palette.clone().or(self.palette.take());
+        self.tv = other.tv.or(self.tv);
+        self.cart = other.cart.or(self.cart);
+        self.controllers[0] = other.controllers[0].or(self.controllers[0]);
+        self.controllers[1] = other.controllers[1].or(self.controllers[1]);
+        self.scale = other.scale.or(self.scale);
+        self.scaling = other.scaling.or(self.scaling);
+        self.overscan = other.overscan.or(self.overscan);
+        self.fullscreen = other.fullscreen.or(self.fullscreen);
+        self.phosphor = other.phosphor.or(self.phosphor);
+        self.scanlines = other.scanlines.or(self.scanlines);
+        self.composite = other.composite.or(self.composite);
+        self.volume = other.volume.or(self.volume);
+        self.save_dir = other.save_dir.clone().or(self.save_dir.take());
+    }
+
+    /// The cartridge's properties these settings replace.
+    pub fn overrides(&self) -> Overrides {
+        return Overrides { bank_type: self.cart, controllers: self.controllers, format: self.tv };
+    }
+
+    /// Take relative paths as relative to `dir`, the config file's
+    /// directory.
+    pub fn resolve(&mut self, dir: &Path) {
+        for path in self.palette.iter_mut().chain(self.save_dir.iter_mut()) {
+            if path.is_relative() {
+                *path = dir.join(&path);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub settings: Settings,
+    /// Key bindings, as the names of actions and of the keys they're bound
+    /// to, for the frontend to make sense of.
+    pub keys: Vec<(String, String)>,
+    /// Settings for single ROMs, keyed by lowercase MD5.
+    roms: HashMap<String, Settings>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, ParseError> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError { line: i + 1, message };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header.strip_suffix(']').ok_or_else(|| error("expected ] after the table name".to_string()))?;
+                section = name.trim().replace('"', "").to_lowercase();
+                match section.strip_prefix("rom.") {
+                    Some(md5) => {
+                        config.roms.entry(md5.to_string()).or_default();
+                    }
+                    None if section == "keys" => {}
+                    None => return Err(error(format!("unknown table: {}", name))),
+                }
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(at) => (line[..at].trim(), line[at + 1..].trim()),
+                None => return Err(error("expected key = value".to_string())),
+            };
+            let value = Value::parse(value).map_err(&error)?;
+            let result = match section.strip_prefix("rom.") {
+                _ if section == "keys" => value.string().map(|name| config.keys.push((key.to_string(), name.to_string()))),
+                Some(md5) => config.roms.get_mut(md5).unwrap().set(key, &value),
+                None => config.settings.set(key, &value),
+            };
+            result.map_err(|message| error(format!("{}: {}", key, message)))?;
+        }
+        return Ok(config);
+    }
+
+    /// Load a config file, taking the paths in it as relative to where it is.
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut config = Config::parse(&text).map_err(|e| e.to_string())?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.settings.resolve(dir);
+        for settings in config.roms.values_mut() {
+            settings.resolve(dir);
+        }
+        return Ok(config);
+    }
+
+    /// The settings for the ROM with `md5`: those in its section over the
+    /// defaults.
+    pub fn for_rom(&self, md5: &str) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(rom) = self.roms.get(&md5.to_lowercase()) {
+            settings.merge(rom);
+        }
+        return settings;
+    }
+}
+
+/// A line up to any `#` outside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), c) if c == open && !escaped => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    return line;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+# defaults for every ROM
+tv = "auto"
+scale = 3
+phosphor = 0.5   # a comment
+volume = 1
+composite = true
+palette = 'C:\palettes\# stella.pal'
+
+[keys]
+fire = "LControl"
+pause = "Pause"
+
+[rom.72FFBEF6504B75E69EE1045AF9075F66]
+tv = "pal"
+cart = "f8"
+left = "paddles"
+phosphor = 0
+"#;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        assert_eq!(config.settings.tv, Some(DisplayFormat::AUTO));
+        assert_eq!(config.settings.scale, Some(3));
+        assert_eq!(config.settings.phosphor, Some(0.5));
+        assert_eq!(config.settings.volume, Some(1.0));
+        assert_eq!(config.settings.composite, Some(true));
+        assert_eq!(config.settings.palette, Some(PathBuf::from("C:\\palettes\\# stella.pal")));
+        assert_eq!(config.keys, vec![
+            ("fire".to_string(), "LControl".to_string()),
+            ("pause".to_string(), "Pause".to_string()),
+        ]);
+
+        let rom = config.for_rom("72ffbef6504b75e69ee1045af9075f66");
+        assert_eq!(rom.tv, Some(DisplayFormat::PAL));
+        assert_eq!(rom.cart, Some(BankType::F8));
+        assert_eq!(rom.controllers, [Some(Controller::PADDLES), None]);
+        assert_eq!(rom.phosphor, Some(0.0));
+        assert_eq!(rom.scale, Some(3));
+        assert_eq!(rom.overrides().format, Some(DisplayFormat::PAL));
+        assert_eq!(config.for_rom("0123"), config.settings);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut settings = Config::parse("scale = 2\noverscan = true").unwrap().settings;
+        let cli = Settings { scale: Some(4), tv: Some(DisplayFormat::NTSC), ..Settings::default() };
+        settings.merge(&cli);
+        assert_eq!(settings.scale, Some(4));
+        assert_eq!(settings.tv, Some(DisplayFormat::NTSC));
+        assert_eq!(settings.overscan, Some(true));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| Config::parse(text).unwrap_err();
+        assert_eq!(error("\nscale = 0"), ParseError { line: 2, message: "scale: expected a whole number from 1".to_string() });
+        assert_eq!(error("speed = 2").message, "speed: unknown setting");
+        assert_eq!(error("phosphor = 2").message, "phosphor: expected a number from 0 to 1");
+        assert_eq!(error("tv = \"pal").message, "unterminated string");
+        assert_eq!(error("tv = \"ntsc\" x").message, "text after a string");
+        assert_eq!(error("tv = \"mono\"").message, "tv: unknown value: mono");
+        assert_eq!(error("[audio]").message, "unknown table: audio");
+        assert_eq!(error("scale").message, "expected key = value");
+        assert_eq!(error("[keys]\nfire = 1").message, "fire: expected a string");
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(Value::parse("\"a \\\"b\\\" \\\\c\""), Ok(Value::String("a \"b\" \\c".to_string())));
+        assert_eq!(Value::parse("1_000"), Ok(Value::Integer(1000)));
+        assert_eq!(Value::parse("-0.25"), Ok(Value::Float(-0.25)));
+        assert_eq!(Value::parse("false"), Ok(Value::Boolean(false)));
+        assert_eq!(strip_comment("a = \"x # y\" # z"), "a = \"x # y\" ");
+        assert_eq!(strip_comment("a = \"x \\\" # y\""), "a = \"x \\\" # y\"");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut settings = Config::parse("palette = \"pal/stella.pal\"\nsave_dir = \"/states\"").unwrap().settings;
+        settings.resolve(Path::new("/home/me/.config/rust_ari"));
+        assert_eq!(settings.palette, Some(PathBuf::from("/home/me/.config/rust_ari/pal/stella.pal")));
+        assert_eq!(settings.save_dir, Some(PathBuf::from("/states")));
+    }
+}
@@ -0,0 +1,1844 @@
+use crate::alu;
+use crate::bus::Bus;
+use crate::error::Fault;
+use crate::state::{Reader, Snapshot, StateError, Writer};
+
+const RESET_VECTOR : usize = 0xFFFC;
+const IRQ_VECTOR : usize = 0xFFFE;
+
+enum FlagWriter {
+    NEG = 0b1000_0000,
+    OVER = 0b0100_0000,
+    UNUSED = 0b0010_0000,
+    BRK = 0b0001_0000,
+    DEC = 0b0000_1000,
+    IRQD = 0b0000_0100,
+    ZERO = 0b0000_0010,
+    CARRY = 0b0000_0001
+ }
+
+ /// The status flags read back by instructions, numbered by their bit.
+ enum Flag {
+    CARRY = 0,
+    ZERO = 1,
+    DEC = 3,
+    OVER = 6,
+    NEG = 7
+ }
+
+ /// How an instruction finds its operand. IMP instructions have none and
+ /// REL is the signed branch offset.
+ #[derive(Display, Debug, Clone, Copy, PartialEq)]
+ pub enum Mode {
+
+    IMP,
+    REL,
+    IMM,
+    ZP,
+    ZPX,
+    ZPY,
+    ABS,
+    ABSX,
+    ABSY,
+    INDX,
+    INDY,
+    IND,
+    ACC
+ }
+
+ /// Bytes taken by an instruction using `mode`, opcode included.
+ pub fn length(mode: Mode) -> usize {
+    return match mode {
+       Mode::IMP | Mode::ACC => 1,
+       Mode::REL | Mode::IMM | Mode::ZP | Mode::ZPX | Mode::ZPY | Mode::INDX | Mode::INDY => 2,
+       Mode::ABS | Mode::ABSX | Mode::ABSY | Mode::IND => 3
+    };
+ }
+
+ /// The documented 6502 instructions.
+ #[derive(Display, Debug, Clone, Copy, PartialEq)]
+ pub enum Instruction {
+    ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC,
+    CLD, CLI, CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP,
+    JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL, ROR, RTI,
+    RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+ }
+
+ /// Every documented opcode with the instruction and addressing mode it
+ /// decodes to. `execute_step` dispatches through this and the
+ /// disassembler renders from it.
+ const OPCODE_LIST: [(u8, Instruction, Mode); 151] = {
+    use Instruction::*;
+    use Mode::*;
+    [
+        //Flag (Processor Status) Instructions
+        (0x18, CLC, IMP),
+        (0x38, SEC, IMP),
+        (0x58, CLI, IMP),
+        (0x78, SEI, IMP),
+        (0xB8, CLV, IMP),
+        (0xD8, CLD, IMP),
+        (0xF8, SED, IMP),
+
+        //LDX (Load X regisiter)
+        (0xA2, LDX, IMM),
+        (0xA6, LDX, ZP),
+        (0xB6, LDX, ZPY),
+        (0xAE, LDX, ABS),
+        (0xBE, LDX, ABSY),
+
+        //LDY (Load Y register)
+        (0xA0, LDY, IMM),
+        (0xA4, LDY, ZP),
+        (0xB4, LDY, ZPX),
+        (0xAC, LDY, ABS),
+        (0xBC, LDY, ABSX),
+
+        //LDA (Load A register)
+        (0xA9, LDA, IMM),
+        (0xA5, LDA, ZP),
+        (0xB5, LDA, ZPX),
+        (0xAD, LDA, ABS),
+        (0xBD, LDA, ABSX),
+        (0xB9, LDA, ABSY),
+        (0xA1, LDA, INDX),
+        (0xB1, LDA, INDY),
+
+        //STA (Store A register)
+        (0x85, STA, ZP),
+        (0x95, STA, ZPX),
+        (0x8D, STA, ABS),
+        (0x9D, STA, ABSX),
+        (0x99, STA, ABSY),
+        (0x81, STA, INDX),
+        (0x91, STA, INDY),
+
+        //STX/STY (Store X/Y register)
+        (0x86, STX, ZP),
+        (0x96, STX, ZPY),
+        (0x8E, STX, ABS),
+        (0x84, STY, ZP),
+        (0x94, STY, ZPX),
+        (0x8C, STY, ABS),
+
+        //Stack Instructions
+        (0x9A, TXS, IMP),
+        (0xBA, TSX, IMP),
+        (0x48, PHA, IMP),
+        (0x68, PLA, IMP),
+        (0x08, PHP, IMP),
+        (0x28, PLP, IMP),
+
+        //Register Instructions
+        (0xAA, TAX, IMP),
+        (0x8A, TXA, IMP),
+        (0xCA, DEX, IMP),
+        (0xE8, INX, IMP),
+        (0xA8, TAY, IMP),
+        (0x98, TYA, IMP),
+        (0x88, DEY, IMP),
+        (0xC8, INY, IMP),
+
+        //Logical Instructions
+        (0x29, AND, IMM),
+        (0x25, AND, ZP),
+        (0x35, AND, ZPX),
+        (0x2D, AND, ABS),
+        (0x3D, AND, ABSX),
+        (0x39, AND, ABSY),
+        (0x21, AND, INDX),
+        (0x31, AND, INDY),
+        (0x09, ORA, IMM),
+        (0x05, ORA, ZP),
+        (0x15, ORA, ZPX),
+        (0x0D, ORA, ABS),
+        (0x1D, ORA, ABSX),
+        (0x19, ORA, ABSY),
+        (0x01, ORA, INDX),
+        (0x11, ORA, INDY),
+        (0x49, EOR, IMM),
+        (0x45, EOR, ZP),
+        (0x55, EOR, ZPX),
+        (0x4D, EOR, ABS),
+        (0x5D, EOR, ABSX),
+        (0x59, EOR, ABSY),
+        (0x41, EOR, INDX),
+        (0x51, EOR, INDY),
+        (0x24, BIT, ZP),
+        (0x2C, BIT, ABS),
+
+        //Arithmetic Instructions
+        (0x69, ADC, IMM),
+        (0x65, ADC, ZP),
+        (0x75, ADC, ZPX),
+        (0x6D, ADC, ABS),
+        (0x7D, ADC, ABSX),
+        (0x79, ADC, ABSY),
+        (0x61, ADC, INDX),
+        (0x71, ADC, INDY),
+        (0xE9, SBC, IMM),
+        (0xE5, SBC, ZP),
+        (0xF5, SBC, ZPX),
+        (0xED, SBC, ABS),
+        (0xFD, SBC, ABSX),
+        (0xF9, SBC, ABSY),
+        (0xE1, SBC, INDX),
+        (0xF1, SBC, INDY),
+        (0xC9, CMP, IMM),
+        (0xC5, CMP, ZP),
+        (0xD5, CMP, ZPX),
+        (0xCD, CMP, ABS),
+        (0xDD, CMP, ABSX),
+        (0xD9, CMP, ABSY),
+        (0xC1, CMP, INDX),
+        (0xD1, CMP, INDY),
+        (0xE0, CPX, IMM),
+        (0xE4, CPX, ZP),
+        (0xEC, CPX, ABS),
+        (0xC0, CPY, IMM),
+        (0xC4, CPY, ZP),
+        (0xCC, CPY, ABS),
+
+        //Increments and Decrements
+        (0xE6, INC, ZP),
+        (0xF6, INC, ZPX),
+        (0xEE, INC, ABS),
+        (0xFE, INC, ABSX),
+        (0xC6, DEC, ZP),
+        (0xD6, DEC, ZPX),
+        (0xCE, DEC, ABS),
+        (0xDE, DEC, ABSX),
+
+        //Shifts
+        (0x0A, ASL, ACC),
+        (0x06, ASL, ZP),
+        (0x16, ASL, ZPX),
+        (0x0E, ASL, ABS),
+        (0x1E, ASL, ABSX),
+        (0x4A, LSR, ACC),
+        (0x46, LSR, ZP),
+        (0x56, LSR, ZPX),
+        (0x4E, LSR, ABS),
+        (0x5E, LSR, ABSX),
+        (0x2A, ROL, ACC),
+        (0x26, ROL, ZP),
+        (0x36, ROL, ZPX),
+        (0x2E, ROL, ABS),
+        (0x3E, ROL, ABSX),
+        (0x6A, ROR, ACC),
+        (0x66, ROR, ZP),
+        (0x76, ROR, ZPX),
+        (0x6E, ROR, ABS),
+        (0x7E, ROR, ABSX),
+
+        //Branching instructions
+        (0x10, BPL, REL),
+        (0x30, BMI, REL),
+        (0x50, BVC, REL),
+        (0x70, BVS, REL),
+        (0x90, BCC, REL),
+        (0xB0, BCS, REL),
+        (0xD0, BNE, REL),
+        (0xF0, BEQ, REL),
+
+        //Jump instructions
+        (0x4C, JMP, ABS),
+        (0x6C, JMP, IND),
+        (0x20, JSR, ABS),
+        (0x60, RTS, IMP),
+        (0x00, BRK, IMP),
+        (0x40, RTI, IMP),
+
+        (0xEA, NOP, IMP),
+    ]
+ };
+
+ /// `OPCODE_LIST` indexed by opcode; the undocumented ones are `None`.
+ pub const OPCODES: [Option<(Instruction, Mode)>; 256] = {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < OPCODE_LIST.len() {
+       let (opcode, instruction, mode) = OPCODE_LIST[i];
+       table[opcode as usize] = Some((instruction, mode));
+       i += 1;
+    }
+    table
+ };
+
+ /// Add an index to a base address, noting whether it crossed a page.
+ fn indexed(base: usize, index: u8) -> (usize, bool) {
+    let addr = (base + index as usize) & 0xFFFF;
+    return (addr, addr & 0xFF00 != base & 0xFF00);
+ }
+
+
+ /// The 6507: a 6502 in a smaller package. It owns only its registers and
+ /// reaches memory and devices through whatever `Bus` it is stepped with.
+ #[derive(Clone)]
+ pub struct Cpu {
+    pub flags: u8,
+    pub pc: usize,
+    pub x_reg: u8,
+    pub y_reg: u8,
+    pub a_reg: u8,
+    pub s_pnt: u8,
+    pub cycles: usize
+ }
+
+ impl Cpu {
+
+    pub fn new() -> Cpu {
+        Cpu {
+            flags: 0,
+            pc: 0,
+            x_reg: 0,
+            y_reg: 0,
+            a_reg: 0,
+            s_pnt: 0,
+            cycles: 0
+        }
+    }
+
+    /// Run the reset sequence: interrupts off, stack at 0xFD and the program
+    /// counter loaded from the reset vector. Takes seven cycles, the first
+    /// five of them dummy reads.
+    pub fn reset(&mut self, bus: &mut dyn Bus) {
+       self.dummy_read(bus, self.pc);
+       self.dummy_read(bus, self.pc);
+       for i in 0..3 {
+          self.dummy_read(bus, 0x100 + self.s_pnt.wrapping_sub(i) as usize);
+       }
+       self.pc = self.read_word(bus, RESET_VECTOR);
+       self.s_pnt = 0xFD;
+       self.flags = FlagWriter::UNUSED as u8 | FlagWriter::IRQD as u8;
+    }
+
+    /* #region Utility functions */
+
+    // The 6502 makes exactly one bus access per cycle, so the cycle count
+    // is kept here rather than by the instructions.
+    fn read_mem(&mut self, bus : &mut dyn Bus, cell : usize) -> u8 {
+       self.cycles += 1;
+       return bus.read(cell as u16);
+    }
+
+    fn write_mem(&mut self, bus : &mut dyn Bus, cell : usize, val : u8) {
+       self.cycles += 1;
+       bus.write(cell as u16, val);
+    }
+
+    /// A read made only because the 6502 can't leave the bus idle. The
+    /// value is thrown away, but the access still reaches the bus.
+    fn dummy_read(&mut self, bus : &mut dyn Bus, cell : usize) {
+       self.read_mem(bus, cell);
+    }
+
+    fn read_word(&mut self, bus : &mut dyn Bus, cell : usize) -> usize {
+       let lo = self.read_mem(bus, cell) as usize;
+       let hi = self.read_mem(bus, (cell + 1) & 0xFFFF) as usize;
+       return hi << 8 | lo;
+    }
+
+    /// Read a pointer from the zero page, wrapping within it.
+    fn read_zp_word(&mut self, bus : &mut dyn Bus, cell : u8) -> usize {
+       let lo = self.read_mem(bus, cell as usize) as usize;
+       let hi = self.read_mem(bus, cell.wrapping_add(1) as usize) as usize;
+       return hi << 8 | lo;
+    }
+
+    fn read_flag(&self, flag : Flag) -> bool {
+       let flagu8 = flag as u8;
+       return self.flags & (1 << flagu8) != 0;
+    }
+
+    fn write_flag(&mut self, flag_writer : FlagWriter, val : bool) {
+       let fw = flag_writer as u8;
+       if val {
+          self.flags |= fw;
+       } else {
+          self.flags &= !fw;
+       }
+    }
+
+
+    fn set_flag_zero(&mut self, val : u8) {
+      if val == 0 {
+         self.write_flag(FlagWriter::ZERO, true);
+      } else {
+         self.write_flag(FlagWriter::ZERO, false);
+      }
+   }
+
+   fn set_flag_neg(&mut self, val : u8) {
+      if val & 0x80 != 0 {
+         self.write_flag(FlagWriter::NEG, true);
+      } else {
+         self.write_flag(FlagWriter::NEG, false);
+      }
+   }
+
+   fn set_flags(&mut self, val : u8) {
+      self.set_flag_zero(val);
+      self.set_flag_neg(val);
+   }
+
+   /// Store the C, Z and N an ALU operation produced. V is left to the
+   /// instructions that affect it.
+   fn set_alu_flags(&mut self, out : alu::Output) {
+      self.write_flag(FlagWriter::CARRY, out.carry);
+      self.write_flag(FlagWriter::ZERO, out.zero);
+      self.write_flag(FlagWriter::NEG, out.negative);
+   }
+
+   fn abs_addr (&mut self, bus : &mut dyn Bus, pc : usize) -> usize {
+      let p2 : u16 = self.read_mem(bus, pc+1) as u16;
+      let p1 : u16 = self.read_mem(bus, pc+2) as u16;
+      let target_loc : u16 = p1 << 8 | p2;
+      return target_loc as usize;
+   }
+
+   /// Add an index to `base`. The 6502 adds to the low byte first and reads
+   /// from that unfixed address while it carries into the high byte, so
+   /// that read happens when a page is crossed, and always for stores and
+   /// read-modify-writes, which can't skip the fix-up cycle.
+   fn index (&mut self, bus : &mut dyn Bus, base : usize, index : u8, write : bool) -> usize {
+      let (addr, crossed) = indexed(base, index);
+      if crossed || write {
+         self.dummy_read(bus, (base & 0xFF00) | (addr & 0xFF));
+      }
+      return addr;
+   }
+
+   /// Work out the effective address of the operand for `mode`, making the
+   /// same bus accesses the 6502 does along the way. `write` is set for
+   /// instructions that write to the address.
+   fn address (&mut self, bus : &mut dyn Bus, mode : Mode, pc : usize, write : bool) -> Result<usize, Fault> {
+      let addr = match mode {
+         Mode::IMM => pc + 1,
+         Mode::ZP => self.read_mem(bus, pc+1) as usize,
+         Mode::ZPX | Mode::ZPY => {
+            let base = self.read_mem(bus, pc+1);
+            self.dummy_read(bus, base as usize);
+            let index = if let Mode::ZPX = mode { self.x_reg } else { self.y_reg };
+            base.wrapping_add(index) as usize
+         }
+         Mode::ABS => self.abs_addr(bus, pc),
+         Mode::ABSX => {
+            let base = self.abs_addr(bus, pc);
+            self.index(bus, base, self.x_reg, write)
+         }
+         Mode::ABSY => {
+            let base = self.abs_addr(bus, pc);
+            self.index(bus, base, self.y_reg, write)
+         }
+         Mode::INDX => {
+            let pointer = self.read_mem(bus, pc+1);
+            self.dummy_read(bus, pointer as usize);
+            self.read_zp_word(bus, pointer.wrapping_add(self.x_reg))
+         }
+         Mode::INDY => {
+            let pointer = self.read_mem(bus, pc+1);
+            let base = self.read_zp_word(bus, pointer);
+            self.index(bus, base, self.y_reg, write)
+         }
+         Mode::IND => {
+            // the pointer's high byte is fetched without carrying into the
+            // next page, so JMP ($10FF) reads 0x10FF and 0x1000
+            let pointer = self.abs_addr(bus, pc);
+            let lo = self.read_mem(bus, pointer) as usize;
+            let hi = self.read_mem(bus, (pointer & 0xFF00) | ((pointer + 1) & 0xFF)) as usize;
+            hi << 8 | lo
+         }
+         Mode::IMP | Mode::REL | Mode::ACC => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+      };
+      return Ok(addr);
+   }
+
+   /// Fetch the operand of an instruction that reads one.
+   fn operand (&mut self, bus : &mut dyn Bus, mode : Mode, pc : usize) -> Result<u8, Fault> {
+      let target_loc = self.address(bus, mode, pc, false)?;
+      return Ok(self.read_mem(bus, target_loc));
+   }
+
+   /// Apply `op` to the accumulator or to memory. In memory the 6502 writes
+   /// the unmodified value back before the result, and both writes reach
+   /// the bus.
+   fn modify (&mut self, bus : &mut dyn Bus, mode : Mode, pc : usize, op : fn(&mut Cpu, u8) -> u8) -> Result<usize, Fault> {
+      if let Mode::ACC = mode {
+         self.dummy_read(bus, pc + 1);
+         let val = self.a_reg;
+         self.a_reg = op(self, val);
+      } else {
+         let target_loc = self.address(bus, mode, pc, true)?;
+         let val = self.read_mem(bus, target_loc);
+         self.write_mem(bus, target_loc, val);
+         let result = op(self, val);
+         self.write_mem(bus, target_loc, result);
+      }
+      return Ok(pc + length(mode));
+   }
+
+   fn push (&mut self, bus : &mut dyn Bus, val : u8) {
+      self.write_mem(bus, 0x100 + self.s_pnt as usize, val);
+      self.s_pnt = self.s_pnt.wrapping_sub(1);
+   }
+
+   /// Pull a byte. The 6502 reads the stack once before moving the stack
+   /// pointer, which only the first pull of an instruction pays for, so
+   /// callers make that read with `stack_read`.
+   fn pull (&mut self, bus : &mut dyn Bus) -> u8 {
+      self.s_pnt = self.s_pnt.wrapping_add(1);
+      return self.read_mem(bus, 0x100 + self.s_pnt as usize);
+   }
+
+   fn stack_read (&mut self, bus : &mut dyn Bus) {
+      self.dummy_read(bus, 0x100 + self.s_pnt as usize);
+   }
+
+   /// Load the status register from a pushed copy. Bits 4 and 5 aren't
+   /// real flags, so they keep their current values.
+   fn pull_flags (&mut self, bus : &mut dyn Bus) {
+      let pulled = self.pull(bus);
+      let fixed = FlagWriter::BRK as u8 | FlagWriter::UNUSED as u8;
+      self.flags = (pulled & !fixed) | (self.flags & fixed);
+   }
+
+    /* #endregion */
+
+    /* #region Step Executor */
+
+    /// Execute the instruction at the program counter. On a fault the
+    /// program counter is left at the instruction, though the accesses made
+    /// before it was found to be bad, such as the opcode fetch, have
+    /// happened.
+    pub fn execute_step(&mut self, bus : &mut dyn Bus) -> Result<(), Fault> {
+
+       let pc = self.pc;
+       let opcode = self.read_mem(bus, pc);
+       let next = match OPCODES[opcode as usize] {
+          Some((instruction, mode)) => self.execute(bus, instruction, mode, pc)?,
+          None => return Err(Fault::Opcode { opcode, pc: pc as u16 }),
+       };
+       self.pc = next & 0xFFFF;
+       return Ok(());
+    }
+
+    /// Run one decoded instruction starting at `pc`, returning the address
+    /// of the next.
+    fn execute(&mut self, bus : &mut dyn Bus, instruction : Instruction, mode : Mode, pc : usize) -> Result<usize, Fault> {
+       use Instruction::*;
+       return match instruction {
+          ADC => self.adc(bus, mode, pc),
+          AND => self.and(bus, mode, pc),
+          ASL => self.asl(bus, mode, pc),
+          BIT => self.bit(bus, mode, pc),
+          CMP => self.cmp(bus, mode, pc),
+          CPX => self.cpx(bus, mode, pc),
+          CPY => self.cpy(bus, mode, pc),
+          DEC => self.dec(bus, mode, pc),
+          EOR => self.eor(bus, mode, pc),
+          INC => self.inc(bus, mode, pc),
+          JMP => self.jmp(bus, mode, pc),
+          LDA => self.lda(bus, mode, pc),
+          LDX => self.ldx(bus, mode, pc),
+          LDY => self.ldy(bus, mode, pc),
+          LSR => self.lsr(bus, mode, pc),
+          ORA => self.ora(bus, mode, pc),
+          ROL => self.rol(bus, mode, pc),
+          ROR => self.ror(bus, mode, pc),
+          SBC => self.sbc(bus, mode, pc),
+          STA => self.sta(bus, mode, pc),
+          STX => self.stx(bus, mode, pc),
+          STY => self.sty(bus, mode, pc),
+          BCC => Ok(self.bcc(bus, pc)),
+          BCS => Ok(self.bcs(bus, pc)),
+          BEQ => Ok(self.beq(bus, pc)),
+          BMI => Ok(self.bmi(bus, pc)),
+          BNE => Ok(self.bne(bus, pc)),
+          BPL => Ok(self.bpl(bus, pc)),
+          BRK => Ok(self.brk(bus, pc)),
+          BVC => Ok(self.bvc(bus, pc)),
+          BVS => Ok(self.bvs(bus, pc)),
+          CLC => Ok(self.clc(bus, pc)),
+          CLD => Ok(self.cld(bus, pc)),
+          CLI => Ok(self.cli(bus, pc)),
+          CLV => Ok(self.clv(bus, pc)),
+          DEX => Ok(self.dex(bus, pc)),
+          DEY => Ok(self.dey(bus, pc)),
+          INX => Ok(self.inx(bus, pc)),
+          INY => Ok(self.iny(bus, pc)),
+          JSR => Ok(self.jsr(bus, pc)),
+          NOP => Ok(self.nop(bus, pc)),
+          PHA => Ok(self.pha(bus, pc)),
+          PHP => Ok(self.php(bus, pc)),
+          PLA => Ok(self.pla(bus, pc)),
+          PLP => Ok(self.plp(bus, pc)),
+          RTI => Ok(self.rti(bus, pc)),
+          RTS => Ok(self.rts(bus, pc)),
+          SEC => Ok(self.sec(bus, pc)),
+          SED => Ok(self.sed(bus, pc)),
+          SEI => Ok(self.sei(bus, pc)),
+          TAX => Ok(self.tax(bus, pc)),
+          TAY => Ok(self.tay(bus, pc)),
+          TSX => Ok(self.tsx(bus, pc)),
+          TXA => Ok(self.txa(bus, pc)),
+          TXS => Ok(self.txs(bus, pc)),
+          TYA => Ok(self.tya(bus, pc)),
+       };
+    }
+   /* #endregion */
+
+   /* #region Arithmetic Instructions */
+
+    fn adc(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.add_with_carry(value);
+       return Ok(pc + length(mode));
+    }
+
+    fn sbc(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.subtract_with_carry(value);
+       return Ok(pc + length(mode));
+    }
+
+    fn add_with_carry(&mut self, value: u8) {
+       let out = alu::adc(self.a_reg, value, self.read_flag(Flag::CARRY), self.read_flag(Flag::DEC));
+       self.a_reg = out.value;
+       self.set_alu_flags(out);
+       self.write_flag(FlagWriter::OVER, out.overflow);
+   }
+
+    fn subtract_with_carry(&mut self, value: u8) {
+       let out = alu::sbc(self.a_reg, value, self.read_flag(Flag::CARRY), self.read_flag(Flag::DEC));
+       self.a_reg = out.value;
+       self.set_alu_flags(out);
+       self.write_flag(FlagWriter::OVER, out.overflow);
+   }
+
+    fn compare(&mut self, reg: u8, value: u8) {
+       self.set_alu_flags(alu::compare(reg, value));
+    }
+
+    fn cmp(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.compare(self.a_reg, value);
+       return Ok(pc + length(mode));
+    }
+
+    fn cpx(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.compare(self.x_reg, value);
+       return Ok(pc + length(mode));
+    }
+
+    fn cpy(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.compare(self.y_reg, value);
+       return Ok(pc + length(mode));
+    }
+
+   /* #endregion */
+
+    /* #region Flag (Processor Status) Instructions */
+    fn sei(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::IRQD, true);
+       return pc+1;
+    }
+ 
+    fn cli(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::IRQD, false);
+       return pc+1;
+    }
+ 
+    fn cld(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::DEC, false);
+       return pc+1;
+    }
+ 
+    fn clc(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::CARRY, false);
+       return pc+1;
+    }
+ 
+    fn clv(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::OVER, false);
+       return pc+1;
+    }
+ 
+    fn sed(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::DEC, true);
+       return pc+1;
+    }
+ 
+    fn sec(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.write_flag(FlagWriter::CARRY, true);
+       return pc+1;
+    }
+    /* #endregion */
+
+    /* #region LDX */
+    fn ldx(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.x_reg = match mode {
+          Mode::IMM | Mode::ZP | Mode::ZPY | Mode::ABS | Mode::ABSY => self.operand(bus, mode, pc)?,
+          _ => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+       };
+
+       self.set_flag_zero(self.x_reg);
+       self.set_flag_neg(self.x_reg);
+       return Ok(pc + length(mode));
+    }
+    /* #endregion */
+
+    /* #region LDY */
+
+     fn ldy(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.y_reg = match mode {
+          Mode::IMM | Mode::ZP | Mode::ZPX | Mode::ABS | Mode::ABSX => self.operand(bus, mode, pc)?,
+          _ => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+       };
+
+       self.set_flag_zero(self.y_reg);
+       self.set_flag_neg(self.y_reg);
+       return Ok(pc + length(mode));
+    }
+    /* #endregion */
+
+    /* #region LDA */
+
+    fn lda(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.a_reg = match mode {
+          Mode::IMP | Mode::REL | Mode::IND | Mode::ACC | Mode::ZPY => return Err(Fault::AddressingMode { mode, pc: pc as u16 }),
+          _ => self.operand(bus, mode, pc)?
+       };
+
+       self.set_flag_zero(self.a_reg);
+       self.set_flag_neg(self.a_reg);
+       return Ok(pc + length(mode));
+    }
+    /* #endregion */
+
+    /* #region STA, STX, STY */
+
+    fn sta(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+
+       let target_loc = match mode {
+          Mode::IMP | Mode::REL | Mode::IND | Mode::ACC | Mode::IMM | Mode::ZPY => return Err(Fault::AddressingMode { mode, pc: pc as u16 }),
+          _ => self.address(bus, mode, pc, true)?
+       };
+
+       self.write_mem(bus, target_loc, self.a_reg);
+       return Ok(pc + length(mode));
+    }
+
+    fn stx(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let target_loc = match mode {
+          Mode::ZP | Mode::ZPY | Mode::ABS => self.address(bus, mode, pc, true)?,
+          _ => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+       };
+       self.write_mem(bus, target_loc, self.x_reg);
+       return Ok(pc + length(mode));
+    }
+
+    fn sty(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let target_loc = match mode {
+          Mode::ZP | Mode::ZPX | Mode::ABS => self.address(bus, mode, pc, true)?,
+          _ => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+       };
+       self.write_mem(bus, target_loc, self.y_reg);
+       return Ok(pc + length(mode));
+    }
+    /* #endregion */
+
+    /* #region Stack Instructions */
+    fn txs(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.s_pnt = self.x_reg;
+       return pc + 1;
+    }
+    fn tsx(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.x_reg = self.s_pnt;
+       self.set_flags(self.x_reg);
+       return pc + 1;
+    }
+    fn pha(&mut self, bus: &mut dyn Bus, pc: usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.push(bus, self.a_reg);
+       return pc + 1;
+    }
+    fn pla(&mut self, bus: &mut dyn Bus, pc: usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.stack_read(bus);
+       self.a_reg = self.pull(bus);
+       self.set_flags(self.a_reg);
+       return pc + 1;
+    }
+    fn php(&mut self, bus: &mut dyn Bus, pc: usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       // the pushed copy always has the break and unused bits set
+       self.push(bus, self.flags | FlagWriter::BRK as u8 | FlagWriter::UNUSED as u8);
+       return pc + 1;
+    }
+    fn plp(&mut self, bus: &mut dyn Bus, pc: usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.stack_read(bus);
+       self.pull_flags(bus);
+       return pc + 1;
+    }
+    /* #endregion */
+
+   /* #region Register Instructions */
+
+     fn tax(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.x_reg = self.a_reg;
+       self.set_flag_zero(self.x_reg);
+       self.set_flag_neg(self.x_reg);
+       return pc + 1;
+    }
+    fn txa(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.a_reg = self.x_reg;
+       self.set_flag_zero(self.a_reg);
+       self.set_flag_neg(self.a_reg);
+       return pc + 1;
+    }
+    fn dex(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       if self.x_reg == 0 {
+          self.x_reg = 0xFF;
+       }
+       else {
+          self.x_reg -= 1;
+       }
+       self.set_flag_zero(self.x_reg);
+       self.set_flag_neg(self.x_reg);
+       return pc + 1;
+    }
+    fn inx(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.x_reg = self.x_reg.wrapping_add(1);
+       self.set_flags(self.x_reg);
+       return pc + 1;
+    }
+    fn tay(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.y_reg = self.a_reg;
+       self.set_flags(self.y_reg);
+       return pc + 1;
+    }
+    fn tya(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.a_reg = self.y_reg;
+       self.set_flags(self.a_reg);
+       return pc + 1;
+    }
+    fn dey(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       if self.y_reg == 0 {
+          self.y_reg = 0xFF;
+       }
+       else {
+          self.y_reg -= 1;
+       }
+       self.set_flags(self.y_reg);
+       return pc + 1;
+    }
+    fn iny(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.y_reg = self.y_reg.wrapping_add(1);
+       self.set_flags(self.y_reg);
+       return pc + 1;
+    }
+    /* #endregion */
+
+    /* #region Logical Instructions */
+    fn and(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.a_reg &= self.operand(bus, mode, pc)?;
+       self.set_flags(self.a_reg);
+       return Ok(pc + length(mode));
+    }
+
+    fn ora(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.a_reg |= self.operand(bus, mode, pc)?;
+       self.set_flags(self.a_reg);
+       return Ok(pc + length(mode));
+    }
+
+    fn eor(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       self.a_reg ^= self.operand(bus, mode, pc)?;
+       self.set_flags(self.a_reg);
+       return Ok(pc + length(mode));
+    }
+
+    fn bit(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       let value = self.operand(bus, mode, pc)?;
+       self.set_flag_zero(self.a_reg & value);
+       self.write_flag(FlagWriter::NEG, value & 0x80 != 0);
+       self.write_flag(FlagWriter::OVER, value & 0x40 != 0);
+       return Ok(pc + length(mode));
+    }
+    /* #endregion */
+
+    /* #region Increments, Decrements and Shifts */
+    fn inc(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| {
+          let result = val.wrapping_add(1);
+          cpu.set_flags(result);
+          result
+       });
+    }
+
+    fn dec(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| {
+          let result = val.wrapping_sub(1);
+          cpu.set_flags(result);
+          result
+       });
+    }
+
+    fn asl(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| cpu.shift(alu::asl(val)));
+    }
+
+    fn lsr(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| cpu.shift(alu::lsr(val)));
+    }
+
+    fn rol(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| {
+          let carry = cpu.read_flag(Flag::CARRY);
+          cpu.shift(alu::rol(val, carry))
+       });
+    }
+
+    fn ror(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+       return self.modify(bus, mode, pc, |cpu, val| {
+          let carry = cpu.read_flag(Flag::CARRY);
+          cpu.shift(alu::ror(val, carry))
+       });
+    }
+
+    fn shift(&mut self, out: alu::Output) -> u8 {
+       self.set_alu_flags(out);
+       return out.value;
+    }
+    /* #endregion */
+
+    /* #region Branching Instructions */
+
+    /// Take the relative branch at `pc` if `condition` holds. Costs one
+    /// more cycle when taken and another if the target is on a new page,
+    /// both spent reading from the wrong address.
+    fn branch(&mut self, bus: &mut dyn Bus, pc : usize, condition: bool) -> usize {
+       let step = self.read_mem(bus, pc+1) as i8;
+       let next = (pc + 2) & 0xFFFF;
+       if !condition {
+          return next;
+       }
+       self.dummy_read(bus, next);
+       let target = (next as i32 + step as i32) as usize & 0xFFFF;
+       if target & 0xFF00 != next & 0xFF00 {
+          self.dummy_read(bus, (next & 0xFF00) | (target & 0xFF));
+       }
+       return target;
+    }
+
+    fn bpl(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = !self.read_flag(Flag::NEG);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bmi(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = self.read_flag(Flag::NEG);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bvc(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = !self.read_flag(Flag::OVER);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bvs(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = self.read_flag(Flag::OVER);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bcc(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = !self.read_flag(Flag::CARRY);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bcs(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = self.read_flag(Flag::CARRY);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn bne(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = !self.read_flag(Flag::ZERO);
+       return self.branch(bus, pc, condition);
+    }
+
+    fn beq(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       let condition = self.read_flag(Flag::ZERO);
+       return self.branch(bus, pc, condition);
+    }
+    /* #endregion */
+
+    /* #region Jumping Instructions */
+    fn jmp(&mut self, bus: &mut dyn Bus, mode: Mode, pc : usize) -> Result<usize, Fault> {
+
+       let target_loc = match mode {
+          Mode::ABS | Mode::IND => self.address(bus, mode, pc, false)?,
+          _ => return Err(Fault::AddressingMode { mode, pc: pc as u16 })
+       };
+       return Ok(target_loc);
+    }
+
+    fn jsr(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       // the high byte of the target is only fetched once the return
+       // address, that of the last byte of the JSR, has been pushed
+       let lo = self.read_mem(bus, pc + 1) as usize;
+       self.stack_read(bus);
+       let ret = pc + 2;
+       self.push(bus, (ret >> 8) as u8);
+       self.push(bus, ret as u8);
+       let hi = self.read_mem(bus, ret) as usize;
+       return hi << 8 | lo;
+    }
+
+    fn rts(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.stack_read(bus);
+       let lo = self.pull(bus) as usize;
+       let hi = self.pull(bus) as usize;
+       let ret = hi << 8 | lo;
+       self.dummy_read(bus, ret);
+       return (ret + 1) & 0xFFFF;
+    }
+
+    fn brk(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       // BRK skips the byte after it, so returns to pc + 2
+       self.dummy_read(bus, pc + 1);
+       let ret = pc + 2;
+       self.push(bus, (ret >> 8) as u8);
+       self.push(bus, ret as u8);
+       self.push(bus, self.flags | FlagWriter::BRK as u8 | FlagWriter::UNUSED as u8);
+       self.write_flag(FlagWriter::IRQD, true);
+       return self.read_word(bus, IRQ_VECTOR);
+    }
+
+    fn rti(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       self.stack_read(bus);
+       self.pull_flags(bus);
+       let lo = self.pull(bus) as usize;
+       let hi = self.pull(bus) as usize;
+       return hi << 8 | lo;
+    }
+
+    /* #endregion */
+
+    fn nop(&mut self, bus: &mut dyn Bus, pc : usize) -> usize {
+       self.dummy_read(bus, pc + 1);
+       return pc + 1;
+    }
+ }
+
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        return Cpu::new();
+    }
+}
+
+impl Snapshot for Cpu {
+    fn save(&self, out: &mut Writer) {
+        out.u8(self.flags);
+        out.u16(self.pc as u16);
+        out.u8(self.x_reg);
+        out.u8(self.y_reg);
+        out.u8(self.a_reg);
+        out.u8(self.s_pnt);
+        out.usize(self.cycles);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        self.flags = input.u8()?;
+        self.pc = input.u16()? as usize;
+        self.x_reg = input.u8()?;
+        self.y_reg = input.u8()?;
+        self.a_reg = input.u8()?;
+        self.s_pnt = input.u8()?;
+        self.cycles = input.usize()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bus::FlatBus;
+
+    fn setup_cpu() -> (Cpu, FlatBus) {
+        return (Cpu::new(), FlatBus::new());
+        }
+
+    /// Put `opcode` at the program counter, in front of whatever operand
+    /// the test has set up, and execute it. Returns the new program counter.
+    fn step(cpu: &mut Cpu, bus: &mut FlatBus, opcode: u8) -> usize {
+        bus.memory[cpu.pc] = opcode;
+        cpu.execute_step(bus).unwrap();
+        return cpu.pc;
+    }
+   /* #region ldx tests */
+    #[test]
+    fn test_ldx_imm() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x10;
+        bus.memory[1] = expected;
+        let pc = step(&mut cpu, &mut bus, 0xA2);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.cycles, 2);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_zp() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x12;
+        bus.memory[0x10] = expected;
+        bus.memory[1]    = 0x10;
+        let pc = step(&mut cpu, &mut bus, 0xA6);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.cycles, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_zpy() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x10+5] = expected;
+        bus.memory[0x10] = 0x12;
+        bus.memory[1]    = 0x10;
+        cpu.y_reg         = 5;
+        let pc = step(&mut cpu, &mut bus, 0xB6);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_abs() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x1210] = expected;
+        bus.memory[1]    = 0x10;
+        bus.memory[2]    = 0x12;
+        let pc = step(&mut cpu, &mut bus, 0xAE);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_absy() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x0010+5] = expected;
+        bus.memory[1]    = 0x10;
+        bus.memory[2]    = 0x00;
+        cpu.y_reg = 5;
+        let pc = step(&mut cpu, &mut bus, 0xBE);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_absy_page_boundary() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x12FE+5] = expected;
+        bus.memory[1]    = 0xFE;
+        bus.memory[2]    = 0x12;
+        cpu.y_reg = 5;
+        let pc = step(&mut cpu, &mut bus, 0xBE);
+        assert_eq!(cpu.x_reg, expected);
+        assert_eq!(cpu.cycles, 5);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_invalid_mode() {
+        let (mut cpu, mut bus) = setup_cpu();
+        assert_eq!(cpu.ldx(&mut bus, Mode::ABSX, 0), Err(Fault::AddressingMode { mode: Mode::ABSX, pc: 0 }));
+    }
+
+    /* #endregion */
+
+    /* #region ldy tests */
+    #[test]
+    fn test_ldy_imm() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x10;
+        bus.memory[1] = expected;
+        let pc = step(&mut cpu, &mut bus, 0xA0);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.cycles, 2);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldy_zp() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x12;
+        bus.memory[0x10] = expected;
+        bus.memory[1]    = 0x10;
+        let pc = step(&mut cpu, &mut bus, 0xA4);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.cycles, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldy_zpx() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x10+5] = expected;
+        bus.memory[0x10] = 0x12;
+        bus.memory[1]    = 0x10;
+        cpu.x_reg         = 5;
+        let pc = step(&mut cpu, &mut bus, 0xB4);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldy_abs() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x1210] = expected;
+        bus.memory[1]    = 0x10;
+        bus.memory[2]    = 0x12;
+        let pc = step(&mut cpu, &mut bus, 0xAC);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldy_absx() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x0010+5] = expected;
+        bus.memory[1]    = 0x10;
+        bus.memory[2]    = 0x00;
+        cpu.x_reg = 5;
+        let pc = step(&mut cpu, &mut bus, 0xBC);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldx_absx_page_boundary() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x12FE+5] = expected;
+        bus.memory[1]    = 0xFE;
+        bus.memory[2]    = 0x12;
+        cpu.x_reg = 5;
+        let pc = step(&mut cpu, &mut bus, 0xBC);
+        assert_eq!(cpu.y_reg, expected);
+        assert_eq!(cpu.cycles, 5);
+        assert_eq!(pc, 3);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+    #[test]
+    fn test_ldy_invalid_mode() {
+        let (mut cpu, mut bus) = setup_cpu();
+        assert_eq!(cpu.ldy(&mut bus, Mode::ABSY, 0), Err(Fault::AddressingMode { mode: Mode::ABSY, pc: 0 }));
+    }
+
+   /* #endregion */
+
+   /* #region lda tests */
+   #[test]
+   fn test_lda_imm() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x10;
+       bus.memory[1] = expected;
+       let pc = step(&mut cpu, &mut bus, 0xA9);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(pc, 2);
+       assert_eq!(cpu.cycles, 2);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_zp() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x12;
+       bus.memory[0x10] = expected;
+       bus.memory[1]    = 0x10;
+       let pc = step(&mut cpu, &mut bus, 0xA5);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(pc, 2);
+       assert_eq!(cpu.cycles, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+    fn test_lda_zpx() {
+        let (mut cpu, mut bus) = setup_cpu();
+        let expected = 0x9;
+        bus.memory[0x10+5] = expected;
+        bus.memory[0x10] = 0x12;
+        bus.memory[1]    = 0x10;
+        cpu.x_reg         = 5;
+        let pc = step(&mut cpu, &mut bus, 0xB5);
+        assert_eq!(cpu.a_reg, expected);
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(pc, 2);
+        assert_eq!(cpu.read_flag(Flag::NEG), false);
+        assert_eq!(cpu.read_flag(Flag::ZERO), false);
+    }
+
+   #[test]
+   fn test_lda_abs() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x9;
+       bus.memory[0x1210] = expected;
+       bus.memory[1]    = 0x10;
+       bus.memory[2]    = 0x12;
+       let pc = step(&mut cpu, &mut bus, 0xAD);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(cpu.cycles, 4);
+       assert_eq!(pc, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_absy() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x9;
+       bus.memory[0x0010+5] = expected;
+       bus.memory[1]    = 0x10;
+       bus.memory[2]    = 0x00;
+       cpu.y_reg = 5;
+       let pc = step(&mut cpu, &mut bus, 0xB9);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(cpu.cycles, 4);
+       assert_eq!(pc, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_absy_page_boundary() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x9;
+       bus.memory[0x12FE+5] = expected;
+       bus.memory[1]    = 0xFE;
+       bus.memory[2]    = 0x12;
+       cpu.y_reg = 5;
+       let pc = step(&mut cpu, &mut bus, 0xB9);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(cpu.cycles, 5);
+       assert_eq!(pc, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_absx() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x9;
+       bus.memory[0x0010+5] = expected;
+       bus.memory[1]    = 0x10;
+       bus.memory[2]    = 0x00;
+       cpu.x_reg = 5;
+       let pc = step(&mut cpu, &mut bus, 0xBD);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(cpu.cycles, 4);
+       assert_eq!(pc, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_absx_page_boundary() {
+       let (mut cpu, mut bus) = setup_cpu();
+       let expected = 0x9;
+       bus.memory[0x12FE+5] = expected;
+       bus.memory[1]    = 0xFE;
+       bus.memory[2]    = 0x12;
+       cpu.x_reg = 5;
+       let pc = step(&mut cpu, &mut bus, 0xBD);
+       assert_eq!(cpu.a_reg, expected);
+       assert_eq!(cpu.cycles, 5);
+       assert_eq!(pc, 3);
+       assert_eq!(cpu.read_flag(Flag::NEG), false);
+       assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_lda_invalid_mode() {
+       let (mut cpu, mut bus) = setup_cpu();
+       assert_eq!(cpu.lda(&mut bus, Mode::ZPY, 0), Err(Fault::AddressingMode { mode: Mode::ZPY, pc: 0 }));
+   }
+
+   /* #endregion */
+
+/* #region sta tests */
+
+#[test]
+fn test_sta_zp() {
+    let (mut cpu, mut bus) = setup_cpu();
+    bus.memory[1]    = 0x10;
+    cpu.a_reg = 0x15;
+    let pc = step(&mut cpu, &mut bus, 0x85);
+    assert_eq!(bus.memory[0x10], cpu.a_reg);
+    assert_eq!(pc, 2);
+    assert_eq!(cpu.cycles, 3);
+}
+
+#[test]
+ fn test_sta_zpx() {
+     let (mut cpu, mut bus) = setup_cpu();
+     bus.memory[1]    = 0x10;
+     cpu.x_reg         = 5;
+     cpu.a_reg = 0x11;
+     let pc = step(&mut cpu, &mut bus, 0x95);
+     assert_eq!(bus.memory[0x15], cpu.a_reg);
+     assert_eq!(cpu.cycles, 4);
+     assert_eq!(pc, 2);
+ }
+
+#[test]
+fn test_sta_abs() {
+    let (mut cpu, mut bus) = setup_cpu();
+    bus.memory[1]    = 0x10;
+    bus.memory[2]    = 0x12;
+    cpu.a_reg = 0x11;
+    let pc = step(&mut cpu, &mut bus, 0x8D);
+    assert_eq!(cpu.a_reg, bus.memory[0x1210]);
+    assert_eq!(cpu.cycles, 4);
+    assert_eq!(pc, 3);
+}
+
+#[test]
+fn test_sta_absy() {
+   let (mut cpu, mut bus) = setup_cpu();
+   bus.memory[1]    = 0x10;
+   bus.memory[2]    = 0x12;
+   cpu.y_reg = 0x12;
+   cpu.a_reg = 0x11;
+   let pc = step(&mut cpu, &mut bus, 0x99);
+   assert_eq!(cpu.a_reg, bus.memory[0x1222]);
+   assert_eq!(cpu.cycles, 5);
+   assert_eq!(pc, 3);
+}
+
+#[test]
+fn test_sta_absx() {
+   let (mut cpu, mut bus) = setup_cpu();
+   bus.memory[1]    = 0x10;
+   bus.memory[2]    = 0x12;
+   cpu.x_reg = 0x12;
+   cpu.a_reg = 0x11;
+   let pc = step(&mut cpu, &mut bus, 0x9D);
+   assert_eq!(cpu.a_reg, bus.memory[0x1222]);
+   assert_eq!(cpu.cycles, 5);
+   assert_eq!(pc, 3);
+}
+
+// #[test]
+// #[should_panic(expected = "INVALID ADDRESSING MODE!!!")]
+// fn test_lda_invalid_mode() {
+//     let (mut cpu, mut bus) = setup_cpu();
+//     cpu.lda(&mut bus, Mode::ZPY, 0);
+// }
+
+/* #endregion */
+
+
+   /* #region Flag (Processor Status) Instructions tests */
+   #[test]
+   fn test_sec() {
+      let (mut cpu, mut bus) = setup_cpu();
+      let pc = step(&mut cpu, &mut bus, 0x38);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_cli() {
+      let (mut cpu, mut bus) = setup_cpu();
+      cpu.write_flag(FlagWriter::IRQD, true);
+      let pc = step(&mut cpu, &mut bus, 0x58);
+      assert_eq!(cpu.flags & FlagWriter::IRQD as u8, 0);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_sei() {
+      let (mut cpu, mut bus) = setup_cpu();
+      let pc = step(&mut cpu, &mut bus, 0x78);
+      assert_ne!(cpu.flags & FlagWriter::IRQD as u8, 0);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_clv() {
+      let (mut cpu, mut bus) = setup_cpu();
+      cpu.write_flag(FlagWriter::OVER, true);
+      let pc = step(&mut cpu, &mut bus, 0xB8);
+      assert_eq!(cpu.read_flag(Flag::OVER), false);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_cld() {
+      let (mut cpu, mut bus) = setup_cpu();
+      cpu.write_flag(FlagWriter::DEC, true);
+      let pc = step(&mut cpu, &mut bus, 0xD8);
+      assert_eq!(cpu.read_flag(Flag::DEC), false);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_sed() {
+      let (mut cpu, mut bus) = setup_cpu();
+      let pc = step(&mut cpu, &mut bus, 0xF8);
+      assert_eq!(cpu.read_flag(Flag::DEC), true);
+      assert_eq!(1, pc);
+      assert_eq!(cpu.cycles, 2);
+   }
+
+   #[test]
+   fn test_txs() {
+      let (mut cpu, mut bus) = setup_cpu();
+      cpu.x_reg = 0x12;
+      step(&mut cpu, &mut bus, 0x9A);
+      assert_eq!(cpu.s_pnt, 0x12);
+   }
+
+   #[test]
+   fn test_tsx() {
+      let (mut cpu, mut bus) = setup_cpu();
+      cpu.s_pnt = 0x12;
+      step(&mut cpu, &mut bus, 0xBA);
+      assert_eq!(cpu.x_reg, 0x12);
+   }
+   /* #endregion */
+   
+   /* #region utility functions tests */
+
+   #[test]
+   fn test_set_flag_neg_false() {
+      let (mut cpu, _) = setup_cpu();
+      let value = 0x12;
+      cpu.set_flag_neg(value);
+      assert_eq!(cpu.read_flag(Flag::NEG), false);
+      let value = 0x40;
+      cpu.set_flag_neg(value);
+      assert_eq!(cpu.read_flag(Flag::NEG), false);
+   }
+
+   #[test]
+   fn test_set_flag_neg_true() {
+      let (mut cpu, _) = setup_cpu();
+      let value = 0x80;
+      cpu.set_flag_neg(value);
+      assert_eq!(cpu.read_flag(Flag::NEG), true);
+      let value = 0xFF;
+      cpu.set_flag_neg(value);
+      assert_eq!(cpu.read_flag(Flag::NEG), true);
+   }
+
+   #[test]
+   fn test_set_flag_zero_false() {
+      let (mut cpu, _) = setup_cpu();
+      let value = 0x12;
+      cpu.set_flag_zero(value);
+      assert_eq!(cpu.read_flag(Flag::ZERO), false);
+      let value = 0xFF;
+      cpu.set_flag_zero(value);
+      assert_eq!(cpu.read_flag(Flag::ZERO), false);
+   }
+
+   #[test]
+   fn test_set_flag_zero_true() {
+      let (mut cpu, _) = setup_cpu();
+      let value = 0x00;
+      cpu.set_flag_zero(value);
+      assert_eq!(cpu.read_flag(Flag::ZERO), true);
+   }
+
+   /* #endregion */
+
+   /* #region program tests */
+
+   /// Load `program` at 0x0200 and execute `steps` instructions of it.
+   fn run_program(program: &[u8], steps: usize) -> (Cpu, FlatBus) {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[0x200..0x200 + program.len()].copy_from_slice(program);
+      cpu.pc = 0x200;
+      cpu.s_pnt = 0xFF;
+      for _ in 0..steps {
+         cpu.execute_step(&mut bus).unwrap();
+      }
+      return (cpu, bus);
+   }
+
+   #[test]
+   fn test_adc_binary() {
+      // CLC; LDA #$50; ADC #$50
+      let (cpu, _) = run_program(&[0x18, 0xA9, 0x50, 0x69, 0x50], 3);
+      assert_eq!(cpu.a_reg, 0xA0);
+      assert_eq!(cpu.read_flag(Flag::OVER), true);
+      assert_eq!(cpu.read_flag(Flag::NEG), true);
+      assert_eq!(cpu.read_flag(Flag::CARRY), false);
+      // SEC; LDA #$FF; ADC #$00
+      let (cpu, _) = run_program(&[0x38, 0xA9, 0xFF, 0x69, 0x00], 3);
+      assert_eq!(cpu.a_reg, 0x00);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      assert_eq!(cpu.read_flag(Flag::ZERO), true);
+      assert_eq!(cpu.read_flag(Flag::OVER), false);
+   }
+
+   #[test]
+   fn test_adc_sbc_decimal() {
+      // SED; CLC; LDA #$58; ADC #$46
+      let (cpu, _) = run_program(&[0xF8, 0x18, 0xA9, 0x58, 0x69, 0x46], 4);
+      assert_eq!(cpu.a_reg, 0x04);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      // SED; SEC; LDA #$46; SBC #$12
+      let (cpu, _) = run_program(&[0xF8, 0x38, 0xA9, 0x46, 0xE9, 0x12], 4);
+      assert_eq!(cpu.a_reg, 0x34);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      // SED; SEC; LDA #$12; SBC #$21
+      let (cpu, _) = run_program(&[0xF8, 0x38, 0xA9, 0x12, 0xE9, 0x21], 4);
+      assert_eq!(cpu.a_reg, 0x91);
+      assert_eq!(cpu.read_flag(Flag::CARRY), false);
+   }
+
+   #[test]
+   fn test_sbc_binary() {
+      // SEC; LDA #$50; SBC #$B0
+      let (cpu, _) = run_program(&[0x38, 0xA9, 0x50, 0xE9, 0xB0], 3);
+      assert_eq!(cpu.a_reg, 0xA0);
+      assert_eq!(cpu.read_flag(Flag::OVER), true);
+      assert_eq!(cpu.read_flag(Flag::CARRY), false);
+   }
+
+   #[test]
+   fn test_compare() {
+      // LDA #$40; CMP #$40; LDX #$10; CPX #$20
+      let (cpu, _) = run_program(&[0xA9, 0x40, 0xC9, 0x40], 2);
+      assert_eq!(cpu.read_flag(Flag::ZERO), true);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      let (cpu, _) = run_program(&[0xA2, 0x10, 0xE0, 0x20], 2);
+      assert_eq!(cpu.read_flag(Flag::ZERO), false);
+      assert_eq!(cpu.read_flag(Flag::CARRY), false);
+      assert_eq!(cpu.read_flag(Flag::NEG), true);
+   }
+
+   #[test]
+   fn test_shifts_and_rotates() {
+      // SEC; LDA #$81; ROR A; ROL A; ASL A; LSR A
+      let (cpu, _) = run_program(&[0x38, 0xA9, 0x81, 0x6A], 3);
+      assert_eq!(cpu.a_reg, 0xC0);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      let (cpu, _) = run_program(&[0x38, 0xA9, 0x81, 0x6A, 0x2A, 0x0A, 0x4A], 7);
+      assert_eq!(cpu.a_reg, 0x01);
+      assert_eq!(cpu.read_flag(Flag::CARRY), false);
+      // ASL $10 with $10 = $80
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[1] = 0x10;
+      bus.memory[0x10] = 0x80;
+      let pc = step(&mut cpu, &mut bus, 0x06);
+      assert_eq!(bus.memory[0x10], 0);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      assert_eq!(cpu.read_flag(Flag::ZERO), true);
+      assert_eq!(pc, 2);
+      assert_eq!(cpu.cycles, 5);
+   }
+
+   #[test]
+   fn test_bit() {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[1] = 0x10;
+      bus.memory[0x10] = 0xC0;
+      cpu.a_reg = 0x01;
+      step(&mut cpu, &mut bus, 0x24);
+      assert_eq!(cpu.read_flag(Flag::ZERO), true);
+      assert_eq!(cpu.read_flag(Flag::NEG), true);
+      assert_eq!(cpu.read_flag(Flag::OVER), true);
+      assert_eq!(cpu.a_reg, 0x01);
+   }
+
+   #[test]
+   fn test_indirect_modes() {
+      let (mut cpu, mut bus) = setup_cpu();
+      // ($20,X) with X = 4 reads the pointer at $24
+      bus.memory[1] = 0x20;
+      bus.memory[0x24] = 0x00;
+      bus.memory[0x25] = 0x30;
+      bus.memory[0x3000] = 0x55;
+      cpu.x_reg = 4;
+      step(&mut cpu, &mut bus, 0xA1);
+      assert_eq!(cpu.a_reg, 0x55);
+      assert_eq!(cpu.cycles, 6);
+      // ($24),Y crossing a page costs a cycle
+      bus.memory[1] = 0x24;
+      bus.memory[0x24] = 0xFF;
+      bus.memory[0x30FF + 2] = 0x66;
+      cpu.y_reg = 2;
+      cpu.pc = 0;
+      cpu.cycles = 0;
+      step(&mut cpu, &mut bus, 0xB1);
+      assert_eq!(cpu.a_reg, 0x66);
+      assert_eq!(cpu.cycles, 6);
+   }
+
+   #[test]
+   fn test_jmp_indirect_page_wrap() {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[1] = 0xFF;
+      bus.memory[2] = 0x10;
+      bus.memory[0x10FF] = 0x34;
+      bus.memory[0x1000] = 0x12;
+      bus.memory[0x1100] = 0x56;
+      let pc = step(&mut cpu, &mut bus, 0x6C);
+      assert_eq!(pc, 0x1234);
+      assert_eq!(cpu.cycles, 5);
+   }
+
+   #[test]
+   fn test_jsr_rts() {
+      // JSR $0206; BRK; NOP; NOP; INX; RTS
+      let (cpu, bus) = run_program(&[0x20, 0x06, 0x02, 0x00, 0xEA, 0xEA, 0xE8, 0x60], 1);
+      assert_eq!(cpu.pc, 0x206);
+      assert_eq!(cpu.s_pnt, 0xFD);
+      assert_eq!(bus.memory[0x1FF], 0x02);
+      assert_eq!(bus.memory[0x1FE], 0x02);
+      let (cpu, _) = run_program(&[0x20, 0x06, 0x02, 0x00, 0xEA, 0xEA, 0xE8, 0x60], 3);
+      assert_eq!(cpu.pc, 0x203);
+      assert_eq!(cpu.x_reg, 1);
+      assert_eq!(cpu.s_pnt, 0xFF);
+   }
+
+   #[test]
+   fn test_brk_rti() {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[0xFFFE] = 0x00;
+      bus.memory[0xFFFF] = 0x30;
+      bus.memory[0x3000] = 0x40; // RTI
+      cpu.pc = 0x200;
+      cpu.s_pnt = 0xFF;
+      cpu.flags = 0b1100_0001;
+      cpu.execute_step(&mut bus).unwrap();
+      assert_eq!(cpu.pc, 0x3000);
+      assert_ne!(cpu.flags & FlagWriter::IRQD as u8, 0);
+      assert_eq!(bus.memory[0x1FD], 0b1111_0001);
+      cpu.execute_step(&mut bus).unwrap();
+      assert_eq!(cpu.pc, 0x202);
+      assert_eq!(cpu.flags, 0b1100_0001);
+   }
+
+   #[test]
+   fn test_unimplemented_opcode() {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[0x200] = 0x02;
+      cpu.pc = 0x200;
+      assert_eq!(cpu.execute_step(&mut bus), Err(Fault::Opcode { opcode: 0x02, pc: 0x200 }));
+      assert_eq!(cpu.pc, 0x200);
+   }
+
+   #[test]
+   fn test_php_plp() {
+      // SEC; SED; PHP; CLC; CLD; PLP
+      let (cpu, bus) = run_program(&[0x38, 0xF8, 0x08, 0x18, 0xD8, 0x28], 6);
+      assert_eq!(bus.memory[0x1FF], 0b0011_1001);
+      assert_eq!(cpu.read_flag(Flag::CARRY), true);
+      assert_eq!(cpu.read_flag(Flag::DEC), true);
+      assert_eq!(cpu.flags & FlagWriter::BRK as u8, 0);
+   }
+
+   #[test]
+   fn test_branches() {
+      // LDX #2; DEX; BNE -3; BEQ +2; ...
+      let (cpu, _) = run_program(&[0xA2, 0x02, 0xCA, 0xD0, 0xFD, 0xF0, 0x02], 6);
+      assert_eq!(cpu.x_reg, 0);
+      assert_eq!(cpu.pc, 0x209);
+      // a taken branch into the next page costs two extra cycles
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[0x2F1] = 0x10;
+      cpu.write_flag(FlagWriter::NEG, true);
+      cpu.pc = 0x2F0;
+      let pc = step(&mut cpu, &mut bus, 0x30);
+      assert_eq!(pc, 0x302);
+      assert_eq!(cpu.cycles, 4);
+   }
+
+   #[test]
+   fn test_reset() {
+      let (mut cpu, mut bus) = setup_cpu();
+      bus.memory[0xFFFC] = 0x00;
+      bus.memory[0xFFFD] = 0xF0;
+      cpu.reset(&mut bus);
+      assert_eq!(cpu.pc, 0xF000);
+      assert_eq!(cpu.s_pnt, 0xFD);
+      assert_ne!(cpu.flags & FlagWriter::IRQD as u8, 0);
+      assert_eq!(cpu.cycles, 7);
+   }
+
+   /* #endregion */
+
+}
@@ -0,0 +1,163 @@
+//! Effects of a CRT, worked out on the CPU over RGBA frames: the glow a
+//! phosphor keeps after the beam has passed, the blur of an NTSC composite
+//! signal and the dark gaps between scanlines.
+
+use crate::viewport::Placement;
+
+/// The glow a phosphor keeps from frame to frame. Many games draw more
+/// objects than the TIA has by showing some on even frames and some on odd
+/// ones; on a TV the glow left over hides the flicker.
+pub struct Phosphor {
+    /// How much of the last frame's brightness is left after a frame, from
+    /// 0 for none to 1 for all of it.
+    decay: f32,
+    /// The last frame shown.
+    previous: Vec<u8>,
+}
+
+impl Phosphor {
+    pub fn new(decay: f32) -> Phosphor {
+        return Phosphor { decay: decay.clamp(0.0, 1.0), previous: Vec::new() };
+    }
+
+    pub fn decay(&self) -> f32 {
+        return self.decay;
+    }
+
+    /// Show `frame` on the phosphor: each channel is lit by the frame or
+    /// still glows from the last, whichever is brighter. A frame of a new
+    /// size starts the glow over.
+    pub fn apply(&mut self, frame: &mut [u8]) {
+        if self.previous.len() == frame.len() {
+            for (value, &previous) in frame.iter_mut().zip(self.previous.iter()) {
+                *value = (*value).max((previous as f32 * self.decay) as u8);
+            }
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(frame);
+    }
+}
+
+/// Smear a picture, RGBA `width` pixels wide, along its lines the way an
+/// NTSC composite signal does: the color carries less detail than the
+/// brightness, so it bleeds over several pixels while edges only soften.
+pub fn composite(picture: &mut [u8], width: usize) {
+    const LUMA: [f32; 3] = [1.0, 2.0, 1.0];
+    const CHROMA: [f32; 5] = [1.0, 2.0, 3.0, 2.0, 1.0];
+    let mut yiq = vec![[0.0f32; 3]; width];
+    for line in picture.chunks_exact_mut(width * 4) {
+        for (pixel, rgba) in yiq.iter_mut().zip(line.chunks_exact(4)) {
+            let (r, g, b) = (rgba[0] as f32, rgba[1] as f32, rgba[2] as f32);
+            *pixel = [
+                0.299 * r + 0.587 * g + 0.114 * b,
+                0.596 * r - 0.274 * g - 0.322 * b,
+                0.211 * r - 0.523 * g + 0.312 * b,
+            ];
+        }
+        // the weighted average of a component over the pixels around x
+        let blur = |x: usize, component: usize, kernel: &[f32]| {
+            let reach = kernel.len() / 2;
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for (i, &weight) in kernel.iter().enumerate() {
+                if let Some(pixel) = (x + i).checked_sub(reach).and_then(|at| yiq.get(at)) {
+                    sum += pixel[component] * weight;
+                    weights += weight;
+                }
+            }
+            return sum / weights;
+        };
+        for (x, rgba) in line.chunks_exact_mut(4).enumerate() {
+            let (y, i, q) = (blur(x, 0, &LUMA), blur(x, 1, &CHROMA), blur(x, 2, &CHROMA));
+            let rgb = [y + 0.956 * i + 0.621 * q, y - 0.272 * i - 0.647 * q, y - 1.106 * i + 1.703 * q];
+            for (value, channel) in rgba.iter_mut().zip(rgb.iter()) {
+                *value = channel.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// Darken the gaps between a picture's `lines` once it has been scaled into
+/// `window`, RGBA `window_width` pixels wide, at `placement`: the lower half
+/// of each line is dimmed by `darkness`, from 0 for not at all to 1 for
+/// black. Lines less than two pixels tall are left alone, as they'd only
+/// show a beat pattern.
+pub fn scanlines(window: &mut [u8], window_width: usize, placement: Placement, lines: usize, darkness: f32) {
+    if lines == 0 || placement.height < 2 * lines {
+        return;
+    }
+    let keep = 1.0 - darkness.clamp(0.0, 1.0);
+    for row in 0..placement.height {
+        let position = (row as f64 + 0.5) * lines as f64 / placement.height as f64;
+        if position.fract() < 0.5 {
+            continue;
+        }
+        let start = ((placement.y + row) * window_width + placement.x) * 4;
+        let line = match window.get_mut(start..start + placement.width * 4) {
+            Some(line) => line,
+            None => break,
+        };
+        for pixel in line.chunks_exact_mut(4) {
+            for value in &mut pixel[..3] {
+                *value = (*value as f32 * keep) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phosphor() {
+        let mut phosphor = Phosphor::new(0.5);
+        let mut lit = vec![200, 100, 0, 255];
+        phosphor.apply(&mut lit);
+        assert_eq!(lit, vec![200, 100, 0, 255]);
+        // the object is drawn on alternate frames
+        let mut dark = vec![10, 10, 10, 255];
+        phosphor.apply(&mut dark);
+        assert_eq!(dark, vec![100, 50, 10, 255]);
+        let mut darker = vec![0, 0, 0, 255];
+        phosphor.apply(&mut darker);
+        assert_eq!(darker, vec![50, 25, 5, 255]);
+
+        // a new size starts over
+        let mut wide = vec![0; 8];
+        phosphor.apply(&mut wide);
+        assert_eq!(wide, vec![0; 8]);
+        assert_eq!(Phosphor::new(2.0).decay(), 1.0);
+    }
+
+    #[test]
+    fn test_composite() {
+        // a gray line keeps its color
+        let mut gray: Vec<u8> = [128, 128, 128, 255].repeat(6);
+        composite(&mut gray, 6);
+        assert!(gray.chunks(4).all(|pixel| pixel == [128, 128, 128, 255]));
+
+        // a red pixel bleeds its color further than its brightness
+        let mut line: Vec<u8> = [0, 0, 0, 255].repeat(7);
+        line[12..15].copy_from_slice(&[255, 0, 0]);
+        composite(&mut line, 7);
+        assert!(line[12] < 255 && line[12] > 0);
+        assert!(line[8] > 0 || line[9] > 0 || line[10] > 0);
+        assert_eq!(&line[..4], &[0, 0, 0, 255]);
+        assert_eq!(line[3], 255);
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut window = vec![200; 3 * 4 * 4];
+        let placement = Placement { x: 1, y: 0, width: 2, height: 4 };
+        scanlines(&mut window, 3, placement, 2, 0.5);
+        let rows: Vec<Vec<u8>> = window.chunks(12).map(|row| row.chunks(4).map(|pixel| pixel[0]).collect()).collect();
+        assert_eq!(rows, vec![vec![200, 200, 200], vec![200, 100, 100], vec![200, 200, 200], vec![200, 100, 100]]);
+        assert_eq!(window[7], 200);
+
+        // too few rows for each line to show a gap
+        let mut small = vec![200; 3 * 4 * 4];
+        scanlines(&mut small, 3, placement, 3, 0.5);
+        assert!(small.iter().all(|&value| value == 200));
+    }
+}
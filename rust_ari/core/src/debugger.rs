@@ -0,0 +1,773 @@
+//! An interactive debugger for kernels running on a `Machine`: PC
+//! breakpoints, watchpoints on addresses and registers, conditions on the
+//! CPU state, breaks on a scanline or cycle, and stepping into, over and
+//! out of subroutines, and stepping backwards. It is driven by text
+//! commands, normally from the prompt `repl` runs.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::bus::Bus;
+use crate::disasm;
+use crate::error::Fault;
+use crate::machine::{decode, Board, Machine};
+use crate::rewind::Rewind;
+use crate::riot::Access;
+use crate::symbols;
+use crate::trace;
+
+/// Frames `continue` and `finish` run for at most, so a breakpoint that is
+/// never hit doesn't hang the prompt.
+const MAX_FRAMES: usize = 600;
+
+/// Instructions between the snapshots `reverse` restores from. Reversing
+/// re-executes up to this many from the nearest one.
+const SNAPSHOT_INTERVAL: usize = 1000;
+/// Snapshots kept for `reverse`, reaching back this many intervals.
+const SNAPSHOTS: usize = 2000;
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+const FLAG_NAMES: &str = "NV-BDIZC";
+
+const HELP: &str = "\
+step [n]              (s)  execute n instructions, into subroutines
+reverse [n]           (rs) go back n instructions
+next                  (n)  execute one instruction, over subroutines
+finish                (f)  run until the current subroutine returns
+continue [frames]     (c)  run until something breaks, for at most 600 frames
+frame [n]                  run n frames
+break <addr> [if <cond>]   (b)  stop before executing <addr>
+break if <cond>            stop wherever <cond> holds
+watch [r|w|rw] <addr|register> [if <cond>]  (w)  stop after an access
+scanline <n> [if <cond>]   stop when the TIA starts scanline <n>
+cycle <n> [if <cond>]      stop when <n> CPU cycles have passed
+list                  (l)  list breakpoints
+delete <id>|all       (d)  remove breakpoints
+regs                  (r)  show the registers
+mem <addr> [len]      (m)  dump memory without side effects
+disasm [addr] [n]     (u)  disassemble n instructions from <addr> or the PC
+reset                      reset the CPU and cartridge banks
+help                  (h)  show this
+quit                  (q)  leave the debugger
+
+<cond> is one or more '<operand> <op> <value>' joined by '&&', where the
+operand is a register (a x y s p pc), a flag (n v d i z c), line or clock
+and <op> is one of == != < <= > >=. Numbers are decimal, or hex with $ or 0x.";
+
+/// What a condition compares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    PC,
+    Flag(u8),
+    Scanline,
+    Clock,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Condition {
+    operand: Operand,
+    compare: Compare,
+    value: usize,
+}
+
+impl Condition {
+    fn parse(text: &str) -> Result<Condition, String> {
+        let operators = [
+            ("==", Compare::Eq),
+            ("!=", Compare::Ne),
+            ("<=", Compare::Le),
+            (">=", Compare::Ge),
+            ("<", Compare::Lt),
+            (">", Compare::Gt),
+        ];
+        for &(symbol, compare) in operators.iter() {
+            if let Some(at) = text.find(symbol) {
+                let name = text[..at].trim().to_ascii_lowercase();
+                let operand = match name.as_str() {
+                    "a" => Operand::A,
+                    "x" => Operand::X,
+                    "y" => Operand::Y,
+                    "s" | "sp" => Operand::S,
+                    "p" => Operand::P,
+                    "pc" => Operand::PC,
+                    "line" | "scanline" => Operand::Scanline,
+                    "clock" => Operand::Clock,
+                    flag if flag.len() == 1 && "nvbdizc".contains(flag) => {
+                        let bit = 7 - FLAG_NAMES.to_ascii_lowercase().find(flag).unwrap();
+                        Operand::Flag(1 << bit)
+                    }
+                    _ => return Err(format!("unknown operand: {}", name)),
+                };
+                let value = parse_number(text[at + symbol.len()..].trim())?;
+                return Ok(Condition { operand, compare, value });
+            }
+        }
+        return Err(format!("expected a comparison: {}", text));
+    }
+
+    fn holds(&self, machine: &Machine) -> bool {
+        let cpu = &machine.cpu;
+        let actual = match self.operand {
+            Operand::A => cpu.a_reg as usize,
+            Operand::X => cpu.x_reg as usize,
+            Operand::Y => cpu.y_reg as usize,
+            Operand::S => cpu.s_pnt as usize,
+            Operand::P => cpu.flags as usize,
+            Operand::PC => cpu.pc,
+            Operand::Flag(mask) => (cpu.flags & mask != 0) as usize,
+            Operand::Scanline => machine.board.tia.scanline,
+            Operand::Clock => machine.board.tia.clock,
+        };
+        return match self.compare {
+            Compare::Eq => actual == self.value,
+            Compare::Ne => actual != self.value,
+            Compare::Lt => actual < self.value,
+            Compare::Le => actual <= self.value,
+            Compare::Gt => actual > self.value,
+            Compare::Ge => actual >= self.value,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// Before the instruction at an address. Only the 13 address lines the
+    /// 6507 has are compared, so $F000 also catches $1000.
+    Pc(u16),
+    /// Wherever the conditions hold.
+    Anywhere,
+    /// After an instruction that accessed the address.
+    Watch(u16, Access),
+    /// When the TIA starts a scanline.
+    Scanline(usize),
+    /// When the board has run for a number of CPU cycles.
+    Cycle(usize),
+}
+
+struct Breakpoint {
+    id: usize,
+    kind: Kind,
+    conditions: Vec<Condition>,
+    /// The command that set it, for listing.
+    text: String,
+}
+
+/// A watched access an instruction made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub addr: u16,
+    pub val: u8,
+    pub write: bool,
+}
+
+/// Why execution stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// The command finished what it was asked to do.
+    Done,
+    Breakpoint(usize),
+    Watchpoint(usize, Hit),
+    /// Ran for the most frames a command may.
+    Limit(usize),
+    /// The CPU couldn't execute the next instruction, and is left at it.
+    Fault(Fault),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match *self {
+            Stop::Done => Ok(()),
+            Stop::Breakpoint(id) => write!(f, "breakpoint {}", id),
+            Stop::Watchpoint(id, hit) => {
+                let (verb, preposition) = if hit.write { ("write", "to") } else { ("read", "from") };
+                write!(f, "watchpoint {}: {} ${:02X} {} ${:04X}", id, verb, hit.val, preposition, hit.addr)?;
+                if let Some(name) = symbols::name(hit.addr, hit.write) {
+                    write!(f, " ({})", name)?;
+                }
+                Ok(())
+            }
+            Stop::Limit(1) => write!(f, "stopped after 1 frame"),
+            Stop::Limit(frames) => write!(f, "stopped after {} frames", frames),
+            Stop::Fault(fault) => write!(f, "CPU fault: {}", fault),
+        };
+    }
+}
+
+/// The board as the CPU sees it while watchpoints are set, noting the
+/// accesses that hit one.
+struct Watcher<'a> {
+    board: &'a mut Board,
+    watches: Vec<(usize, u16, Access)>,
+    hits: Vec<(usize, Hit)>,
+}
+
+impl<'a> Watcher<'a> {
+    fn check(&mut self, addr: u16, val: u8, write: bool) {
+        for &(id, watched, access) in &self.watches {
+            let allowed = match access {
+                Access::Read => !write,
+                Access::Write => write,
+                Access::Both => true,
+            };
+            if allowed && decode(addr, write) == decode(watched, write) {
+                self.hits.push((id, Hit { addr, val, write }));
+            }
+        }
+    }
+}
+
+impl<'a> Bus for Watcher<'a> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.board.read(addr);
+        self.check(addr, val, false);
+        return val;
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.board.write(addr, val);
+        self.check(addr, val, true);
+    }
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+    /// Instructions executed under the debugger, which `history` is marked
+    /// with.
+    executed: usize,
+    history: Rewind,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        return Debugger {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            executed: 0,
+            history: Rewind::new(SNAPSHOTS),
+        };
+    }
+
+    /// Prompt for commands on `output` and run them until `quit` or the end
+    /// of `input`. An empty line repeats the last command.
+    pub fn repl(&mut self, machine: &mut Machine, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "{}", self.status(machine))?;
+        let mut last = String::new();
+        loop {
+            write!(output, "(rustari) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = match line.trim() {
+                "" => last.clone(),
+                line => line.to_string(),
+            };
+            if line == "q" || line == "quit" {
+                return Ok(());
+            }
+            match self.command(machine, &line) {
+                Ok(text) => writeln!(output, "{}", text)?,
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            last = line;
+        }
+    }
+
+    /// Run one command and return what it has to say.
+    pub fn command(&mut self, machine: &mut Machine, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+
+        let stop = match name {
+            "s" | "step" => {
+                let mut left = count_at(&args, 0, 1)?;
+                if left == 0 {
+                    return Err("step needs a count of at least 1".to_string());
+                }
+                self.run(machine, MAX_FRAMES, &mut |_, _| {
+                    left -= 1;
+                    left == 0
+                })
+            }
+            "rs" | "reverse" => {
+                let count = count_at(&args, 0, 1)?;
+                let target = self.executed.checked_sub(count).ok_or("not that many instructions executed")?;
+                self.executed = self.history.rewind_to(machine, target).ok_or("no history that far back")?;
+                while self.executed < target {
+                    self.execute(machine, Vec::new()).map_err(|fault| fault.to_string())?;
+                }
+                Stop::Done
+            }
+            "n" | "next" => {
+                if machine.board.peek(machine.cpu.pc as u16) != JSR {
+                    self.run(machine, MAX_FRAMES, &mut |_, _| true)
+                } else {
+                    let ret = (machine.cpu.pc + 3) & 0xFFFF;
+                    let stack = machine.cpu.s_pnt;
+                    self.run(machine, MAX_FRAMES, &mut |machine, _| {
+                        machine.cpu.pc == ret && machine.cpu.s_pnt >= stack
+                    })
+                }
+            }
+            "f" | "finish" => {
+                let stack = machine.cpu.s_pnt;
+                self.run(machine, MAX_FRAMES, &mut |machine, opcode| {
+                    (opcode == RTS || opcode == RTI) && machine.cpu.s_pnt > stack
+                })
+            }
+            "c" | "continue" => {
+                let frames = count_at(&args, 0, MAX_FRAMES)?;
+                self.run(machine, frames, &mut |_, _| false)
+            }
+            "frame" => {
+                let frames = count_at(&args, 0, 1)?;
+                let last = machine.board.tia.frame_count + frames;
+                self.run(machine, frames + 1, &mut |machine, _| machine.board.tia.frame_count >= last)
+            }
+            "b" | "break" | "watch" | "w" | "scanline" | "cycle" => return self.add(name, &args),
+            "l" | "list" => return Ok(self.list()),
+            "d" | "delete" => return self.delete(&args),
+            "r" | "regs" => return Ok(self.status(machine)),
+            "m" | "mem" => {
+                let addr = parse_address(args.first().ok_or("mem needs an address")?)?.0;
+                return Ok(dump(machine, addr, count_at(&args, 1, 16)?));
+            }
+            "u" | "disasm" => {
+                let addr = match args.first() {
+                    Some(addr) => parse_address(addr)?.0,
+                    None => machine.cpu.pc as u16,
+                };
+                return Ok(disassemble(machine, addr, count_at(&args, 1, 10)?));
+            }
+            "reset" => {
+                machine.reset();
+                Stop::Done
+            }
+            "h" | "help" | "?" => return Ok(HELP.to_string()),
+            _ => return Err(format!("unknown command: {} (try help)", name)),
+        };
+
+        let status = self.status(machine);
+        return Ok(match stop {
+            Stop::Done => status,
+            stop => format!("{}\n{}", stop, status),
+        });
+    }
+
+    /// Execute instructions until `done` is satisfied by the state after one
+    /// of them and the opcode it executed, a breakpoint hits, or `frames`
+    /// frames have been completed.
+    fn run(&mut self, machine: &mut Machine, frames: usize, done: &mut dyn FnMut(&Machine, u8) -> bool) -> Stop {
+        let first_frame = machine.board.tia.frame_count;
+        loop {
+            let opcode = machine.board.peek(machine.cpu.pc as u16);
+            if let Some(stop) = self.step(machine) {
+                return stop;
+            }
+            if done(machine, opcode) {
+                return Stop::Done;
+            }
+            let run = machine.board.tia.frame_count - first_frame;
+            if run >= frames {
+                return Stop::Limit(run);
+            }
+        }
+    }
+
+    /// Execute one instruction and check every breakpoint against it.
+    pub fn step(&mut self, machine: &mut Machine) -> Option<Stop> {
+        let scanline = machine.board.tia.scanline;
+        let cycles = machine.board.cycles;
+        let watches = self
+            .breakpoints
+            .iter()
+            .filter_map(|bp| match bp.kind {
+                Kind::Watch(addr, access) => Some((bp.id, addr, access)),
+                _ => None,
+            })
+            .collect();
+        trace::record(machine);
+        let hits = match self.execute(machine, watches) {
+            Ok(hits) => hits,
+            Err(fault) => return Some(Stop::Fault(fault)),
+        };
+
+        for bp in &self.breakpoints {
+            let hit = hits.iter().find(|&&(id, _)| id == bp.id).map(|&(_, hit)| hit);
+            let triggered = match bp.kind {
+                Kind::Pc(addr) => machine.cpu.pc & 0x1FFF == addr as usize & 0x1FFF,
+                Kind::Anywhere => true,
+                Kind::Watch(..) => hit.is_some(),
+                Kind::Scanline(line) => machine.board.tia.scanline == line && scanline != line,
+                Kind::Cycle(n) => machine.board.cycles >= n && cycles < n,
+            };
+            if !triggered || !bp.conditions.iter().all(|c| c.holds(machine)) {
+                continue;
+            }
+            return Some(match hit {
+                Some(hit) => Stop::Watchpoint(bp.id, hit),
+                None => Stop::Breakpoint(bp.id),
+            });
+        }
+        return None;
+    }
+
+    /// Execute one instruction, noting accesses to `watches`, and take a
+    /// snapshot for `reverse` first if one is due. An instruction that
+    /// faults doesn't count as executed.
+    fn execute(&mut self, machine: &mut Machine, watches: Vec<(usize, u16, Access)>) -> Result<Vec<(usize, Hit)>, Fault> {
+        if self.executed.is_multiple_of(SNAPSHOT_INTERVAL) && self.history.latest_mark() != Some(self.executed) {
+            self.history.record(machine, self.executed);
+        }
+        let mut watcher = Watcher {
+            board: &mut machine.board,
+            watches,
+            hits: Vec::new(),
+        };
+        machine.cpu.execute_step(&mut watcher)?;
+        self.executed += 1;
+        return Ok(watcher.hits);
+    }
+
+    /// Add a breakpoint from a `break`, `watch`, `scanline` or `cycle`
+    /// command.
+    fn add(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        let split = args.iter().position(|&arg| arg == "if").unwrap_or(args.len());
+        let (spec, condition) = (&args[..split], &args[split..]);
+        let conditions = match condition.len() {
+            0 => Vec::new(),
+            1 => return Err("'if' needs a condition".to_string()),
+            _ => condition[1..]
+                .join(" ")
+                .split("&&")
+                .map(Condition::parse)
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        let kind = match (command, spec) {
+            ("b", []) | ("break", []) if !conditions.is_empty() => Kind::Anywhere,
+            ("b", [addr]) | ("break", [addr]) => Kind::Pc(parse_number(addr)? as u16),
+            ("w", [target]) | ("watch", [target]) => {
+                let (addr, access) = parse_address(target)?;
+                Kind::Watch(addr, access)
+            }
+            ("w", [mode, target]) | ("watch", [mode, target]) => {
+                let access = match *mode {
+                    "r" => Access::Read,
+                    "w" => Access::Write,
+                    "rw" => Access::Both,
+                    _ => return Err(format!("expected r, w or rw: {}", mode)),
+                };
+                Kind::Watch(parse_address(target)?.0, access)
+            }
+            ("scanline", [line]) => Kind::Scanline(parse_number(line)?),
+            ("cycle", [n]) => Kind::Cycle(parse_number(n)?),
+            _ => return Err(format!("usage: see 'help' for {}", command)),
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let text = format!("{} {}", command, args.join(" "));
+        self.breakpoints.push(Breakpoint { id, kind, conditions, text });
+        return Ok(format!("{}: {}", id, self.breakpoints.last().unwrap().text));
+    }
+
+    fn list(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints".to_string();
+        }
+        let lines: Vec<String> = self.breakpoints.iter().map(|bp| format!("{}: {}", bp.id, bp.text)).collect();
+        return lines.join("\n");
+    }
+
+    fn delete(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            ["all"] => self.breakpoints.clear(),
+            [id] => {
+                let id = parse_number(id)?;
+                let before = self.breakpoints.len();
+                self.breakpoints.retain(|bp| bp.id != id);
+                if self.breakpoints.len() == before {
+                    return Err(format!("no breakpoint {}", id));
+                }
+            }
+            _ => return Err("delete needs an id or 'all'".to_string()),
+        }
+        return Ok(self.list());
+    }
+
+    /// The registers, flags and where the TIA's beam is, followed by the
+    /// next instruction.
+    pub fn status(&self, machine: &Machine) -> String {
+        let cpu = &machine.cpu;
+        let tia = &machine.board.tia;
+        return format!(
+            "PC={:04X} A={:02X} X={:02X} Y={:02X} S={:02X} P={:02X} {}  line {} clock {} cycle {}",
+            cpu.pc, cpu.a_reg, cpu.x_reg, cpu.y_reg, cpu.s_pnt, cpu.flags, flags(cpu.flags), tia.scanline, tia.clock,
+            machine.board.cycles
+        ) + "\n" + &disassemble(machine, cpu.pc as u16, 1);
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        return Debugger::new();
+    }
+}
+
+/// The status register as `NV-BDIZC`, upper case for the flags that are
+/// set, e.g. `nv-bdIzc` after reset.
+pub fn flags(p: u8) -> String {
+    return FLAG_NAMES
+        .chars()
+        .enumerate()
+        .map(|(i, name)| if p & (0x80 >> i) != 0 { name } else { name.to_ascii_lowercase() })
+        .collect();
+}
+
+/// Parse a number: decimal, or hex with a `$` or `0x` prefix.
+pub fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = if let Some(hex) = text.strip_prefix('$') {
+        usize::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+    return parsed.map_err(|_| format!("not a number: {}", text));
+}
+
+/// Parse an address or a TIA/RIOT register name, returning the kinds of
+/// access the name refers to. Plain addresses refer to both.
+fn parse_address(text: &str) -> Result<(u16, Access), String> {
+    if let Some(symbol) = symbols::lookup(text) {
+        return Ok(symbol);
+    }
+    let addr = parse_number(text).map_err(|_| format!("not an address or register: {}", text))?;
+    return Ok((addr as u16, Access::Both));
+}
+
+fn count_at(args: &[&str], index: usize, default: usize) -> Result<usize, String> {
+    return args.get(index).map_or(Ok(default), |arg| parse_number(arg));
+}
+
+/// Hex dump `len` bytes from `addr`, sixteen to a line.
+fn dump(machine: &Machine, addr: u16, len: usize) -> String {
+    let mut lines = Vec::new();
+    for row in (0..len).step_by(16) {
+        let start = addr.wrapping_add(row as u16);
+        let bytes: Vec<String> = (row..len.min(row + 16))
+            .map(|i| format!("{:02X}", machine.board.peek(addr.wrapping_add(i as u16))))
+            .collect();
+        lines.push(format!("${:04X}: {}", start, bytes.join(" ")));
+    }
+    return lines.join("\n");
+}
+
+/// Disassemble `count` instructions from `addr` without side effects.
+fn disassemble(machine: &Machine, addr: u16, count: usize) -> String {
+    let read = |addr: u16| machine.board.peek(addr);
+    let mut lines = Vec::new();
+    let mut addr = addr;
+    for _ in 0..count {
+        let line = disasm::instruction(&read, addr);
+        addr = addr.wrapping_add(line.bytes.len() as u16);
+        lines.push(line.to_string());
+    }
+    return lines.join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::properties::Properties;
+
+    /// A 4K cartridge holding `program` at 0xF000 with the reset vector
+    /// pointing at it.
+    fn machine(program: &[u8]) -> Machine {
+        let mut rom = vec![0; 0x1000];
+        rom[..program.len()].copy_from_slice(program);
+        rom[0xFFD] = 0xF0;
+        return Machine::new(Cartridge::new(rom, Properties::new("")));
+    }
+
+    /// JSR $F007; NOP; JMP $F004; INX; INX; RTS
+    const SUBROUTINE: [u8; 10] = [0x20, 0x07, 0xF0, 0xEA, 0x4C, 0x04, 0xF0, 0xE8, 0xE8, 0x60];
+
+    #[test]
+    fn test_conditional_pc_breakpoint() {
+        // LDX #0; INX; JMP $F002
+        let mut machine = machine(&[0xA2, 0x00, 0xE8, 0x4C, 0x02, 0xF0]);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut machine, "break $1002 if x == 3").unwrap();
+        let text = debugger.command(&mut machine, "continue").unwrap();
+        assert!(text.starts_with("breakpoint 1\nPC=F002"), "{}", text);
+        assert_eq!(machine.cpu.x_reg, 3);
+    }
+
+    #[test]
+    fn test_watch_register_by_name() {
+        // NOP; STA WSYNC; JMP $F000
+        let mut machine = machine(&[0xEA, 0x85, 0x42, 0x4C, 0x00, 0xF0]);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut machine, "watch wsync").unwrap();
+        let text = debugger.command(&mut machine, "c").unwrap();
+        assert!(text.starts_with("watchpoint 1: write $00 to $0042 (WSYNC)"), "{}", text);
+        assert_eq!(machine.cpu.pc, 0xF003);
+        // reads of the same address aren't WSYNC
+        debugger.command(&mut machine, "delete all").unwrap();
+        debugger.command(&mut machine, "watch r $02").unwrap();
+        let text = debugger.command(&mut machine, "c 1").unwrap();
+        assert!(text.starts_with("stopped after 1 frame\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut over = machine(&SUBROUTINE);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut over, "next").unwrap();
+        assert_eq!(over.cpu.pc, 0xF003);
+        assert_eq!(over.cpu.x_reg, 2);
+
+        let mut out = machine(&SUBROUTINE);
+        debugger.command(&mut out, "step 2").unwrap();
+        assert_eq!(out.cpu.pc, 0xF008);
+        debugger.command(&mut out, "finish").unwrap();
+        assert_eq!(out.cpu.pc, 0xF003);
+        assert_eq!(out.cpu.s_pnt, 0xFD);
+    }
+
+    #[test]
+    fn test_scanline_and_cycle_breaks() {
+        // STA WSYNC; JMP $F000
+        let mut machine = machine(&[0x85, 0x02, 0x4C, 0x00, 0xF0]);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut machine, "scanline 5").unwrap();
+        debugger.command(&mut machine, "c").unwrap();
+        assert_eq!(machine.board.tia.scanline, 5);
+        debugger.command(&mut machine, "cycle 1000").unwrap();
+        let text = debugger.command(&mut machine, "c").unwrap();
+        assert!(text.starts_with("breakpoint 2"), "{}", text);
+        // the JMP after STA WSYNC takes up to a scanline to execute
+        assert!(machine.board.cycles >= 1000 && machine.board.cycles < 1000 + 76);
+    }
+
+    #[test]
+    fn test_flag_condition() {
+        // LDX #3; DEX; JMP $F002
+        let mut machine = machine(&[0xA2, 0x03, 0xCA, 0x4C, 0x02, 0xF0]);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut machine, "break if z == 1 && pc == $F003").unwrap();
+        debugger.command(&mut machine, "c").unwrap();
+        assert_eq!(machine.cpu.x_reg, 0);
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let mut machine = machine(&[]);
+        let mut debugger = Debugger::new();
+        assert!(debugger.command(&mut machine, "break").is_err());
+        assert!(debugger.command(&mut machine, "break $F000 if").is_err());
+        assert!(debugger.command(&mut machine, "break if q == 1").is_err());
+        assert!(debugger.command(&mut machine, "watch NOPE").is_err());
+        assert!(debugger.command(&mut machine, "delete 7").is_err());
+        assert!(debugger.command(&mut machine, "frobnicate").is_err());
+        assert!(debugger.command(&mut machine, "step 0").is_err());
+        assert_eq!(debugger.command(&mut machine, "list").unwrap(), "no breakpoints");
+    }
+
+    #[test]
+    fn test_fault_stops() {
+        // LDX #1; a JAM opcode
+        let mut machine = machine(&[0xA2, 0x01, 0x02]);
+        let mut debugger = Debugger::new();
+        let text = debugger.command(&mut machine, "continue").unwrap();
+        assert!(text.starts_with("CPU fault: unimplemented opcode $02 at $F002\n"), "{}", text);
+        assert_eq!(debugger.step(&mut machine), Some(Stop::Fault(Fault::Opcode { opcode: 0x02, pc: 0xF002 })));
+        assert_eq!(machine.cpu.pc, 0xF002);
+        assert_eq!(machine.cpu.x_reg, 1);
+        assert_eq!(debugger.executed, 1);
+        debugger.command(&mut machine, "reverse").unwrap();
+        assert_eq!(machine.cpu.pc, 0xF000);
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut machine = machine(&SUBROUTINE);
+        let mut debugger = Debugger::new();
+        let mut input = io::Cursor::new("break $F008\ncontinue\nmem $F000 4\n\nquit\nstep\n");
+        let mut output = Vec::new();
+        debugger.repl(&mut machine, &mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("1: break $F008"), "{}", output);
+        assert!(output.contains("breakpoint 1\nPC=F008"), "{}", output);
+        // the empty line repeats the dump, and nothing runs after quit
+        assert_eq!(output.matches("$F000: 20 07 F0 EA").count(), 2, "{}", output);
+        assert_eq!(machine.cpu.pc, 0xF008);
+    }
+
+    #[test]
+    fn test_disasm() {
+        let mut machine = machine(&SUBROUTINE);
+        let mut debugger = Debugger::new();
+        let text = debugger.command(&mut machine, "step").unwrap();
+        assert!(text.ends_with("\nF007  E8        INX"), "{}", text);
+        let text = debugger.command(&mut machine, "disasm $F000 3").unwrap();
+        assert_eq!(text, "F000  20 07 F0  JSR $F007\nF003  EA        NOP\nF004  4C 04 F0  JMP $F004");
+        assert_eq!(debugger.command(&mut machine, "u").unwrap().lines().count(), 10);
+    }
+
+    #[test]
+    fn test_reverse() {
+        // LDX #0; INX; STX $80; JMP $F002
+        let mut machine = machine(&[0xA2, 0x00, 0xE8, 0x86, 0x80, 0x4C, 0x02, 0xF0]);
+        let mut debugger = Debugger::new();
+        debugger.command(&mut machine, "step 2500").unwrap();
+        let pc = machine.cpu.pc;
+        let cycles = machine.board.cycles;
+        let x = machine.cpu.x_reg;
+
+        debugger.command(&mut machine, "step 3").unwrap();
+        let text = debugger.command(&mut machine, "reverse 3").unwrap();
+        assert!(text.starts_with(&format!("PC={:04X}", pc)), "{}", text);
+        assert_eq!((machine.cpu.x_reg, machine.board.cycles), (x, cycles));
+        // stepping back over the INX undoes it, and the STX after it
+        debugger.command(&mut machine, "break $F005").unwrap();
+        debugger.command(&mut machine, "c").unwrap();
+        assert_eq!(machine.ram()[0], machine.cpu.x_reg);
+        debugger.command(&mut machine, "rs 2").unwrap();
+        assert_eq!(machine.cpu.pc, 0xF002);
+        assert_eq!(machine.ram()[0], machine.cpu.x_reg);
+
+        assert!(debugger.command(&mut machine, "reverse 5000").is_err());
+        let back_to_reset = format!("reverse {}", debugger.executed);
+        debugger.command(&mut machine, &back_to_reset).unwrap();
+        assert_eq!(machine.cpu.pc, 0xF000);
+        assert_eq!(machine.board.cycles, 7);
+    }
+}
@@ -0,0 +1,309 @@
+//! A 6502 disassembler built on the CPU's opcode table. Operands that hit a
+//! TIA or RIOT register are shown by name, the way 2600 source writes them.
+//!
+//! `listing` disassembles a whole cartridge image, telling code from data by
+//! following every path the CPU can take from the reset and BRK vectors.
+
+use std::fmt;
+
+use crate::cpu::{self, Instruction, Mode, OPCODES};
+use crate::symbols;
+
+/// Size of the cartridge window, and of the banks a listing is split into.
+const BANK_SIZE: usize = 0x1000;
+
+/// Data bytes shown per `.byte` line, which start at multiples of it.
+const BYTES_PER_LINE: usize = 8;
+
+const RESET_VECTOR: u16 = 0xFFFC;
+const BRK_VECTOR: u16 = 0xFFFE;
+
+/// One disassembled instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    /// The opcode and operand bytes.
+    pub bytes: Vec<u8>,
+    /// Mnemonic and operand, e.g. `STA WSYNC`.
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        return write!(f, "{:04X}  {:<8}  {}", self.addr, bytes.join(" "), self.text);
+    }
+}
+
+/// Disassemble the instruction at `addr`, fetching bytes with `read`. An
+/// undocumented opcode comes out as a one byte `.byte`.
+pub fn instruction(read: &dyn Fn(u16) -> u8, addr: u16) -> Line {
+    let opcode = read(addr);
+    let (instruction, mode) = match OPCODES[opcode as usize] {
+        Some(decoded) => decoded,
+        None => {
+            return Line {
+                addr,
+                bytes: vec![opcode],
+                text: format!(".byte ${:02X}", opcode),
+            }
+        }
+    };
+    let bytes: Vec<u8> = (0..cpu::length(mode))
+        .map(|i| read(addr.wrapping_add(i as u16)))
+        .collect();
+    let operand = match bytes.len() {
+        1 => 0,
+        2 => bytes[1] as u16,
+        _ => u16::from_le_bytes([bytes[1], bytes[2]]),
+    };
+    let target = || {
+        if let Instruction::JMP | Instruction::JSR = instruction {
+            return format!("${:04X}", operand);
+        }
+        return match symbols::name(operand, writes(instruction)) {
+            Some(name) => name.to_string(),
+            None if bytes.len() == 2 => format!("${:02X}", operand),
+            None => format!("${:04X}", operand),
+        };
+    };
+    let text = match mode {
+        Mode::IMP => String::new(),
+        Mode::ACC => "A".to_string(),
+        Mode::IMM => format!("#${:02X}", operand),
+        Mode::REL => format!("${:04X}", branch_target(addr, operand as u8)),
+        Mode::ZP | Mode::ABS => target(),
+        Mode::ZPX | Mode::ABSX => format!("{},X", target()),
+        Mode::ZPY | Mode::ABSY => format!("{},Y", target()),
+        Mode::INDX => format!("(${:02X},X)", operand),
+        Mode::INDY => format!("(${:02X}),Y", operand),
+        Mode::IND => format!("(${:04X})", operand),
+    };
+    let text = if text.is_empty() {
+        instruction.to_string()
+    } else {
+        format!("{} {}", instruction, text)
+    };
+    return Line { addr, bytes, text };
+}
+
+/// Disassemble a cartridge image one 4K bank at a time (2K images are a
+/// single bank), tracing code from each bank's vectors. Bytes that no
+/// traced path reaches are listed as `.byte` data.
+pub fn listing(rom: &[u8]) -> String {
+    let mut out = String::new();
+    for (number, image) in rom.chunks(BANK_SIZE).enumerate() {
+        if number > 0 {
+            out.push('\n');
+        }
+        let bank = Bank::new(image);
+        let reset = bank.word(RESET_VECTOR);
+        let brk = bank.word(BRK_VECTOR);
+        out.push_str(&format!("; bank {}: reset ${:04X}, brk ${:04X}\n", number, reset, brk));
+        let starts = bank.trace(&[reset, brk]);
+        out.push_str(&bank.render(&starts));
+    }
+    return out;
+}
+
+/// Whether `instruction` writes the memory it addresses, which decides
+/// which of the TIA's read or write registers an address names.
+fn writes(instruction: Instruction) -> bool {
+    use Instruction::*;
+    return matches!(instruction, STA | STX | STY | ASL | LSR | ROL | ROR | INC | DEC);
+}
+
+fn branch_target(addr: u16, offset: u8) -> u16 {
+    return addr.wrapping_add(2).wrapping_add(offset as i8 as u16);
+}
+
+/// A bank of a cartridge image as seen in the cartridge window.
+struct Bank<'a> {
+    image: &'a [u8],
+    /// Address the first byte is shown at.
+    origin: u16,
+}
+
+impl<'a> Bank<'a> {
+    /// Place the bank where its reset vector says it was assembled, or at
+    /// the top of memory if the vector points outside the cartridge.
+    fn new(image: &'a [u8]) -> Bank<'a> {
+        let mut bank = Bank { image, origin: 0 };
+        let reset = bank.word(RESET_VECTOR);
+        bank.origin = if in_cartridge(reset) {
+            reset - bank.offset(reset) as u16
+        } else {
+            (0x10000 - image.len()) as u16
+        };
+        return bank;
+    }
+
+    /// Where `addr`, or its mirror, is in the image.
+    fn offset(&self, addr: u16) -> usize {
+        return (addr as usize & (BANK_SIZE - 1)) % self.image.len();
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        return self.image[self.offset(addr)];
+    }
+
+    fn word(&self, addr: u16) -> u16 {
+        return u16::from_le_bytes([self.read(addr), self.read(addr.wrapping_add(1))]);
+    }
+
+    /// Follow every path from `entries`, returning for each byte of the
+    /// image whether an instruction starts there. A path ends at a return,
+    /// an indirect jump, an undocumented opcode or code it has seen.
+    fn trace(&self, entries: &[u16]) -> Vec<bool> {
+        let mut starts = vec![false; self.image.len()];
+        let mut covered = vec![false; self.image.len()];
+        let mut pending: Vec<u16> = entries.to_vec();
+        while let Some(mut addr) = pending.pop() {
+            while in_cartridge(addr) {
+                let offset = self.offset(addr);
+                let (instruction, mode) = match OPCODES[self.read(addr) as usize] {
+                    Some(decoded) => decoded,
+                    None => break,
+                };
+                let len = cpu::length(mode);
+                if offset + len > self.image.len() || covered[offset..offset + len].contains(&true) {
+                    break;
+                }
+                starts[offset] = true;
+                for byte in &mut covered[offset..offset + len] {
+                    *byte = true;
+                }
+                let operand = self.word(addr.wrapping_add(1));
+                match (instruction, mode) {
+                    (_, Mode::REL) => pending.push(branch_target(addr, operand as u8)),
+                    (Instruction::JSR, _) => pending.push(operand),
+                    (Instruction::JMP, Mode::ABS) => {
+                        pending.push(operand);
+                        break;
+                    }
+                    (Instruction::JMP, _) | (Instruction::RTS, _) | (Instruction::RTI, _) | (Instruction::BRK, _) => {
+                        break
+                    }
+                    _ => {}
+                }
+                addr = addr.wrapping_add(len as u16);
+            }
+        }
+        return starts;
+    }
+
+    /// List the traced instructions, with the bytes between them as data.
+    fn render(&self, starts: &[bool]) -> String {
+        let mut out = String::new();
+        let read = |addr: u16| self.read(addr);
+        let mut offset = 0;
+        while offset < self.image.len() {
+            let addr = self.origin.wrapping_add(offset as u16);
+            if starts[offset] {
+                let line = instruction(&read, addr);
+                offset += line.bytes.len();
+                out.push_str(&format!("{}\n", line));
+                continue;
+            }
+            let mut end = offset + 1;
+            while end < self.image.len() && end % BYTES_PER_LINE != 0 && !starts[end] {
+                end += 1;
+            }
+            let data: Vec<String> = self.image[offset..end].iter().map(|b| format!("${:02X}", b)).collect();
+            out.push_str(&format!("{:04X}  {:<8}  .byte {}\n", addr, "", data.join(",")));
+            offset = end;
+        }
+        return out;
+    }
+}
+
+fn in_cartridge(addr: u16) -> bool {
+    return addr & 0x1000 != 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(bytes: &[u8]) -> String {
+        let read = |addr: u16| bytes.get(addr as usize - 0xF000).cloned().unwrap_or(0);
+        return instruction(&read, 0xF000).to_string();
+    }
+
+    #[test]
+    fn test_modes() {
+        assert_eq!(disassemble(&[0xEA]), "F000  EA        NOP");
+        assert_eq!(disassemble(&[0x0A]), "F000  0A        ASL A");
+        assert_eq!(disassemble(&[0xA9, 0x1F]), "F000  A9 1F     LDA #$1F");
+        assert_eq!(disassemble(&[0xA5, 0x80]), "F000  A5 80     LDA $80");
+        assert_eq!(disassemble(&[0xB5, 0x80]), "F000  B5 80     LDA $80,X");
+        assert_eq!(disassemble(&[0xB6, 0x80]), "F000  B6 80     LDX $80,Y");
+        assert_eq!(disassemble(&[0xAD, 0x34, 0xF2]), "F000  AD 34 F2  LDA $F234");
+        assert_eq!(disassemble(&[0xBD, 0x34, 0xF2]), "F000  BD 34 F2  LDA $F234,X");
+        assert_eq!(disassemble(&[0xB9, 0x34, 0xF2]), "F000  B9 34 F2  LDA $F234,Y");
+        assert_eq!(disassemble(&[0xA1, 0x80]), "F000  A1 80     LDA ($80,X)");
+        assert_eq!(disassemble(&[0xB1, 0x80]), "F000  B1 80     LDA ($80),Y");
+        assert_eq!(disassemble(&[0x6C, 0xFE, 0xFF]), "F000  6C FE FF  JMP ($FFFE)");
+        assert_eq!(disassemble(&[0xD0, 0xFE]), "F000  D0 FE     BNE $F000");
+        assert_eq!(disassemble(&[0x10, 0x10]), "F000  10 10     BPL $F012");
+        assert_eq!(disassemble(&[0x20, 0x00, 0x00]), "F000  20 00 00  JSR $0000");
+        assert_eq!(disassemble(&[0x02]), "F000  02        .byte $02");
+    }
+
+    #[test]
+    fn test_register_names() {
+        assert_eq!(disassemble(&[0x85, 0x02]), "F000  85 02     STA WSYNC");
+        assert_eq!(disassemble(&[0xA5, 0x02]), "F000  A5 02     LDA CXP0FB");
+        assert_eq!(disassemble(&[0x95, 0x10]), "F000  95 10     STA RESP0,X");
+        assert_eq!(disassemble(&[0x24, 0x3C]), "F000  24 3C     BIT INPT4");
+        assert_eq!(disassemble(&[0x8D, 0x96, 0x02]), "F000  8D 96 02  STA TIM64T");
+        assert_eq!(disassemble(&[0xAD, 0x84, 0x02]), "F000  AD 84 02  LDA INTIM");
+        assert_eq!(disassemble(&[0xAE, 0x80, 0x02]), "F000  AE 80 02  LDX SWCHA");
+    }
+
+    #[test]
+    fn test_listing_separates_code_and_data() {
+        let mut rom = vec![0xFF; 0x800];
+        let program = [
+            0x20, 0x07, 0xF8, // F800 JSR $F807
+            0x4C, 0x00, 0xF8, // F803 JMP $F800
+            0x12, // F806 data
+            0xB0, 0x01, // F807 BCS $F80A
+            0x60, // F809 RTS
+            0xBD, 0x06, 0xF8, // F80A LDA $F806,X
+            0x60, // F80D RTS
+        ];
+        rom[..program.len()].copy_from_slice(&program);
+        rom[0x7FC..].copy_from_slice(&[0x00, 0xF8, 0x07, 0xF8]);
+        let listing = listing(&rom);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[..10], [
+            "; bank 0: reset $F800, brk $F807",
+            "F800  20 07 F8  JSR $F807",
+            "F803  4C 00 F8  JMP $F800",
+            "F806            .byte $12",
+            "F807  B0 01     BCS $F80A",
+            "F809  60        RTS",
+            "F80A  BD 06 F8  LDA $F806,X",
+            "F80D  60        RTS",
+            "F80E            .byte $FF,$FF",
+            "F810            .byte $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF",
+        ]);
+        assert_eq!(lines.last(), Some(&"FFF8            .byte $FF,$FF,$FF,$FF,$00,$F8,$07,$F8"));
+    }
+
+    #[test]
+    fn test_listing_banks() {
+        let mut rom = vec![0xEA; 0x2000];
+        for bank in 0..2 {
+            // each bank loops on itself at its own origin
+            let base = bank * 0x1000;
+            let origin = if bank == 0 { 0xD0 } else { 0xF0 };
+            rom[base..base + 3].copy_from_slice(&[0x4C, 0x00, origin]);
+            rom[base + 0xFFC..base + 0x1000].copy_from_slice(&[0x00, origin, 0x00, origin]);
+        }
+        let listing = listing(&rom);
+        assert!(listing.contains("; bank 0: reset $D000, brk $D000\nD000  4C 00 D0  JMP $D000\nD003 "), "{}", listing);
+        assert!(listing.contains("; bank 1: reset $F000, brk $F000\nF000  4C 00 F0  JMP $F000\nF003 "), "{}", listing);
+    }
+}
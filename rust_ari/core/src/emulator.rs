@@ -0,0 +1,178 @@
+//! The emulation without a frontend around it: the machine run a frame at a
+//! time, with the TV standard detected from the frames it draws, each frame
+//! rendered to RGBA and the audio generated along the way kept until it's
+//! taken. Frontends that run the loop themselves, like a libretro one, only
+//! have to hand over input and show what comes out.
+
+use crate::audio;
+use crate::cartridge::Cartridge;
+use crate::error::Fault;
+use crate::machine::Machine;
+use crate::palette::{Adjustments, ColorTable};
+use crate::tia::CLOCKS_PER_LINE;
+use crate::tv::{Palette, TvStandard};
+
+/// Frames to run before trusting the scanline count for TV standard
+/// detection.
+pub const DETECT_AFTER_FRAMES: usize = 3;
+
+pub struct Emulator {
+    pub machine: Machine,
+    /// The standard given by the cartridge's properties or the frontend,
+    /// or `None` to detect it.
+    forced: Option<TvStandard>,
+    standard: TvStandard,
+    /// Colors to use rather than the standard's own.
+    palette: Option<Palette>,
+    colors: ColorTable,
+    /// The last frame run, RGBA `CLOCKS_PER_LINE` pixels wide.
+    frame: Vec<u8>,
+    /// Unsigned 8 bit mono samples generated since they were last taken.
+    audio: Vec<u8>,
+}
+
+impl Emulator {
+    /// Power on with `cartridge` inserted, in the TV standard its
+    /// properties give or else detecting it.
+    pub fn new(cartridge: Cartridge) -> Emulator {
+        let forced = TvStandard::from_format(cartridge.properties.format);
+        let standard = forced.unwrap_or(TvStandard::NTSC);
+        let mut emulator = Emulator {
+            machine: Machine::new(cartridge),
+            forced,
+            standard,
+            palette: None,
+            colors: ColorTable::new(standard.palette(), standard, &Adjustments::default()),
+            frame: Vec::new(),
+            audio: Vec::new(),
+        };
+        emulator.render();
+        return emulator;
+    }
+
+    pub fn standard(&self) -> TvStandard {
+        return self.standard;
+    }
+
+    /// Show frames in `standard`, or detect it for `None`.
+    pub fn set_standard(&mut self, standard: Option<TvStandard>) {
+        self.forced = standard;
+        if let Some(standard) = standard {
+            self.switch_standard(standard);
+        }
+    }
+
+    /// Draw with `palette`, or the standard's own for `None`.
+    pub fn set_palette(&mut self, palette: Option<Palette>) {
+        self.palette = palette;
+        self.switch_standard(self.standard);
+    }
+
+    /// Audio samples per second in the current standard.
+    pub fn sample_rate(&self) -> u32 {
+        return audio::sample_rate(self.standard);
+    }
+
+    /// Run the machine for a frame and render it. A fault stops it partway,
+    /// leaving the last frame as it was.
+    pub fn run_frame(&mut self) -> Result<(), Fault> {
+        let result = self.machine.run_frame();
+        self.audio.extend(self.machine.board.tia.audio.take_samples());
+        result?;
+
+        let tia = self.machine.tia();
+        if self.forced.is_none() && tia.frame_count > DETECT_AFTER_FRAMES {
+            self.switch_standard(TvStandard::detect(tia.frame_lines));
+        }
+        self.render();
+        return Ok(());
+    }
+
+    /// The last frame run, RGBA `CLOCKS_PER_LINE` pixels wide and as many
+    /// lines as the standard has.
+    pub fn frame(&self) -> &[u8] {
+        return &self.frame;
+    }
+
+    /// Hand over the audio generated since it was last taken.
+    pub fn take_audio(&mut self) -> Vec<u8> {
+        return std::mem::take(&mut self.audio);
+    }
+
+    fn switch_standard(&mut self, standard: TvStandard) {
+        self.standard = standard;
+        let palette = self.palette.as_ref().unwrap_or(standard.palette());
+        self.colors = ColorTable::new(palette, standard, &Adjustments::default());
+    }
+
+    fn render(&mut self) {
+        let tia = self.machine.tia();
+        self.frame.resize(CLOCKS_PER_LINE * self.standard.scanlines() * 4, 0);
+        self.colors.render(tia, self.standard.color_loss(tia.frame_lines), &mut self.frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::{DisplayFormat, Properties};
+    use crate::tia::VSYNC;
+
+    /// A 4K cartridge that draws frames of `lines` scanlines, each with one
+    /// line of VSYNC, forever.
+    fn frames_of(lines: u16) -> Cartridge {
+        let mut rom = vec![0xEA; 4096];
+        let blank = lines - 1;
+        let program = [
+            0xA9, 0x02, // LDA #2
+            0x85, VSYNC, // STA VSYNC
+            0x85, 0x02, // STA WSYNC
+            0xA9, 0x00, // LDA #0
+            0x85, VSYNC, // STA VSYNC
+            0xA2, (blank & 0xFF) as u8, // LDX #<blank
+            0xA0, (blank >> 8) as u8 + 1, // LDY #>blank + 1
+            0x85, 0x02, // loop: STA WSYNC
+            0xCA, // DEX
+            0xD0, 0xFB, // BNE loop
+            0x88, // DEY
+            0xD0, 0xF8, // BNE loop
+            0x4C, 0x00, 0xF0, // JMP $F000
+        ];
+        rom[..program.len()].copy_from_slice(&program);
+        rom[0xFFC] = 0x00;
+        rom[0xFFD] = 0xF0;
+        return Cartridge::from_rom(rom);
+    }
+
+    #[test]
+    fn test_detects_standard() {
+        let mut emulator = Emulator::new(frames_of(312));
+        assert_eq!(emulator.standard(), TvStandard::NTSC);
+        for _ in 0..DETECT_AFTER_FRAMES + 2 {
+            emulator.run_frame().unwrap();
+        }
+        assert_eq!(emulator.standard(), TvStandard::PAL);
+        assert_eq!(emulator.frame().len(), CLOCKS_PER_LINE * 312 * 4);
+        assert!(!emulator.take_audio().is_empty());
+        assert!(emulator.take_audio().is_empty());
+
+        // a standard given isn't second guessed
+        emulator.set_standard(Some(TvStandard::NTSC));
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.standard(), TvStandard::NTSC);
+        assert_eq!(emulator.frame().len(), CLOCKS_PER_LINE * 262 * 4);
+    }
+
+    #[test]
+    fn test_standard_from_properties() {
+        let mut properties = Properties::new("");
+        properties.format = DisplayFormat::SECAM;
+        let cartridge = Cartridge::new(frames_of(262).rom().to_vec(), properties);
+        let mut emulator = Emulator::new(cartridge);
+        for _ in 0..DETECT_AFTER_FRAMES + 2 {
+            emulator.run_frame().unwrap();
+        }
+        assert_eq!(emulator.standard(), TvStandard::SECAM);
+        assert_eq!(emulator.sample_rate(), audio::sample_rate(TvStandard::SECAM));
+    }
+}
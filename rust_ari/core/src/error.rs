@@ -0,0 +1,112 @@
+//! Errors from loading a cartridge and running the machine.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::cpu::Mode;
+use crate::properties::BankType;
+use crate::state::StateError;
+
+/// Something the CPU can't execute. It's left at the instruction, so a
+/// debugger can look at what led there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// An opcode the CPU doesn't implement.
+    Opcode { opcode: u8, pc: u16 },
+    /// An instruction used with an addressing mode it doesn't have.
+    AddressingMode { mode: Mode, pc: u16 },
+}
+
+impl Fault {
+    /// Where the faulting instruction is.
+    pub fn pc(&self) -> u16 {
+        return match *self {
+            Fault::Opcode { pc, .. } | Fault::AddressingMode { pc, .. } => pc,
+        };
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match *self {
+            Fault::Opcode { opcode, pc } => write!(f, "unimplemented opcode ${:02X} at ${:04X}", opcode, pc),
+            Fault::AddressingMode { mode, pc } => write!(f, "invalid addressing mode {} at ${:04X}", mode, pc),
+        };
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read.
+    Io(PathBuf, io::Error),
+    /// The ROM is smaller than its bank switching scheme needs.
+    RomSize { size: usize, bank_type: BankType },
+    /// The CPU couldn't go on.
+    Cpu(Fault),
+    State(StateError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            Error::RomSize { size: 0, .. } => write!(f, "the ROM is empty"),
+            Error::RomSize { size, bank_type } => write!(
+                f,
+                "a {} byte ROM is too small for bank type {}, which needs {}",
+                size,
+                bank_type,
+                bank_type.min_size()
+            ),
+            Error::Cpu(fault) => write!(f, "CPU fault: {}", fault),
+            Error::State(e) => write!(f, "{}", e),
+        };
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io(_, e) => Some(e),
+            Error::Cpu(fault) => Some(fault),
+            Error::State(e) => Some(e),
+            Error::RomSize { .. } => None,
+        };
+    }
+}
+
+impl From<Fault> for Error {
+    fn from(fault: Fault) -> Error {
+        return Error::Cpu(fault);
+    }
+}
+
+impl From<StateError> for Error {
+    fn from(e: StateError) -> Error {
+        return Error::State(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let fault = Fault::Opcode { opcode: 0x02, pc: 0xF012 };
+        assert_eq!(fault.to_string(), "unimplemented opcode $02 at $F012");
+        assert_eq!(Error::from(fault).to_string(), "CPU fault: unimplemented opcode $02 at $F012");
+        let mode = Fault::AddressingMode { mode: Mode::IMP, pc: 0x1000 };
+        assert_eq!(mode.to_string(), "invalid addressing mode IMP at $1000");
+        assert_eq!(mode.pc(), 0x1000);
+
+        let small = Error::RomSize { size: 4096, bank_type: BankType::F8 };
+        assert_eq!(small.to_string(), "a 4096 byte ROM is too small for bank type F8, which needs 8192");
+        assert_eq!(Error::RomSize { size: 0, bank_type: BankType::K4 }.to_string(), "the ROM is empty");
+        let missing = Error::Io(PathBuf::from("x.a26"), io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(missing.to_string(), "failed to read x.a26: not found");
+    }
+}
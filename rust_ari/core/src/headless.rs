@@ -0,0 +1,221 @@
+//! Running a cartridge without a window or GPU, for CI and automated tests.
+
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{self, Write};
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+use crate::audio;
+#[cfg(feature = "fs")]
+use crate::capture;
+use crate::error::Fault;
+use crate::machine::Machine;
+use crate::palette::{Adjustments, ColorTable};
+use crate::tia::CLOCKS_PER_LINE;
+use crate::tv::{Palette, TvStandard};
+
+/// What a headless run leaves behind: the last frame, all audio generated
+/// along the way and the contents of RAM.
+pub struct Output {
+    pub standard: TvStandard,
+    pub width: usize,
+    pub height: usize,
+    /// RGBA, `width * height * 4` bytes.
+    pub frame: Vec<u8>,
+    /// Unsigned 8 bit mono samples at `sample_rate()`.
+    pub audio: Vec<u8>,
+    pub ram: Vec<u8>,
+    rate: u32,
+}
+
+/// Run `frames` frames and collect the results. The TV standard is detected
+/// from the last frame unless one is given. A CPU fault ends the run.
+pub fn run(machine: &mut Machine, frames: usize, standard: Option<TvStandard>) -> Result<Output, Fault> {
+    return run_with(machine, frames, standard, None, &mut Machine::run_frame);
+}
+
+/// Like `run`, but with each frame run by `run_frame`, as when recording or
+/// playing back a movie, and the last frame drawn in `palettes`, one for
+/// each standard, if given rather than the built-in palettes.
+pub fn run_with(
+    machine: &mut Machine,
+    frames: usize,
+    standard: Option<TvStandard>,
+    palettes: Option<&[Palette; 3]>,
+    run_frame: &mut dyn FnMut(&mut Machine) -> Result<(), Fault>,
+) -> Result<Output, Fault> {
+    let mut audio = Vec::new();
+    for _ in 0..frames {
+        run_frame(machine)?;
+        audio.extend(machine.board.tia.audio.take_samples());
+    }
+
+    let lines = machine.tia().frame_lines;
+    let standard = standard.unwrap_or(TvStandard::detect(lines));
+    let palette = palettes.map_or(standard.palette(), |palettes| &palettes[standard as usize]);
+    let colors = ColorTable::new(palette, standard, &Adjustments::default());
+    let width = CLOCKS_PER_LINE;
+    let height = standard.scanlines();
+    let mut frame = vec![0; width * height * 4];
+    colors.render(machine.tia(), standard.color_loss(lines), &mut frame);
+
+    return Ok(Output {
+        standard,
+        width,
+        height,
+        frame,
+        audio,
+        ram: machine.ram().to_vec(),
+        rate: audio::sample_rate(standard),
+    });
+}
+
+impl Output {
+    /// Audio samples per second: that of the standard the frames ran at,
+    /// unless resampled.
+    pub fn sample_rate(&self) -> u32 {
+        return self.rate;
+    }
+
+    /// Convert the audio to `rate` samples per second.
+    pub fn resample(&mut self, rate: u32) {
+        self.audio = audio::Resampler::new(self.rate, rate).resample(&self.audio);
+        self.rate = rate;
+    }
+
+    /// Write the frame as a binary PPM.
+    #[cfg(feature = "fs")]
+    pub fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let rgb: Vec<u8> = self.frame.chunks_exact(4).flat_map(|p| p[..3].to_vec()).collect();
+        file.write_all(&rgb)?;
+        return Ok(());
+    }
+
+    /// Write the audio as an 8 bit mono WAV.
+    #[cfg(feature = "fs")]
+    pub fn write_wav(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&capture::wav_header(self.sample_rate(), self.audio.len() as u32))?;
+        file.write_all(&self.audio)?;
+        return Ok(());
+    }
+
+    /// Write the frame, audio and RAM next to each other as `<prefix>.ppm`,
+    /// `<prefix>.wav` and `<prefix>.ram`, returning the paths written.
+    #[cfg(feature = "fs")]
+    pub fn write_files(&self, prefix: &Path) -> io::Result<Vec<PathBuf>> {
+        let ppm = prefix.with_extension("ppm");
+        let wav = prefix.with_extension("wav");
+        let ram = prefix.with_extension("ram");
+        self.write_ppm(&ppm)?;
+        self.write_wav(&wav)?;
+        std::fs::write(&ram, &self.ram)?;
+        return Ok(vec![ppm, wav, ram]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    /// A kernel that draws 259-line frames with a blue background.
+    fn test_machine() -> Machine {
+        let program: [u8; 33] = [
+            0x78,             // SEI
+            0xD8,             // CLD
+            0xA2, 0xFF,       // LDX #$FF
+            0x9A,             // TXS
+            0xA9, 0x02,       // frame: LDA #2
+            0x85, 0x00,       // STA VSYNC
+            0x85, 0x02,       // STA WSYNC
+            0x85, 0x02,       // STA WSYNC
+            0x85, 0x02,       // STA WSYNC
+            0xA9, 0x00,       // LDA #0
+            0x85, 0x00,       // STA VSYNC
+            0xA9, 0x84,       // LDA #$84
+            0x85, 0x09,       // STA COLUBK
+            0xA2, 0x00,       // LDX #0
+            0x85, 0x02,       // line: STA WSYNC
+            0xCA,             // DEX
+            0xD0, 0xFB,       // BNE line
+            0x4C, 0x05, 0xF0, // JMP frame
+        ];
+        let mut rom = vec![0; 0x1000];
+        rom[..program.len()].copy_from_slice(&program);
+        rom[0xFFD] = 0xF0;
+        return Machine::new(Cartridge::from_rom(rom));
+    }
+
+    #[test]
+    fn test_run_collects_frame_audio_and_ram() {
+        let mut machine = test_machine();
+        let output = run(&mut machine, 5, None).unwrap();
+        assert_eq!(output.standard, TvStandard::NTSC);
+        assert_eq!(machine.tia().frame_lines, 259);
+        assert_eq!(output.width, CLOCKS_PER_LINE);
+        assert_eq!(output.height, 262);
+        assert_eq!(output.frame.len(), CLOCKS_PER_LINE * 262 * 4);
+        assert_eq!(output.ram.len(), 128);
+        // four full frames of audio clocks, plus the lines before the first VSYNC
+        assert!(output.audio.len() >= 4 * 259 * audio::SAMPLES_PER_LINE);
+
+        let colors = ColorTable::new(TvStandard::NTSC.palette(), TvStandard::NTSC, &Adjustments::default());
+        let at = (100 * CLOCKS_PER_LINE + 100) * 4;
+        assert_eq!(&output.frame[at..at + 4], &colors.rgba(0x84, false));
+    }
+
+    #[test]
+    fn test_custom_palette() {
+        let mut machine = test_machine();
+        let mut palettes = [*TvStandard::NTSC.palette(); 3];
+        palettes[TvStandard::NTSC as usize][0x84 >> 1] = [1, 2, 3];
+        let output = run_with(&mut machine, 2, None, Some(&palettes), &mut Machine::run_frame).unwrap();
+        let at = (100 * CLOCKS_PER_LINE + 100) * 4;
+        assert_eq!(&output.frame[at..at + 4], &[1, 2, 3, 0xFF]);
+    }
+
+    #[test]
+    fn test_forced_standard() {
+        let mut machine = test_machine();
+        let output = run(&mut machine, 2, Some(TvStandard::PAL)).unwrap();
+        assert_eq!(output.standard, TvStandard::PAL);
+        assert_eq!(output.height, 312);
+        assert_eq!(output.sample_rate(), 31113);
+    }
+
+    #[test]
+    fn test_resample() {
+        let mut machine = test_machine();
+        let mut output = run(&mut machine, 2, None).unwrap();
+        let samples = output.audio.len();
+        output.resample(output.sample_rate() / 2);
+        assert_eq!(output.sample_rate(), 15699);
+        assert_eq!(output.audio.len(), samples.div_ceil(2));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_write_files() {
+        let mut machine = test_machine();
+        let output = run(&mut machine, 2, None).unwrap();
+        let prefix = std::env::temp_dir().join(format!("rustari-headless-{}", std::process::id()));
+        let paths = output.write_files(&prefix).unwrap();
+
+        let ppm = std::fs::read(&paths[0]).unwrap();
+        assert!(ppm.starts_with(b"P6\n228 262\n255\n"));
+        assert_eq!(ppm.len(), 15 + 228 * 262 * 3);
+        let wav = std::fs::read(&paths[1]).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav.len(), 44 + output.audio.len());
+        assert_eq!(std::fs::read(&paths[2]).unwrap().len(), 128);
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,39 @@
+extern crate strum;
+#[macro_use]
+extern crate strum_macros;
+
+pub mod alu;
+pub mod audio;
+pub mod bus;
+#[cfg(feature = "fs")]
+pub mod capture;
+pub mod cartridge;
+pub mod cpu;
+pub mod config;
+pub mod crt;
+pub mod debugger;
+pub mod disasm;
+pub mod emulator;
+pub mod error;
+pub mod headless;
+pub mod machine;
+pub mod movie;
+pub mod pacing;
+pub mod palette;
+pub mod properties;
+pub mod rewind;
+pub mod riot;
+pub mod rom_read;
+pub mod state;
+pub mod symbols;
+pub mod tia;
+pub mod trace;
+pub mod tv;
+pub mod viewport;
+
+pub use crate::cartridge::Cartridge;
+pub use crate::cpu::Cpu;
+pub use crate::error::Error;
+pub use crate::machine::Machine;
+pub use crate::riot::Riot;
+pub use crate::tia::Tia;
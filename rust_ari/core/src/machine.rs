@@ -0,0 +1,590 @@
+//! The whole console: CPU, TIA, RIOT and cartridge on one bus, kept in step.
+
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::cpu::Cpu;
+use crate::error::Fault;
+use crate::properties::Difficulty;
+use crate::riot::{self, Joystick, Riot};
+use crate::state::{Reader, Snapshot, StateError, Writer};
+use crate::tia::Tia;
+use crate::trace::{self, Tracer};
+
+/// Color clocks per CPU cycle.
+pub const CLOCKS_PER_CYCLE: usize = 3;
+
+/// Mirror an address in the TIA's range down to its register number.
+fn translate_for_tia(mut addr: u16) -> u16 {
+    addr &= 0b0001_0000_1011_1111;
+    return addr;
+}
+
+/// What an address selects. Only 13 address lines are decoded: A12 selects
+/// the cartridge, A7 the RIOT over the TIA and A9 the RIOT's I/O and timer
+/// over its RAM. Everything else is mirrored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// Offset into the 4K cartridge window.
+    Cartridge(u16),
+    /// TIA register. Reads only decode the low four bits.
+    Tia(u8),
+    /// Index into the RIOT's 128 bytes of RAM.
+    Ram(u8),
+    /// RIOT I/O or timer register.
+    Riot(u8),
+}
+
+/// Decode an access to `addr`.
+pub fn decode(addr: u16, write: bool) -> Target {
+    if addr & 0x1000 != 0 {
+        return Target::Cartridge(addr & 0x0FFF);
+    } else if addr & 0x0080 == 0 {
+        let reg = translate_for_tia(addr) as u8;
+        return Target::Tia(if write { reg } else { reg & 0x0F });
+    } else if addr & 0x0200 == 0 {
+        return Target::Ram((addr & 0x7F) as u8);
+    }
+    return Target::Riot((addr & 0x1F) as u8);
+}
+
+/// Everything on the CPU's side of the address bus.
+///
+/// The 6502 makes one bus access every cycle, so each access also clocks
+/// the TIA and RIOT through that cycle, after the access itself.
+#[derive(Clone)]
+pub struct Board {
+    pub cartridge: Cartridge,
+    pub tia: Tia,
+    pub riot: Riot,
+    /// CPU cycles the TIA and RIOT have been clocked for.
+    pub cycles: usize,
+}
+
+impl Board {
+    fn cycle(&mut self) {
+        for _ in 0..CLOCKS_PER_CYCLE {
+            self.tia.clock();
+        }
+        self.riot.tick();
+        self.cycles += 1;
+    }
+
+    /// Read what the CPU would see at `addr` without clocking anything or
+    /// switching banks.
+    pub fn peek(&self, addr: u16) -> u8 {
+        return match decode(addr, false) {
+            Target::Cartridge(_) => self.cartridge.peek(addr),
+            Target::Tia(reg) => self.tia.read(reg),
+            Target::Ram(index) => self.riot.ram[index as usize],
+            Target::Riot(reg) => self.riot.read(reg),
+        };
+    }
+}
+
+impl Snapshot for Board {
+    fn save(&self, out: &mut Writer) {
+        self.cartridge.save(out);
+        self.tia.save(out);
+        self.riot.save(out);
+        out.usize(self.cycles);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        self.cartridge.load(input)?;
+        self.tia.load(input)?;
+        self.riot.load(input)?;
+        self.cycles = input.usize()?;
+        return Ok(());
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, addr: u16) -> u8 {
+        // WSYNC pulls RDY low, which holds the CPU on its next read until
+        // the TIA reaches the end of the line
+        while self.tia.wsync {
+            self.cycle();
+        }
+        let val = match decode(addr, false) {
+            Target::Cartridge(_) => self.cartridge.read(addr),
+            Target::Tia(reg) => self.tia.read(reg),
+            Target::Ram(index) => self.riot.ram[index as usize],
+            Target::Riot(reg) => self.riot.read(reg),
+        };
+        self.cartridge.snoop(addr, val, false);
+        self.cycle();
+        return val;
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match decode(addr, true) {
+            Target::Cartridge(_) => self.cartridge.write(addr, val),
+            Target::Tia(reg) => {
+                self.tia.write(reg, val);
+            }
+            Target::Ram(index) => self.riot.ram[index as usize] = val,
+            Target::Riot(reg) => self.riot.write(reg, val),
+        }
+        self.cartridge.snoop(addr, val, true);
+        self.cycle();
+    }
+}
+
+/// The board as the CPU sees it part way through an instruction that
+/// `step_cycle` is stepping through. The CPU runs the instruction again from
+/// its start each cycle: the accesses it made on earlier cycles are replayed
+/// from `made` without reaching the board, accesses up to `limit` reach it,
+/// and after that reads see nothing and writes are dropped.
+struct Replay<'a> {
+    board: &'a mut Board,
+    /// The value each access so far read or wrote.
+    made: &'a mut Vec<u8>,
+    limit: usize,
+    /// Accesses made this time through.
+    count: usize,
+    cut: bool,
+}
+
+impl Replay<'_> {
+    /// Replay the next access, make it on the board with `live`, or cut it
+    /// off.
+    fn access(&mut self, live: impl FnOnce(&mut Board) -> u8) -> u8 {
+        let index = self.count;
+        self.count += 1;
+        if index < self.made.len() {
+            return self.made[index];
+        }
+        if index < self.limit {
+            let val = live(self.board);
+            self.made.push(val);
+            return val;
+        }
+        self.cut = true;
+        return 0;
+    }
+}
+
+impl Bus for Replay<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        return self.access(|board| board.read(addr));
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.access(|board| {
+            board.write(addr, val);
+            val
+        });
+    }
+}
+
+/// An instruction `step_cycle` is part way through: the board as it was
+/// before it, for `save_state`, and the accesses it has made so far.
+struct Stepping {
+    board: Board,
+    made: Vec<u8>,
+}
+
+/// An Atari 2600 with a cartridge inserted.
+///
+/// The CPU drives time: the rest of the board is clocked by its bus
+/// accesses, so TIA and RIOT registers are written on the exact cycle the
+/// CPU writes them.
+pub struct Machine {
+    pub cpu: Cpu,
+    pub board: Board,
+    /// Logs each instruction it selects before it executes; see `trace`.
+    pub tracer: Option<Tracer>,
+    stepping: Option<Stepping>,
+}
+
+impl Machine {
+    /// Power on with `cartridge` inserted, setting the difficulty switches
+    /// from its properties.
+    pub fn new(cartridge: Cartridge) -> Machine {
+        let mut riot = Riot::new();
+        let properties = &cartridge.properties;
+        riot.set_switch(riot::SWITCH_LEFT_DIFFICULTY, properties.left_difficulty == Difficulty::A);
+        riot.set_switch(riot::SWITCH_RIGHT_DIFFICULTY, properties.right_difficulty == Difficulty::A);
+        let mut machine = Machine {
+            cpu: Cpu::new(),
+            board: Board {
+                cartridge,
+                tia: Tia::new(),
+                riot,
+                cycles: 0,
+            },
+            tracer: None,
+            stepping: None,
+        };
+        machine.cpu.reset(&mut machine.board);
+        return machine;
+    }
+
+    /// Reset the CPU and cartridge banks, as if the console had been power
+    /// cycled with the same RAM and TIA state.
+    pub fn reset(&mut self) {
+        self.stepping = None;
+        self.board.cartridge.reset();
+        self.cpu.reset(&mut self.board);
+    }
+
+    /// Snapshot the whole machine as a save state; see `state`. Part way
+    /// through an instruction from `step_cycle` it is saved from the start
+    /// of that instruction.
+    pub fn save_state(&self) -> Vec<u8> {
+        let board = match &self.stepping {
+            Some(stepping) => &stepping.board,
+            None => &self.board,
+        };
+        let mut out = Writer::new(board.cartridge.rom());
+        self.cpu.save(&mut out);
+        board.save(&mut out);
+        return out.into_bytes();
+    }
+
+    /// Restore a state from `save_state`. If it can't be loaded the machine
+    /// is left as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut input = Reader::new(state, self.board.cartridge.rom())?;
+        let mut cpu = self.cpu.clone();
+        let mut board = self.board.clone();
+        cpu.load(&mut input)?;
+        board.load(&mut input)?;
+        input.finish()?;
+        self.cpu = cpu;
+        self.board = board;
+        self.stepping = None;
+        return Ok(());
+    }
+
+    /// Advance by one CPU cycle. The CPU executes instructions whole, so
+    /// one is stepped through by running it again from its start each
+    /// cycle, replaying the bus accesses of the cycles before and letting
+    /// one more reach the board. The board is saved once, at the start of
+    /// the instruction, for `save_state`. The CPU's registers stay as they
+    /// were before the instruction until its last cycle, and a cycle the
+    /// CPU is halted by WSYNC at the start of an instruction only clocks
+    /// the TIA and RIOT. Returns true when the cycle finished an
+    /// instruction.
+    pub fn step_cycle(&mut self) -> Result<bool, Fault> {
+        let mut stepping = match self.stepping.take() {
+            Some(stepping) => stepping,
+            None => {
+                if self.board.tia.wsync {
+                    self.board.cycle();
+                    return Ok(false);
+                }
+                trace::record(self);
+                Stepping {
+                    board: self.board.clone(),
+                    made: Vec::new(),
+                }
+            }
+        };
+        let limit = stepping.made.len() + 1;
+        if self.finish(&mut stepping.made, limit)? {
+            return Ok(true);
+        }
+        self.stepping = Some(stepping);
+        return Ok(false);
+    }
+
+    /// Execute one instruction, along with any cycles the CPU spends halted
+    /// by WSYNC before it. Part way through one from `step_cycle`, the rest
+    /// of that instruction is run.
+    pub fn step_instruction(&mut self) -> Result<(), Fault> {
+        if let Some(mut stepping) = self.stepping.take() {
+            self.finish(&mut stepping.made, usize::MAX)?;
+            return Ok(());
+        }
+        trace::record(self);
+        return self.cpu.execute_step(&mut self.board);
+    }
+
+    /// Run the instruction at the CPU from its start, replaying the
+    /// accesses in `made` and making the rest up to `limit` in all on the
+    /// board. The CPU only moves on if the instruction was finished, which
+    /// it returns.
+    fn finish(&mut self, made: &mut Vec<u8>, limit: usize) -> Result<bool, Fault> {
+        let mut cpu = self.cpu.clone();
+        let mut bus = Replay {
+            board: &mut self.board,
+            made,
+            limit,
+            count: 0,
+            cut: false,
+        };
+        cpu.execute_step(&mut bus)?;
+        if bus.cut {
+            return Ok(false);
+        }
+        self.cpu = cpu;
+        return Ok(true);
+    }
+
+    /// Run until the TIA completes a frame. The frame ends during the last
+    /// instruction executed. A fault stops it partway, with the CPU at the
+    /// faulting instruction.
+    pub fn run_frame(&mut self) -> Result<(), Fault> {
+        let frame_count = self.board.tia.frame_count;
+        while self.board.tia.frame_count == frame_count {
+            self.step_instruction()?;
+        }
+        return Ok(());
+    }
+
+    /// The 128 bytes of RIOT RAM at 0x80-0xFF.
+    pub fn ram(&self) -> &[u8] {
+        return &self.board.riot.ram;
+    }
+
+    pub fn tia(&self) -> &Tia {
+        return &self.board.tia;
+    }
+
+    /// Move a joystick. Player 0 is the left port unless the cartridge's
+    /// properties swap them.
+    pub fn set_joystick(&mut self, player: usize, joystick: Joystick) {
+        let port = self.port(player);
+        self.board.riot.set_joystick(port, joystick);
+    }
+
+    pub fn set_fire(&mut self, player: usize, pressed: bool) {
+        let port = self.port(player);
+        self.board.tia.set_fire(port, pressed);
+    }
+
+    fn port(&self, player: usize) -> usize {
+        if self.board.cartridge.properties.swap_ports {
+            return 1 - player;
+        }
+        return player;
+    }
+
+    /// Set the console's Color/B&W switch, read by games through SWCHB.
+    pub fn set_color_switch(&mut self, color: bool) {
+        self.board.riot.set_switch(riot::SWITCH_COLOR, color);
+    }
+
+    pub fn set_reset_switch(&mut self, pressed: bool) {
+        self.board.riot.set_switch(riot::SWITCH_RESET, !pressed);
+    }
+
+    pub fn set_select_switch(&mut self, pressed: bool) {
+        self.board.riot.set_switch(riot::SWITCH_SELECT, !pressed);
+    }
+
+    pub fn set_difficulty(&mut self, player: usize, difficulty: Difficulty) {
+        let switch = if player == 0 {
+            riot::SWITCH_LEFT_DIFFICULTY
+        } else {
+            riot::SWITCH_RIGHT_DIFFICULTY
+        };
+        self.board.riot.set_switch(switch, difficulty == Difficulty::A);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::Properties;
+
+    /// A 4K cartridge holding `program` at 0xF000 with the reset vector
+    /// pointing at it.
+    fn machine(program: &[u8]) -> Machine {
+        let mut rom = vec![0; 0x1000];
+        rom[..program.len()].copy_from_slice(program);
+        rom[0xFFD] = 0xF0;
+        return Machine::new(Cartridge::new(rom, Properties::new("")));
+    }
+
+    #[test]
+    fn test_translate_for_tia() {
+        let result = translate_for_tia(0xEF3F);
+        assert_eq!(result, 0x3F);
+    }
+
+    #[test]
+    fn test_reset_vector() {
+        let machine = machine(&[]);
+        assert_eq!(machine.cpu.pc, 0xF000);
+        assert_eq!(machine.cpu.s_pnt, 0xFD);
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut machine = machine(&[]);
+        let board = &mut machine.board;
+        // RAM is mirrored into page one, where the stack lives
+        board.write(0x0080, 0x12);
+        assert_eq!(board.read(0x0180), 0x12);
+        assert_eq!(machine.ram()[0], 0x12);
+        let board = &mut machine.board;
+        // the timer counts down once in the cycle the write takes
+        board.write(0x0296, 0x10);
+        assert_eq!(board.read(0x0284), 0x0F);
+        board.tia.set_fire(0, true);
+        assert_eq!(board.read(0x003C), 0);
+        assert_eq!(board.read(0xF000), 0);
+    }
+
+    #[test]
+    fn test_decode_mirrors() {
+        assert_eq!(decode(0x0049, true), Target::Tia(0x09));
+        assert_eq!(decode(0x0049, false), Target::Tia(0x09));
+        assert_eq!(decode(0x003C, false), Target::Tia(0x0C));
+        assert_eq!(decode(0x0180, false), Target::Ram(0));
+        assert_eq!(decode(0x0284, false), Target::Riot(0x04));
+        assert_eq!(decode(0xF123, false), Target::Cartridge(0x123));
+        assert_eq!(decode(0x1123, false), Target::Cartridge(0x123));
+    }
+
+    #[test]
+    fn test_wsync_halts_cpu() {
+        // STA WSYNC; LDA #7; JMP $F000
+        let mut machine = machine(&[0x85, 0x02, 0xA9, 0x07, 0x4C, 0x00, 0xF0]);
+        machine.step_instruction().unwrap();
+        assert_eq!(machine.cpu.pc, 0xF002);
+        assert_eq!(machine.board.tia.wsync, true);
+        machine.step_instruction().unwrap();
+        assert_eq!(machine.cpu.a_reg, 7);
+        assert_eq!(machine.board.tia.scanline, 1);
+        assert_eq!(machine.board.tia.clock, 2 * CLOCKS_PER_CYCLE);
+    }
+
+    #[test]
+    fn test_step_cycle() {
+        // LDA #7; STA $80; STA WSYNC; INX
+        let mut machine = machine(&[0xA9, 0x07, 0x85, 0x80, 0x85, 0x02, 0xE8]);
+        let start = machine.board.cycles;
+        assert_eq!(machine.step_cycle(), Ok(false));
+        assert_eq!(machine.cpu.a_reg, 0);
+        assert_eq!(machine.step_cycle(), Ok(true));
+        assert_eq!(machine.cpu.a_reg, 7);
+        // the store lands on the third cycle of STA, not before
+        assert_eq!(machine.step_cycle(), Ok(false));
+        assert_eq!(machine.step_cycle(), Ok(false));
+        assert_eq!(machine.board.cycles, start + 4);
+        assert_eq!(machine.ram()[0], 0);
+        assert_eq!(machine.cpu.pc, 0xF002);
+        assert_eq!(machine.step_cycle(), Ok(true));
+        assert_eq!(machine.ram()[0], 7);
+        assert_eq!(machine.cpu.pc, 0xF004);
+        assert_eq!(machine.board.cycles, start + 5);
+
+        // halted by WSYNC the board still moves a cycle at a time
+        for _ in 0..3 {
+            machine.step_cycle().unwrap();
+        }
+        assert_eq!(machine.board.tia.wsync, true);
+        let halted = machine.board.cycles;
+        assert_eq!(machine.step_cycle(), Ok(false));
+        assert_eq!(machine.board.cycles, halted + 1);
+        assert_eq!(machine.cpu.pc, 0xF006);
+
+        // an instruction stepped into is finished from where it got to
+        while machine.board.tia.wsync {
+            machine.step_cycle().unwrap();
+        }
+        let before = machine.board.cycles;
+        assert_eq!(machine.step_cycle(), Ok(false));
+        machine.step_instruction().unwrap();
+        assert_eq!(machine.cpu.x_reg, 1);
+        assert_eq!(machine.cpu.pc, 0xF007);
+        assert_eq!(machine.board.cycles, before + 2);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut machine = machine(&[
+            0xA9, 0x02, // LDA #2
+            0x85, 0x00, // STA VSYNC
+            0x85, 0x02, // STA WSYNC
+            0xA9, 0x00, // LDA #0
+            0x85, 0x00, // STA VSYNC
+            0xA2, 0x64, // LDX #100
+            0x85, 0x02, // STA WSYNC
+            0xCA, //       DEX
+            0xD0, 0xFB, // BNE -5
+            0x4C, 0x00, 0xF0, // JMP $F000
+        ]);
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.board.tia.frame_lines, 101);
+        assert_eq!(machine.board.tia.frame_count, 2);
+    }
+
+    #[test]
+    fn test_fault_stops_frame() {
+        // INX; a JAM opcode
+        let mut machine = machine(&[0xE8, 0x02]);
+        assert_eq!(machine.run_frame(), Err(Fault::Opcode { opcode: 0x02, pc: 0xF001 }));
+        assert_eq!(machine.cpu.pc, 0xF001);
+        assert_eq!(machine.cpu.x_reg, 1);
+    }
+
+    #[test]
+    fn test_swapped_ports() {
+        let mut properties = Properties::new("");
+        properties.swap_ports = true;
+        let mut machine = Machine::new(Cartridge::new(vec![0; 0x1000], properties));
+        machine.set_joystick(0, Joystick { up: true, ..Joystick::default() });
+        assert_eq!(machine.board.riot.read(riot::SWCHA), 0b1111_1110);
+    }
+
+    #[test]
+    fn test_save_state_is_deterministic() {
+        let program = [
+            0xA9, 0x02, //       LDA #2
+            0x85, 0x00, //       STA VSYNC
+            0x85, 0x02, //       STA WSYNC
+            0xA9, 0x00, //       LDA #0
+            0x85, 0x00, //       STA VSYNC
+            0xA2, 0x64, //       LDX #100
+            0x86, 0x09, // loop: STX COLUBK
+            0x85, 0x02, //       STA WSYNC
+            0xE6, 0x80, //       INC $80
+            0xCA, //             DEX
+            0xD0, 0xF7, //       BNE loop
+            0xA5, 0x80, //       LDA $80
+            0x85, 0x19, //       STA AUDV0
+            0x8D, 0x96, 0x02, // STA TIM64T
+            0x4C, 0x00, 0xF0, // JMP $F000
+        ];
+        let mut machine = machine(&program);
+        machine.run_frame().unwrap();
+        for _ in 0..1234 {
+            machine.step_instruction().unwrap();
+        }
+        let state = machine.save_state();
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        let expected = machine.save_state();
+        assert_ne!(expected, state);
+
+        let mut restored = self::machine(&program);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        restored.run_frame().unwrap();
+        restored.run_frame().unwrap();
+        assert_eq!(restored.save_state(), expected);
+        assert_eq!(restored.tia().pixel(100, 50), machine.tia().pixel(100, 50));
+    }
+
+    #[test]
+    fn test_bad_state_leaves_machine_alone() {
+        let mut machine = machine(&[0xE8, 0x4C, 0x00, 0xF0]);
+        let state = machine.save_state();
+        machine.run_frame().unwrap();
+        let before = machine.save_state();
+        assert_eq!(machine.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+        assert_eq!(machine.save_state(), before);
+        let mut longer = state.clone();
+        longer.push(0);
+        assert_eq!(machine.load_state(&longer), Err(StateError::Invalid("length")));
+        let mut other = self::machine(&[0xEA]);
+        assert_eq!(other.load_state(&state), Err(StateError::WrongCartridge));
+        assert_eq!(machine.load_state(&state), Ok(()));
+        assert_eq!(machine.cpu.pc, 0xF000);
+    }
+}
@@ -0,0 +1,413 @@
+//! Input movies: the joystick and console switch input of every frame,
+//! recorded from power on or from a save state, for replaying a session
+//! exactly in regression tests and bug reports.
+//!
+//! Each frame also records an MD5 of the TIA's frame buffer after it ran.
+//! Playback feeds the input back in and checks the frames come out the
+//! same, reporting the first one that doesn't.
+//!
+//! A movie file starts with `MAGIC`, the format `VERSION` and the MD5 of
+//! the cartridge ROM. Then a byte says how it starts: 0 for power on, or 1
+//! followed by the length of a save state as a u32 and the state itself.
+//! Last comes the number of frames as a u32 and, for each frame, SWCHA,
+//! SWCHB, the fire buttons in bits 0 and 1 and the frame's hash. Integers
+//! are little endian.
+
+use std::fmt;
+
+use crate::cartridge::Cartridge;
+use crate::error::Fault;
+use crate::machine::Machine;
+use crate::riot::SWCHA;
+
+pub const MAGIC: &[u8; 4] = b"R26M";
+
+/// Bumped whenever the layout changes; older movies are rejected.
+pub const VERSION: u16 = 1;
+
+/// The input a frame ran with, as the levels on the console's ports. The
+/// joystick bits are those of the ports rather than the players, so a
+/// movie replays the same whatever the cartridge's properties say.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Input {
+    pub swcha: u8,
+    pub swchb: u8,
+    pub fire: [bool; 2],
+}
+
+impl Input {
+    /// The input `machine` has been given.
+    pub fn capture(machine: &Machine) -> Input {
+        let tia = machine.tia();
+        return Input {
+            swcha: machine.board.riot.read(SWCHA),
+            swchb: machine.board.riot.swchb(),
+            fire: [tia.fire(0), tia.fire(1)],
+        };
+    }
+
+    pub fn apply(&self, machine: &mut Machine) {
+        let riot = &mut machine.board.riot;
+        riot.set_swcha(self.swcha);
+        riot.set_switch(self.swchb, true);
+        riot.set_switch(!self.swchb, false);
+        for (port, &pressed) in self.fire.iter().enumerate() {
+            machine.board.tia.set_fire(port, pressed);
+        }
+    }
+}
+
+/// Where a movie starts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Start {
+    PowerOn,
+    /// A save state from `Machine::save_state`.
+    State(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Frame {
+    input: Input,
+    hash: [u8; 16],
+}
+
+/// The first frame of a playback that didn't match the recording.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Divergence {
+    /// Counted from 0, the first frame of the movie.
+    pub frame: usize,
+    pub expected: [u8; 16],
+    pub actual: [u8; 16],
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "movie diverged at frame {}: frame hash {} instead of {}",
+            self.frame,
+            hex(&self.actual),
+            hex(&self.expected)
+        );
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+#[derive(Clone)]
+pub struct Movie {
+    pub start: Start,
+    /// MD5 of the cartridge ROM the movie was recorded with.
+    rom: [u8; 16],
+    frames: Vec<Frame>,
+}
+
+impl Movie {
+    /// Start recording on a machine that has just been powered on.
+    pub fn from_power_on(machine: &Machine) -> Movie {
+        return Movie::new(machine, Start::PowerOn);
+    }
+
+    /// Start recording from the current state of `machine`.
+    pub fn from_state(machine: &Machine) -> Movie {
+        return Movie::new(machine, Start::State(machine.save_state()));
+    }
+
+    fn new(machine: &Machine, start: Start) -> Movie {
+        return Movie {
+            start,
+            rom: md5::compute(machine.board.cartridge.rom()).0,
+            frames: Vec::new(),
+        };
+    }
+
+    /// The number of frames recorded.
+    pub fn len(&self) -> usize {
+        return self.frames.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.frames.is_empty();
+    }
+
+    /// Run a frame of `machine` with the input it has been given, and
+    /// record both. A frame cut short by a fault isn't recorded.
+    pub fn record_frame(&mut self, machine: &mut Machine) -> Result<(), Fault> {
+        let input = Input::capture(machine);
+        machine.run_frame()?;
+        self.frames.push(Frame { input, hash: frame_hash(machine) });
+        return Ok(());
+    }
+
+    /// Put `machine` where the movie starts: powered on afresh, or in the
+    /// save state it was recorded from.
+    pub fn restart(&self, machine: &mut Machine) -> Result<(), String> {
+        if md5::compute(machine.board.cartridge.rom()).0 != self.rom {
+            return Err("the movie was recorded with a different cartridge".to_string());
+        }
+        match &self.start {
+            Start::PowerOn => {
+                let cartridge = &machine.board.cartridge;
+                let cartridge = Cartridge::new(cartridge.rom().to_vec(), cartridge.properties.clone());
+                let tracer = machine.tracer.take();
+                *machine = Machine::new(cartridge);
+                machine.tracer = tracer;
+            }
+            Start::State(state) => machine.load_state(state).map_err(|e| e.to_string())?,
+        }
+        return Ok(());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom);
+        match &self.start {
+            Start::PowerOn => out.push(0),
+            Start::State(state) => {
+                out.push(1);
+                out.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                out.extend_from_slice(state);
+            }
+        }
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            let input = &frame.input;
+            out.extend_from_slice(&[input.swcha, input.swchb, input.fire[0] as u8 | (input.fire[1] as u8) << 1]);
+            out.extend_from_slice(&frame.hash);
+        }
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, String> {
+        let mut input = bytes;
+        let mut take = |len: usize| -> Result<&[u8], String> {
+            if input.len() < len {
+                return Err("movie is truncated".to_string());
+            }
+            let (taken, rest) = input.split_at(len);
+            input = rest;
+            return Ok(taken);
+        };
+
+        if take(4).map_err(|_| "not a movie")? != MAGIC {
+            return Err("not a movie".to_string());
+        }
+        let version = u16::from_le_bytes([take(1)?[0], take(1)?[0]]);
+        if version != VERSION {
+            return Err(format!("movie version {} isn't supported (expected {})", version, VERSION));
+        }
+        let mut rom = [0; 16];
+        rom.copy_from_slice(take(16)?);
+        let start = match take(1)?[0] {
+            0 => Start::PowerOn,
+            1 => {
+                let len = u32_at(take(4)?) as usize;
+                Start::State(take(len)?.to_vec())
+            }
+            other => return Err(format!("movie has an unknown start {}", other)),
+        };
+        let count = u32_at(take(4)?) as usize;
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let ports = take(3)?;
+            if ports[2] > 3 {
+                return Err("movie has invalid fire buttons".to_string());
+            }
+            let input = Input {
+                swcha: ports[0],
+                swchb: ports[1],
+                fire: [ports[2] & 1 != 0, ports[2] & 2 != 0],
+            };
+            let mut hash = [0; 16];
+            hash.copy_from_slice(take(16)?);
+            frames.push(Frame { input, hash });
+        }
+        if !input.is_empty() {
+            return Err("movie has data after its last frame".to_string());
+        }
+        return Ok(Movie { start, rom, frames });
+    }
+}
+
+fn u32_at(bytes: &[u8]) -> u32 {
+    return u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+}
+
+/// MD5 of the TIA's frame buffer.
+pub fn frame_hash(machine: &Machine) -> [u8; 16] {
+    return md5::compute(machine.tia().frame()).0;
+}
+
+/// Plays a movie back a frame at a time.
+pub struct Player {
+    movie: Movie,
+    next: usize,
+    divergence: Option<Divergence>,
+}
+
+impl Player {
+    /// Put `machine` at the start of `movie` and get ready to play it.
+    pub fn new(movie: Movie, machine: &mut Machine) -> Result<Player, String> {
+        movie.restart(machine)?;
+        return Ok(Player {
+            movie,
+            next: 0,
+            divergence: None,
+        });
+    }
+
+    /// Run the next frame of the movie. Past its end the machine runs with
+    /// whatever input it is given. Returns the divergence if this is the
+    /// first frame that didn't match.
+    pub fn play_frame(&mut self, machine: &mut Machine) -> Result<Option<Divergence>, Fault> {
+        let frame = match self.movie.frames.get(self.next) {
+            Some(frame) => *frame,
+            None => {
+                machine.run_frame()?;
+                return Ok(None);
+            }
+        };
+        frame.input.apply(machine);
+        machine.run_frame()?;
+        self.next += 1;
+
+        let actual = frame_hash(machine);
+        if actual == frame.hash || self.divergence.is_some() {
+            return Ok(None);
+        }
+        self.divergence = Some(Divergence {
+            frame: self.next - 1,
+            expected: frame.hash,
+            actual,
+        });
+        return Ok(self.divergence);
+    }
+
+    /// Frames in the movie.
+    pub fn frames(&self) -> usize {
+        return self.movie.len();
+    }
+
+    /// Frames played so far.
+    pub fn played(&self) -> usize {
+        return self.next;
+    }
+
+    pub fn finished(&self) -> bool {
+        return self.next >= self.movie.len();
+    }
+
+    /// The first frame that didn't match, if any has.
+    pub fn divergence(&self) -> Option<Divergence> {
+        return self.divergence;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::Properties;
+    use crate::riot::Joystick;
+
+    /// Sets the background color from SWCHA at the start of every frame.
+    fn machine() -> Machine {
+        let mut rom = vec![0; 0x1000];
+        rom[..25].copy_from_slice(&[
+            0xA9, 0x02, //       LDA #2
+            0x85, 0x00, //       STA VSYNC
+            0x85, 0x02, //       STA WSYNC
+            0xA9, 0x00, //       LDA #0
+            0x85, 0x00, //       STA VSYNC
+            0xAD, 0x80, 0x02, // LDA SWCHA
+            0x85, 0x09, //       STA COLUBK
+            0xA2, 0x00, //       LDX #0
+            0x85, 0x02, // line: STA WSYNC
+            0xCA, //             DEX
+            0xD0, 0xFB, //       BNE line
+            0x4C, 0x00, 0xF0, // JMP $F000
+        ]);
+        rom[0xFFD] = 0xF0;
+        return Machine::new(Cartridge::new(rom, Properties::new("")));
+    }
+
+    /// Record 10 frames, pushing up for frames 3 to 5.
+    fn record(machine: &mut Machine, movie: &mut Movie) {
+        for frame in 0..10 {
+            let up = (3..6).contains(&frame);
+            machine.set_joystick(0, Joystick { up, ..Joystick::default() });
+            movie.record_frame(machine).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_record_and_play_back() {
+        let mut machine = machine();
+        let mut movie = Movie::from_power_on(&machine);
+        record(&mut machine, &mut movie);
+        assert_eq!(movie.len(), 10);
+        let end = machine.save_state();
+
+        let mut movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        let mut player = Player::new(movie.clone(), &mut machine).unwrap();
+        assert_eq!(machine.board.cycles, 7);
+        while !player.finished() {
+            assert_eq!(player.play_frame(&mut machine), Ok(None));
+        }
+        assert_eq!(machine.save_state(), end);
+
+        // pushing up a frame late changes what frame 3 draws
+        movie.frames[3].input.swcha = 0xFF;
+        movie.frames[6].input.swcha = 0xEF;
+        let mut player = Player::new(movie, &mut machine).unwrap();
+        let mut reported = Vec::new();
+        while !player.finished() {
+            reported.extend(player.play_frame(&mut machine).unwrap());
+        }
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].frame, 3);
+        assert_eq!(player.divergence(), Some(reported[0]));
+        assert!(reported[0].to_string().starts_with("movie diverged at frame 3: frame hash "));
+    }
+
+    #[test]
+    fn test_start_from_state() {
+        let mut machine = machine();
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        let mut movie = Movie::from_state(&machine);
+        record(&mut machine, &mut movie);
+        let end = machine.save_state();
+
+        let mut machine = self::machine();
+        let mut player = Player::new(Movie::from_bytes(&movie.to_bytes()).unwrap(), &mut machine).unwrap();
+        while !player.finished() {
+            player.play_frame(&mut machine).unwrap();
+        }
+        assert_eq!(player.divergence(), None);
+        assert_eq!(player.played(), 10);
+        assert_eq!(machine.save_state(), end);
+    }
+
+    #[test]
+    fn test_bad_movies() {
+        let mut machine = machine();
+        let mut movie = Movie::from_power_on(&machine);
+        record(&mut machine, &mut movie);
+        let bytes = movie.to_bytes();
+        assert_eq!(bytes.len(), 4 + 2 + 16 + 1 + 4 + 10 * 19);
+
+        assert_eq!(Movie::from_bytes(b"R26S").err().unwrap(), "not a movie");
+        assert_eq!(Movie::from_bytes(&bytes[..40]).err().unwrap(), "movie is truncated");
+        let extra = [bytes.clone(), vec![0]].concat();
+        assert!(Movie::from_bytes(&extra).is_err());
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(Movie::from_bytes(&newer).err().unwrap().starts_with("movie version 2"));
+
+        let mut other = Machine::new(Cartridge::new(vec![0xEA; 0x1000], Properties::new("")));
+        assert!(Player::new(movie, &mut other).is_err());
+    }
+}
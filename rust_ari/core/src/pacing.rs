@@ -0,0 +1,137 @@
+//! Pacing: when to run each frame so the machine keeps the real console's
+//! time, sped up or slowed down.
+//!
+//! Each frame is due one console frame after the last, measured from when
+//! the last was due rather than when it ran, so the error of waking up late
+//! doesn't add up. A frontend playing audio can also nudge the rate to keep
+//! its buffer from running dry or filling up.
+
+use std::time::{Duration, Instant};
+
+/// How far behind the schedule can fall, after a stall, before it's given
+/// up on rather than caught up with a burst of frames.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// The most the frame period is stretched or shrunk to keep an audio
+/// buffer level: half a percent, too little to hear as a change of pitch.
+const MAX_AUDIO_ADJUST: f64 = 0.005;
+
+pub struct Pacer {
+    /// The rate relative to the real console, or `None` to run uncapped.
+    speed: Option<f64>,
+    /// When the next frame is due, once one has run.
+    next: Option<Instant>,
+    /// The frame period's adjustment for the audio buffer.
+    adjust: f64,
+}
+
+impl Pacer {
+    /// A pacer running at the real console's speed.
+    pub fn new() -> Pacer {
+        return Pacer { speed: Some(1.0), next: None, adjust: 1.0 };
+    }
+
+    pub fn speed(&self) -> Option<f64> {
+        return self.speed;
+    }
+
+    /// Run at `speed` times the real console, or uncapped for `None`.
+    /// The schedule starts over from the next frame.
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        self.speed = speed.filter(|&speed| speed > 0.0);
+        self.next = None;
+    }
+
+    /// Whether a frame is due at `now`.
+    pub fn due(&self, now: Instant) -> bool {
+        return self.speed.is_none() || self.next.is_none_or(|next| now >= next);
+    }
+
+    /// When the next frame is due, or `None` when running uncapped.
+    pub fn next(&self) -> Option<Instant> {
+        return self.speed.and(self.next);
+    }
+
+    /// Count a frame as run at `now`, one that lasts `period` on the real
+    /// console, scheduling the next.
+    pub fn ran(&mut self, period: Duration, now: Instant) {
+        let speed = match self.speed {
+            Some(speed) => speed,
+            None => return,
+        };
+        let step = period.mul_f64(self.adjust / speed);
+        self.next = match self.next {
+            Some(next) if next + MAX_LAG >= now => Some(next + step),
+            _ => Some(now + step),
+        };
+    }
+
+    /// Pace by how full an audio buffer is, as a fraction of the level it's
+    /// meant to stay at: frames come a little slower while it's above and a
+    /// little faster while it's below.
+    pub fn audio_fill(&mut self, fill: f64) {
+        self.adjust = 1.0 + MAX_AUDIO_ADJUST * (fill - 1.0).clamp(-1.0, 1.0);
+    }
+}
+
+impl Default for Pacer {
+    fn default() -> Pacer {
+        return Pacer::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn test_schedule() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new();
+        assert!(pacer.due(start));
+        pacer.ran(PERIOD, start);
+        assert_eq!(pacer.next(), Some(start + PERIOD));
+        assert!(!pacer.due(start + PERIOD / 2));
+        // waking late doesn't push the frames after back
+        pacer.ran(PERIOD, start + PERIOD + Duration::from_millis(5));
+        assert_eq!(pacer.next(), Some(start + PERIOD * 2));
+        // but falling far behind starts the schedule over
+        let later = start + Duration::from_secs(1);
+        pacer.ran(PERIOD, later);
+        assert_eq!(pacer.next(), Some(later + PERIOD));
+    }
+
+    #[test]
+    fn test_speed() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new();
+        pacer.set_speed(Some(2.0));
+        pacer.ran(PERIOD, start);
+        assert_eq!(pacer.next(), Some(start + PERIOD / 2));
+        pacer.set_speed(Some(0.25));
+        pacer.ran(PERIOD, start);
+        assert_eq!(pacer.next(), Some(start + PERIOD * 4));
+
+        pacer.set_speed(None);
+        pacer.ran(PERIOD, start);
+        assert_eq!(pacer.next(), None);
+        assert!(pacer.due(start));
+        pacer.set_speed(Some(0.0));
+        assert_eq!(pacer.speed(), None);
+    }
+
+    #[test]
+    fn test_audio_fill() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new();
+        pacer.audio_fill(1.5);
+        pacer.ran(Duration::from_millis(100), start);
+        assert_eq!(pacer.next(), Some(start + Duration::from_micros(100_250)));
+        pacer.set_speed(Some(1.0));
+        pacer.audio_fill(-3.0);
+        pacer.ran(Duration::from_millis(100), start);
+        assert_eq!(pacer.next(), Some(start + Duration::from_micros(99_500)));
+    }
+}
@@ -0,0 +1,310 @@
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::tia::{Tia, CLOCKS_PER_LINE};
+use crate::tv::{Palette, TvStandard};
+
+/// Tweaks applied to a palette before it is used to draw frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    /// Rotation of every hue, in degrees.
+    pub hue: f32,
+    /// Chroma scale; 0 gives a grey picture.
+    pub saturation: f32,
+    pub contrast: f32,
+    /// Offset added to every channel, -1.0 to 1.0.
+    pub brightness: f32,
+    pub gamma: f32,
+    /// Extra degrees between consecutive NTSC hues, as turned by the color
+    /// pot on an NTSC console. Ignored for PAL and SECAM.
+    pub phase_shift: f32,
+}
+
+impl Default for Adjustments {
+    fn default() -> Adjustments {
+        Adjustments {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+            phase_shift: 0.0,
+        }
+    }
+}
+
+/// One of the `Adjustments`, for frontends that step through them.
+#[derive(Display, Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    HUE,
+    SATURATION,
+    CONTRAST,
+    BRIGHTNESS,
+    GAMMA,
+    PHASE,
+}
+
+impl Adjustment {
+    pub fn next(&self) -> Adjustment {
+        match self {
+            Adjustment::HUE => Adjustment::SATURATION,
+            Adjustment::SATURATION => Adjustment::CONTRAST,
+            Adjustment::CONTRAST => Adjustment::BRIGHTNESS,
+            Adjustment::BRIGHTNESS => Adjustment::GAMMA,
+            Adjustment::GAMMA => Adjustment::PHASE,
+            Adjustment::PHASE => Adjustment::HUE,
+        }
+    }
+}
+
+impl Adjustments {
+    /// Nudge one setting by `steps` increments, keeping it in a usable range.
+    pub fn step(&mut self, which: Adjustment, steps: i32) -> f32 {
+        let s = steps as f32;
+        match which {
+            Adjustment::HUE => {
+                self.hue = (self.hue + 5.0 * s).clamp(-180.0, 180.0);
+                self.hue
+            }
+            Adjustment::SATURATION => {
+                self.saturation = (self.saturation + 0.05 * s).clamp(0.0, 2.0);
+                self.saturation
+            }
+            Adjustment::CONTRAST => {
+                self.contrast = (self.contrast + 0.05 * s).clamp(0.0, 2.0);
+                self.contrast
+            }
+            Adjustment::BRIGHTNESS => {
+                self.brightness = (self.brightness + 0.02 * s).clamp(-1.0, 1.0);
+                self.brightness
+            }
+            Adjustment::GAMMA => {
+                self.gamma = (self.gamma + 0.05 * s).clamp(0.1, 3.0);
+                self.gamma
+            }
+            Adjustment::PHASE => {
+                self.phase_shift = (self.phase_shift + 0.5 * s).clamp(-10.0, 10.0);
+                self.phase_shift
+            }
+        }
+    }
+}
+
+/// Read a `.pal` file. Accepts 128 RGB triples, 256 triples with every other
+/// entry used (as written by most emulators), or Stella's NTSC/PAL/SECAM
+/// triple of 128-color tables, from which the one for `standard` is taken.
+#[cfg(feature = "fs")]
+pub fn load_pal(path: &Path, standard: TvStandard) -> Result<Palette, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    return parse_pal(&bytes, standard);
+}
+
+pub fn parse_pal(bytes: &[u8], standard: TvStandard) -> Result<Palette, String> {
+    let (offset, stride) = match bytes.len() {
+        384 => (0, 3),
+        768 => (0, 6),
+        1152 => {
+            let table = match standard {
+                TvStandard::NTSC => 0,
+                TvStandard::PAL => 1,
+                TvStandard::SECAM => 2,
+            };
+            (table * 384, 3)
+        }
+        n => return Err(format!("palette must be 384, 768 or 1152 bytes, not {}", n)),
+    };
+
+    let mut palette = [[0u8; 3]; 128];
+    for (i, color) in palette.iter_mut().enumerate() {
+        let at = offset + i * stride;
+        color.copy_from_slice(&bytes[at..at + 3]);
+    }
+    return Ok(palette);
+}
+
+/// The 128 RGBA values frames are drawn with, built once from a palette and
+/// the current adjustments.
+pub struct ColorTable {
+    colors: [[u8; 4]; 128],
+}
+
+impl ColorTable {
+    pub fn new(palette: &Palette, standard: TvStandard, adjust: &Adjustments) -> ColorTable {
+        let mut colors = [[0u8; 4]; 128];
+        for (i, color) in colors.iter_mut().enumerate() {
+            let rgb = if *adjust == Adjustments::default() {
+                palette[i]
+            } else {
+                adjust_color(palette[i], i >> 3, standard, adjust)
+            };
+            *color = [rgb[0], rgb[1], rgb[2], 0xff];
+        }
+        return ColorTable { colors };
+    }
+
+    /// Convert a `COLUxx` value to RGBA. With `color_loss` set only the luma
+    /// is kept, which is how a PAL set shows a frame it can't decode color for.
+    pub fn rgba(&self, colu: u8, color_loss: bool) -> [u8; 4] {
+        let mut index = (colu >> 1) as usize;
+        if color_loss {
+            index &= 0x07;
+        }
+        return self.colors[index];
+    }
+
+    /// Convert the TIA's last frame to RGBA, `CLOCKS_PER_LINE` pixels wide
+    /// and as many lines as fit in `frame`.
+    pub fn render(&self, tia: &Tia, color_loss: bool, frame: &mut [u8]) {
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let x = i % CLOCKS_PER_LINE;
+            let y = i / CLOCKS_PER_LINE;
+            pixel.copy_from_slice(&self.rgba(tia.pixel(x, y), color_loss));
+        }
+    }
+}
+
+fn adjust_color(rgb: [u8; 3], hue_index: usize, standard: TvStandard, adjust: &Adjustments) -> [u8; 3] {
+    let r = rgb[0] as f32 / 255.0;
+    let g = rgb[1] as f32 / 255.0;
+    let b = rgb[2] as f32 / 255.0;
+
+    // work in YIQ, where hue is the angle of the (I, Q) chroma vector
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let i = 0.596 * r - 0.274 * g - 0.322 * b;
+    let q = 0.211 * r - 0.523 * g + 0.312 * b;
+
+    let mut degrees = adjust.hue;
+    if standard == TvStandard::NTSC && hue_index > 0 {
+        degrees += adjust.phase_shift * (hue_index - 1) as f32;
+    }
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let i2 = (i * cos - q * sin) * adjust.saturation;
+    let q2 = (i * sin + q * cos) * adjust.saturation;
+
+    let channels = [
+        y + 0.956 * i2 + 0.621 * q2,
+        y - 0.272 * i2 - 0.647 * q2,
+        y - 1.106 * i2 + 1.703 * q2,
+    ];
+
+    let mut out = [0u8; 3];
+    for (o, c) in out.iter_mut().zip(channels.iter()) {
+        let v = ((c - 0.5) * adjust.contrast + 0.5 + adjust.brightness).clamp(0.0, 1.0);
+        let v = v.powf(1.0 / adjust.gamma);
+        *o = (v * 255.0).round() as u8;
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tv::{NTSC_PALETTE, PAL_PALETTE, SECAM_PALETTE};
+
+    fn table(standard: TvStandard, adjust: &Adjustments) -> ColorTable {
+        return ColorTable::new(standard.palette(), standard, adjust);
+    }
+
+    #[test]
+    fn test_colu_mapping() {
+        let ntsc = table(TvStandard::NTSC, &Adjustments::default());
+        // hue 4, luma 6: the low bit of COLUxx is ignored
+        assert_eq!(ntsc.rgba(0x4C, false), [0xf0, 0x80, 0x80, 0xff]);
+        assert_eq!(ntsc.rgba(0x4D, false), [0xf0, 0x80, 0x80, 0xff]);
+        assert_eq!(ntsc.rgba(0x0E, false), [0xec, 0xec, 0xec, 0xff]);
+    }
+
+    #[test]
+    fn test_color_loss_keeps_luma() {
+        let pal = table(TvStandard::PAL, &Adjustments::default());
+        assert_eq!(pal.rgba(0x4C, true), pal.rgba(0x0C, false));
+    }
+
+    #[test]
+    fn test_secam_ignores_hue() {
+        let secam = table(TvStandard::SECAM, &Adjustments::default());
+        assert_eq!(secam.rgba(0x08, false), secam.rgba(0xF8, false));
+    }
+
+    #[test]
+    fn test_parse_pal_128() {
+        let mut bytes = vec![0u8; 384];
+        bytes[3..6].copy_from_slice(&[1, 2, 3]);
+        let palette = parse_pal(&bytes, TvStandard::NTSC).unwrap();
+        assert_eq!(palette[0], [0, 0, 0]);
+        assert_eq!(palette[1], [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_parse_pal_256_skips_odd_entries() {
+        let mut bytes = vec![0u8; 768];
+        bytes[3..6].copy_from_slice(&[9, 9, 9]);
+        bytes[6..9].copy_from_slice(&[1, 2, 3]);
+        let palette = parse_pal(&bytes, TvStandard::NTSC).unwrap();
+        assert_eq!(palette[1], [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_parse_pal_stella_picks_standard() {
+        let mut bytes = Vec::new();
+        for p in [&NTSC_PALETTE, &PAL_PALETTE, &SECAM_PALETTE].iter() {
+            for rgb in p.iter() {
+                bytes.extend_from_slice(rgb);
+            }
+        }
+        assert_eq!(parse_pal(&bytes, TvStandard::PAL).unwrap(), PAL_PALETTE);
+        assert_eq!(parse_pal(&bytes, TvStandard::SECAM).unwrap(), SECAM_PALETTE);
+    }
+
+    #[test]
+    fn test_parse_pal_bad_size() {
+        assert!(parse_pal(&[0; 100], TvStandard::NTSC).is_err());
+    }
+
+    #[test]
+    fn test_zero_saturation_is_grey() {
+        let adjust = Adjustments { saturation: 0.0, ..Adjustments::default() };
+        let ntsc = table(TvStandard::NTSC, &adjust);
+        for colu in (0..=0xFEu8).step_by(2) {
+            let c = ntsc.rgba(colu, false);
+            assert!((c[0] as i32 - c[1] as i32).abs() <= 1, "{:02X}: {:?}", colu, c);
+            assert!((c[1] as i32 - c[2] as i32).abs() <= 1, "{:02X}: {:?}", colu, c);
+        }
+    }
+
+    #[test]
+    fn test_brightness_and_gamma_lighten() {
+        let plain = table(TvStandard::NTSC, &Adjustments::default());
+        let brighter = table(TvStandard::NTSC, &Adjustments { brightness: 0.1, ..Adjustments::default() });
+        let gamma = table(TvStandard::NTSC, &Adjustments { gamma: 1.5, ..Adjustments::default() });
+        assert!(brighter.rgba(0x06, false)[0] > plain.rgba(0x06, false)[0]);
+        assert!(gamma.rgba(0x06, false)[0] > plain.rgba(0x06, false)[0]);
+        assert_eq!(brighter.rgba(0x0E, false)[3], 0xff);
+    }
+
+    #[test]
+    fn test_phase_shift_only_affects_ntsc_hues() {
+        let adjust = Adjustments { phase_shift: 5.0, ..Adjustments::default() };
+        let plain = table(TvStandard::NTSC, &Adjustments::default());
+        let shifted = table(TvStandard::NTSC, &adjust);
+        // greys have no chroma to rotate
+        assert_eq!(shifted.rgba(0x08, false), plain.rgba(0x08, false));
+        assert_ne!(shifted.rgba(0xC8, false), plain.rgba(0xC8, false));
+
+        // force both PAL tables through the adjustment path
+        let pal = Adjustments { contrast: 1.0001, ..Adjustments::default() };
+        let pal_shifted = Adjustments { phase_shift: 5.0, ..pal };
+        assert_eq!(
+            table(TvStandard::PAL, &pal_shifted).rgba(0xC8, false),
+            table(TvStandard::PAL, &pal).rgba(0xC8, false)
+        );
+    }
+
+    #[test]
+    fn test_step_clamps() {
+        let mut adjust = Adjustments::default();
+        assert_eq!(adjust.step(Adjustment::SATURATION, -100), 0.0);
+        assert_eq!(adjust.step(Adjustment::HUE, 100), 180.0);
+        assert_eq!(Adjustment::PHASE.next(), Adjustment::HUE);
+    }
+}
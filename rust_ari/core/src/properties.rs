@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::str::FromStr;
+
+use log::warn;
+
+/// The properties database that ships with rustARI, in Stella's `.pro` format.
+/// It only lists the ROMs bundled with rustARI; anything else needs a
+/// `stella.pro` or falls back to detection.
+const BUILTIN_PROPERTIES: &str = include_str!("../assets/rustari.pro");
+
+/// Name of the user supplied properties file, looked up next to the ROM and
+/// in the working directory.
+pub const USER_PROPERTIES_FILE: &str = "stella.pro";
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum BankType {
+    AUTO,
+    #[strum(serialize = "2K")]
+    K2,
+    #[strum(serialize = "4K")]
+    K4,
+    F8,
+    F8SC,
+    F6,
+    F6SC,
+    F4,
+    F4SC,
+    FA,
+    FE,
+    E0,
+    #[strum(serialize = "3F")]
+    X3F,
+}
+
+impl BankType {
+    /// Guess the bank switching scheme from the size of the ROM image, the
+    /// way Stella does for carts it has no entry for.
+    pub fn detect(rom: &[u8]) -> BankType {
+        match rom.len() {
+            0x0800 => BankType::K2,
+            0x1000 => BankType::K4,
+            0x2000 => BankType::F8,
+            0x3000 => BankType::FA,
+            0x4000 => BankType::F6,
+            0x8000 => BankType::F4,
+            _ => BankType::K4,
+        }
+    }
+
+    /// The smallest ROM holding every bank the scheme switches between.
+    /// Smaller unbanked ROMs are mirrored through the window.
+    pub fn min_size(self) -> usize {
+        return match self {
+            BankType::AUTO | BankType::K2 | BankType::K4 => 1,
+            BankType::X3F => 0x0800,
+            BankType::F8 | BankType::F8SC | BankType::FE | BankType::E0 => 0x2000,
+            BankType::FA => 0x3000,
+            BankType::F6 | BankType::F6SC => 0x4000,
+            BankType::F4 | BankType::F4SC => 0x8000,
+        };
+    }
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum Controller {
+    JOYSTICK,
+    PADDLES,
+    BOOSTERGRIP,
+    DRIVING,
+    KEYBOARD,
+    TRAKBALL,
+    GENESIS,
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    A,
+    B,
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum DisplayFormat {
+    AUTO,
+    NTSC,
+    PAL,
+    SECAM,
+    NTSC50,
+    PAL60,
+    SECAM60,
+}
+
+/// Per-title settings for a cartridge, as found in Stella's `stella.pro`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Properties {
+    pub md5: String,
+    pub name: String,
+    pub manufacturer: String,
+    pub bank_type: BankType,
+    pub left_controller: Controller,
+    pub right_controller: Controller,
+    pub swap_ports: bool,
+    pub left_difficulty: Difficulty,
+    pub right_difficulty: Difficulty,
+    pub format: DisplayFormat,
+    pub y_start: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Properties {
+    pub fn new(md5: &str) -> Properties {
+        Properties {
+            md5: md5.to_lowercase(),
+            name: String::new(),
+            manufacturer: String::new(),
+            bank_type: BankType::AUTO,
+            left_controller: Controller::JOYSTICK,
+            right_controller: Controller::JOYSTICK,
+            swap_ports: false,
+            left_difficulty: Difficulty::B,
+            right_difficulty: Difficulty::B,
+            format: DisplayFormat::AUTO,
+            y_start: None,
+            height: None,
+        }
+    }
+
+    /// Apply a single `"Key" "Value"` pair. Unknown keys are ignored so newer
+    /// Stella files still load; values we can't parse are reported and skipped.
+    fn set(&mut self, key: &str, value: &str) {
+        let ok = match key {
+            "Cartridge.MD5" => {
+                self.md5 = value.to_lowercase();
+                true
+            }
+            "Cartridge.Name" => {
+                self.name = value.to_string();
+                true
+            }
+            "Cartridge.Manufacturer" => {
+                self.manufacturer = value.to_string();
+                true
+            }
+            "Cartridge.Type" => parse_into(value, &mut self.bank_type),
+            "Controller.Left" => parse_into(value, &mut self.left_controller),
+            "Controller.Right" => parse_into(value, &mut self.right_controller),
+            "Console.SwapPorts" => parse_yes_no(value, &mut self.swap_ports),
+            "Console.LeftDifficulty" => parse_into(value, &mut self.left_difficulty),
+            "Console.RightDifficulty" => parse_into(value, &mut self.right_difficulty),
+            "Display.Format" => parse_into(value, &mut self.format),
+            "Display.YStart" => parse_opt(value, &mut self.y_start),
+            "Display.Height" => parse_opt(value, &mut self.height),
+            _ => true,
+        };
+        if !ok {
+            warn!("ignoring invalid value {:?} for property {}", value, key);
+        }
+    }
+}
+
+/// Properties given on the command line, which take precedence over any
+/// found for the ROM.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Overrides {
+    pub bank_type: Option<BankType>,
+    /// Left and right.
+    pub controllers: [Option<Controller>; 2],
+    pub format: Option<DisplayFormat>,
+}
+
+impl Overrides {
+    pub fn apply(&self, properties: &mut Properties) {
+        if let Some(bank_type) = self.bank_type {
+            properties.bank_type = bank_type;
+        }
+        if let Some(controller) = self.controllers[0] {
+            properties.left_controller = controller;
+        }
+        if let Some(controller) = self.controllers[1] {
+            properties.right_controller = controller;
+        }
+        if let Some(format) = self.format {
+            properties.format = format;
+        }
+    }
+}
+
+fn parse_into<T: FromStr>(value: &str, target: &mut T) -> bool {
+    match value.to_uppercase().parse() {
+        Ok(v) => {
+            *target = v;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn parse_opt(value: &str, target: &mut Option<u32>) -> bool {
+    match value.parse() {
+        Ok(v) => {
+            *target = Some(v);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn parse_yes_no(value: &str, target: &mut bool) -> bool {
+    match value.to_uppercase().as_str() {
+        "YES" => *target = true,
+        "NO" => *target = false,
+        _ => return false,
+    }
+    return true;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A set of `Properties` keyed by lowercase MD5.
+#[derive(Debug, Default)]
+pub struct PropertiesDb {
+    entries: HashMap<String, Properties>,
+}
+
+impl PropertiesDb {
+    /// The database embedded in the binary.
+    pub fn builtin() -> PropertiesDb {
+        return PropertiesDb::parse(BUILTIN_PROPERTIES).expect("built-in properties are invalid");
+    }
+
+    /// Parse a Stella `.pro` file. Each entry is a run of `"Key" "Value"`
+    /// lines terminated by a line holding only `""`; `;` starts a comment.
+    pub fn parse(text: &str) -> Result<PropertiesDb, ParseError> {
+        let mut db = PropertiesDb::default();
+        let mut current: Option<Properties> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let tokens = tokenize(line).map_err(|message| ParseError { line: i + 1, message })?;
+            match tokens.len() {
+                0 => continue,
+                1 if tokens[0].is_empty() => {
+                    if let Some(props) = current.take() {
+                        db.insert(props);
+                    }
+                }
+                2 => current
+                    .get_or_insert_with(|| Properties::new(""))
+                    .set(&tokens[0], &tokens[1]),
+                _ => {
+                    return Err(ParseError {
+                        line: i + 1,
+                        message: format!("expected a key and a value, found {} strings", tokens.len()),
+                    })
+                }
+            }
+        }
+        if let Some(props) = current.take() {
+            db.insert(props);
+        }
+        return Ok(db);
+    }
+
+    /// Load a `.pro` file from disk.
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<PropertiesDb, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        return PropertiesDb::parse(&text).map_err(|e| e.to_string());
+    }
+
+    fn insert(&mut self, props: Properties) {
+        if props.md5.is_empty() {
+            warn!("ignoring properties entry without Cartridge.MD5");
+            return;
+        }
+        self.entries.insert(props.md5.clone(), props);
+    }
+
+    /// Add every entry of `other`, replacing ours where both have one.
+    pub fn merge(&mut self, other: PropertiesDb) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    pub fn get(&self, md5: &str) -> Option<&Properties> {
+        return self.entries.get(&md5.to_lowercase());
+    }
+
+    /// Look up the properties for a ROM image. Carts without an entry get the
+    /// defaults, and an `AUTO` bank type is resolved from the image size.
+    pub fn lookup(&self, rom: &[u8]) -> Properties {
+        let md5 = rom_md5(rom);
+        let mut props = match self.get(&md5) {
+            Some(props) => props.clone(),
+            None => Properties::new(&md5),
+        };
+        if props.bank_type == BankType::AUTO {
+            props.bank_type = BankType::detect(rom);
+        }
+        return props;
+    }
+}
+
+/// Lowercase hex MD5 of a ROM image, the key Stella uses for its database.
+pub fn rom_md5(rom: &[u8]) -> String {
+    return format!("{:x}", md5::compute(rom));
+}
+
+/// Split a `.pro` line into its quoted strings. Backslash escapes the next
+/// character inside a string.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => token.push(escaped),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(ch) => token.push(ch),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => continue,
+            c => return Err(format!("unexpected character {:?}", c)),
+        }
+    }
+    return Ok(tokens);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+; a comment line
+"Cartridge.MD5" "0DB4F4150FECF77E4CE72CA4D04C052F"
+"Cartridge.Name" "Tic-Tac-Toe \"3D\""
+"Cartridge.Type" "F8"
+"Controller.Left" "PADDLES"
+"Console.SwapPorts" "YES"
+"Console.RightDifficulty" "A"
+"Display.Format" "PAL"
+"Display.Height" "210"
+""
+
+"Cartridge.MD5" "ffffffffffffffffffffffffffffffff"
+"Cartridge.Type" "bogus"
+""
+"#;
+
+    #[test]
+    fn test_parse_entry() {
+        let db = PropertiesDb::parse(SAMPLE).unwrap();
+        assert_eq!(db.len(), 2);
+        let props = db.get("0db4f4150fecf77e4ce72ca4d04c052f").unwrap();
+        assert_eq!(props.name, "Tic-Tac-Toe \"3D\"");
+        assert_eq!(props.bank_type, BankType::F8);
+        assert_eq!(props.left_controller, Controller::PADDLES);
+        assert_eq!(props.right_controller, Controller::JOYSTICK);
+        assert_eq!(props.swap_ports, true);
+        assert_eq!(props.left_difficulty, Difficulty::B);
+        assert_eq!(props.right_difficulty, Difficulty::A);
+        assert_eq!(props.format, DisplayFormat::PAL);
+        assert_eq!(props.height, Some(210));
+        assert_eq!(props.y_start, None);
+    }
+
+    #[test]
+    fn test_parse_invalid_value_keeps_default() {
+        let db = PropertiesDb::parse(SAMPLE).unwrap();
+        let props = db.get("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF").unwrap();
+        assert_eq!(props.bank_type, BankType::AUTO);
+    }
+
+    #[test]
+    fn test_parse_unterminated_string() {
+        let err = PropertiesDb::parse("\"Cartridge.MD5\" \"abc").unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+
+    #[test]
+    fn test_parse_too_many_strings() {
+        let err = PropertiesDb::parse("\n\"a\" \"b\" \"c\"").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut db = PropertiesDb::parse(SAMPLE).unwrap();
+        let user = PropertiesDb::parse(
+            "\"Cartridge.MD5\" \"0db4f4150fecf77e4ce72ca4d04c052f\"\n\"Cartridge.Type\" \"F6\"\n\"\"",
+        )
+        .unwrap();
+        db.merge(user);
+        assert_eq!(db.len(), 2);
+        assert_eq!(db.get("0db4f4150fecf77e4ce72ca4d04c052f").unwrap().bank_type, BankType::F6);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut props = PropertiesDb::parse(SAMPLE).unwrap().get("0db4f4150fecf77e4ce72ca4d04c052f").unwrap().clone();
+        Overrides::default().apply(&mut props);
+        assert_eq!(props.bank_type, BankType::F8);
+        let overrides = Overrides {
+            bank_type: Some(BankType::E0),
+            controllers: [None, Some(Controller::DRIVING)],
+            format: Some(DisplayFormat::NTSC),
+        };
+        overrides.apply(&mut props);
+        assert_eq!(props.bank_type, BankType::E0);
+        assert_eq!(props.left_controller, Controller::PADDLES);
+        assert_eq!(props.right_controller, Controller::DRIVING);
+        assert_eq!(props.format, DisplayFormat::NTSC);
+    }
+
+    #[test]
+    fn test_lookup_unknown_rom_detects_bank_type() {
+        let db = PropertiesDb::default();
+        let props = db.lookup(&[0; 0x2000]);
+        assert_eq!(props.bank_type, BankType::F8);
+        assert_eq!(props.md5, rom_md5(&[0; 0x2000]));
+        assert_eq!(props.format, DisplayFormat::AUTO);
+    }
+
+    #[test]
+    fn test_rom_md5() {
+        assert_eq!(rom_md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(rom_md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn test_builtin_database() {
+        let db = PropertiesDb::builtin();
+        assert!(!db.is_empty());
+        let props = db.get("72ffbef6504b75e69ee1045af9075f66").unwrap();
+        assert_eq!(props.bank_type, BankType::K4);
+        assert_eq!(props.format, DisplayFormat::NTSC);
+    }
+}
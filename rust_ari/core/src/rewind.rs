@@ -0,0 +1,318 @@
+//! Rewinding: a ring buffer of save states reaching back in time.
+//!
+//! Only the newest state is kept whole. Each older one is stored as its
+//! difference from the state after it: the two XORed together, which is
+//! mostly zeros, with the runs of zeros squeezed out. Stepping back XORs the
+//! newest state with the last difference, and the oldest differences are
+//! dropped once the buffer holds as many states as it may, or as many bytes.
+//! A busy picture makes for big differences, since the TIA's frame buffer is
+//! part of the state, so a long buffer needs the byte limit too.
+
+use std::collections::VecDeque;
+
+use crate::machine::Machine;
+
+pub struct Rewind {
+    capacity: usize,
+    max_size: usize,
+    /// The newest state and its mark.
+    latest: Option<(usize, Vec<u8>)>,
+    /// The older states, oldest first, each with its mark and its
+    /// compressed difference from the state after it.
+    deltas: VecDeque<(usize, Vec<u8>)>,
+    /// Bytes taken by `deltas`.
+    deltas_size: usize,
+}
+
+impl Rewind {
+    /// A buffer holding up to `capacity` states, of any size.
+    pub fn new(capacity: usize) -> Rewind {
+        return Rewind {
+            capacity: capacity.max(1),
+            max_size: usize::MAX,
+            latest: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        };
+    }
+
+    /// Drop the oldest states whenever the ones held take more than
+    /// `bytes`, as `size` counts them. The newest is always kept.
+    pub fn set_max_size(&mut self, bytes: usize) {
+        self.max_size = bytes;
+        self.trim();
+    }
+
+    /// The number of states held.
+    pub fn len(&self) -> usize {
+        return self.deltas.len() + self.latest.is_some() as usize;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.latest.is_none();
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+
+    /// Bytes taken by the states held.
+    pub fn size(&self) -> usize {
+        let latest = self.latest.as_ref().map_or(0, |(_, state)| state.len());
+        return latest + self.deltas_size;
+    }
+
+    /// The mark of the newest state.
+    pub fn latest_mark(&self) -> Option<usize> {
+        return self.latest.as_ref().map(|&(mark, _)| mark);
+    }
+
+    /// Record the state of `machine` as the newest, tagged with `mark`:
+    /// whatever the caller counts time in, such as frames or instructions.
+    pub fn record(&mut self, machine: &Machine, mark: usize) {
+        let state = machine.save_state();
+        if let Some((latest_mark, latest)) = self.latest.take() {
+            if latest.len() == state.len() {
+                let delta = diff(&latest, &state);
+                self.deltas_size += delta.len();
+                self.deltas.push_back((latest_mark, delta));
+            } else {
+                self.deltas.clear();
+                self.deltas_size = 0;
+            }
+        }
+        self.latest = Some((mark, state));
+        self.trim();
+    }
+
+    /// Drop the oldest states until the rest fit.
+    fn trim(&mut self) {
+        while self.len() > self.capacity || (self.size() > self.max_size && !self.deltas.is_empty()) {
+            self.drop_oldest();
+        }
+    }
+
+    /// Take the newest difference off the buffer.
+    fn pop_newest(&mut self) -> Option<(usize, Vec<u8>)> {
+        let (mark, delta) = self.deltas.pop_back()?;
+        self.deltas_size -= delta.len();
+        return Some((mark, delta));
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some((_, delta)) = self.deltas.pop_front() {
+            self.deltas_size -= delta.len();
+        }
+    }
+
+    /// Drop the newest state and restore the one before it, returning its
+    /// mark. With nothing older, the machine is left alone.
+    pub fn step_back(&mut self, machine: &mut Machine) -> Option<usize> {
+        let (mark, delta) = self.pop_newest()?;
+        let (_, state) = self.latest.as_mut()?;
+        patch(state, &delta);
+        self.latest = Some((mark, std::mem::take(state)));
+        return self.restore(machine);
+    }
+
+    /// Restore the newest state marked at or before `mark`, dropping the
+    /// ones after it, and return its mark. If every state is later, the
+    /// machine and buffer are left alone.
+    pub fn rewind_to(&mut self, machine: &mut Machine, mark: usize) -> Option<usize> {
+        let oldest = self.deltas.front().map(|&(mark, _)| mark).or(self.latest_mark())?;
+        if oldest > mark {
+            return None;
+        }
+        while self.latest_mark()? > mark {
+            let (older, delta) = self.pop_newest()?;
+            let (_, state) = self.latest.as_mut()?;
+            patch(state, &delta);
+            self.latest = Some((older, std::mem::take(state)));
+        }
+        return self.restore(machine);
+    }
+
+    fn restore(&self, machine: &mut Machine) -> Option<usize> {
+        let (mark, state) = self.latest.as_ref()?;
+        machine.load_state(state).ok()?;
+        return Some(*mark);
+    }
+}
+
+/// XOR two states of the same length and encode the result as a series of
+/// runs: a count of zero bytes, a count of literal bytes, then the literals.
+/// Counts are LEB128 varints.
+fn diff(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < a.len() {
+        let zeros = a[i..].iter().zip(&b[i..]).take_while(|(x, y)| x == y).count();
+        i += zeros;
+        let literals = a[i..].iter().zip(&b[i..]).take_while(|(x, y)| x != y).count();
+        push_varint(&mut out, zeros);
+        push_varint(&mut out, literals);
+        out.extend(a[i..i + literals].iter().zip(&b[i..i + literals]).map(|(x, y)| x ^ y));
+        i += literals;
+    }
+    return out;
+}
+
+/// Apply a `diff` of two states to either of them, turning it into the
+/// other.
+fn patch(state: &mut [u8], diff: &[u8]) {
+    let mut input = diff.iter().cloned();
+    let mut i = 0;
+    while let Some(zeros) = next_varint(&mut input) {
+        i += zeros;
+        let literals = next_varint(&mut input).unwrap_or(0);
+        for byte in &mut state[i..i + literals] {
+            *byte ^= input.next().unwrap_or(0);
+        }
+        i += literals;
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn next_varint(input: &mut dyn Iterator<Item = u8>) -> Option<usize> {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = input.next()?;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(val);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::properties::Properties;
+
+    /// Counts frames in RAM at $80, starting from 0 after the first.
+    fn machine() -> Machine {
+        let mut rom = vec![0; 0x1000];
+        rom[..21].copy_from_slice(&[
+            0xA9, 0x02, //       LDA #2
+            0x85, 0x00, //       STA VSYNC
+            0x85, 0x02, //       STA WSYNC
+            0xA9, 0x00, //       LDA #0
+            0x85, 0x00, //       STA VSYNC
+            0xE6, 0x80, //       INC $80
+            0x85, 0x02, // loop: STA WSYNC
+            0xC6, 0x81, //       DEC $81
+            0xD0, 0xFA, //       BNE loop
+            0x4C, 0x00, 0xF0, // JMP $F000
+        ]);
+        rom[0xFFD] = 0xF0;
+        return Machine::new(Cartridge::new(rom, Properties::new("")));
+    }
+
+    #[test]
+    fn test_diff_and_patch() {
+        let a: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut b = a.clone();
+        b[0] ^= 1;
+        b[500..700].iter_mut().for_each(|byte| *byte = 0x55);
+        b[999] = 0;
+        let delta = diff(&a, &b);
+        assert!(delta.len() < 250, "{}", delta.len());
+        let mut patched = a.clone();
+        patch(&mut patched, &delta);
+        assert_eq!(patched, b);
+        patch(&mut patched, &delta);
+        assert_eq!(patched, a);
+        assert_eq!(diff(&a, &a), [0xE8, 0x07, 0x00]);
+    }
+
+    #[test]
+    fn test_step_back_and_capacity() {
+        let mut machine = machine();
+        let mut rewind = Rewind::new(5);
+        for frame in 0..8 {
+            machine.run_frame().unwrap();
+            rewind.record(&machine, frame);
+        }
+        assert_eq!(rewind.len(), 5);
+        assert_eq!(machine.ram()[0], 7);
+        let full = machine.save_state().len();
+        assert!(rewind.size() < full + 4 * full / 10, "{}", rewind.size());
+
+        assert_eq!(rewind.step_back(&mut machine), Some(6));
+        assert_eq!(machine.ram()[0], 6);
+        assert_eq!(rewind.step_back(&mut machine), Some(5));
+        assert_eq!(rewind.step_back(&mut machine), Some(4));
+        assert_eq!(rewind.step_back(&mut machine), Some(3));
+        assert_eq!(machine.ram()[0], 3);
+        // the oldest state is kept and the machine left alone after it
+        assert_eq!(rewind.step_back(&mut machine), None);
+        assert_eq!(machine.ram()[0], 3);
+        assert_eq!(rewind.len(), 1);
+
+        // running on from a rewound state replaces the future
+        machine.run_frame().unwrap();
+        rewind.record(&machine, 4);
+        assert_eq!(machine.ram()[0], 4);
+        assert_eq!(rewind.step_back(&mut machine), Some(3));
+        assert_eq!(machine.ram()[0], 3);
+    }
+
+    #[test]
+    fn test_max_size() {
+        let mut machine = machine();
+        let mut rewind = Rewind::new(100);
+        for frame in 0..8 {
+            machine.run_frame().unwrap();
+            rewind.record(&machine, frame);
+        }
+        assert_eq!(rewind.len(), 8);
+        let full = machine.save_state().len();
+        let delta = (rewind.size() - full) / 7;
+        rewind.set_max_size(full + 1);
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.latest_mark(), Some(7));
+        rewind.set_max_size(0);
+        assert_eq!(rewind.len(), 1);
+
+        // room for the newest state and three or so differences
+        let max_size = full + delta * 7 / 2;
+        rewind.set_max_size(max_size);
+        for frame in 8..20 {
+            machine.run_frame().unwrap();
+            rewind.record(&machine, frame);
+            assert!(rewind.size() <= max_size, "{}", rewind.size());
+        }
+        assert!((2..=6).contains(&rewind.len()), "{}", rewind.len());
+        assert_eq!(rewind.step_back(&mut machine), Some(18));
+        assert_eq!(machine.ram()[0], 18);
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let mut machine = machine();
+        let mut rewind = Rewind::new(100);
+        for frame in (10..50).step_by(10) {
+            rewind.record(&machine, frame);
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(rewind.rewind_to(&mut machine, 5), None);
+        assert_eq!(machine.ram()[0], 3);
+        assert_eq!(rewind.rewind_to(&mut machine, 35), Some(30));
+        assert_eq!(machine.ram()[0], 1);
+        assert_eq!(rewind.latest_mark(), Some(30));
+        assert_eq!(rewind.rewind_to(&mut machine, 30), Some(30));
+        assert_eq!(rewind.len(), 3);
+    }
+}
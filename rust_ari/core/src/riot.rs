@@ -0,0 +1,260 @@
+//! The 6532 RIOT: 128 bytes of RAM, the interval timer and the two I/O
+//! ports wired to the joysticks (SWCHA) and console switches (SWCHB).
+
+use crate::state::{Reader, Snapshot, StateError, Writer};
+
+pub const SWCHA: u8 = 0x00;
+pub const SWACNT: u8 = 0x01;
+pub const SWCHB: u8 = 0x02;
+pub const SWBCNT: u8 = 0x03;
+pub const INTIM: u8 = 0x04;
+pub const TIMINT: u8 = 0x05;
+pub const TIM1T: u8 = 0x14;
+pub const TIM8T: u8 = 0x15;
+pub const TIM64T: u8 = 0x16;
+pub const T1024T: u8 = 0x17;
+
+/// Register names, with whether each is read, written or both.
+pub const NAMES: [(u8, &str, Access); 10] = [
+    (SWCHA, "SWCHA", Access::Both),
+    (SWACNT, "SWACNT", Access::Both),
+    (SWCHB, "SWCHB", Access::Both),
+    (SWBCNT, "SWBCNT", Access::Both),
+    (INTIM, "INTIM", Access::Read),
+    (TIMINT, "TIMINT", Access::Read),
+    (TIM1T, "TIM1T", Access::Write),
+    (TIM8T, "TIM8T", Access::Write),
+    (TIM64T, "TIM64T", Access::Write),
+    (T1024T, "T1024T", Access::Write),
+];
+
+/// Which kinds of access a register name refers to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Both,
+}
+
+/// Console switches as seen in SWCHB. Reset and select are active low.
+pub const SWITCH_RESET: u8 = 0b0000_0001;
+pub const SWITCH_SELECT: u8 = 0b0000_0010;
+pub const SWITCH_COLOR: u8 = 0b0000_1000;
+pub const SWITCH_LEFT_DIFFICULTY: u8 = 0b0100_0000;
+pub const SWITCH_RIGHT_DIFFICULTY: u8 = 0b1000_0000;
+
+/// Joystick directions for one player.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Joystick {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+#[derive(Clone)]
+pub struct Riot {
+    pub ram: [u8; 128],
+    /// Timer value read through INTIM.
+    intim: u8,
+    /// CPU cycles per timer decrement; 1 once the timer has expired.
+    interval: usize,
+    /// Cycles left until the next decrement.
+    prescale: usize,
+    expired: bool,
+    /// Port A input, one nibble per joystick, active low.
+    swcha: u8,
+    swacnt: u8,
+    swchb: u8,
+    swbcnt: u8,
+}
+
+impl Riot {
+    pub fn new() -> Riot {
+        Riot {
+            ram: [0; 128],
+            intim: 0,
+            interval: 1024,
+            prescale: 0,
+            expired: false,
+            swcha: 0xFF,
+            swacnt: 0,
+            // reset and select released, color, both difficulties B
+            swchb: SWITCH_RESET | SWITCH_SELECT | SWITCH_COLOR,
+            swbcnt: 0,
+        }
+    }
+
+    /// Advance the timer by one CPU cycle.
+    pub fn tick(&mut self) {
+        if self.prescale > 0 {
+            self.prescale -= 1;
+            return;
+        }
+        self.intim = self.intim.wrapping_sub(1);
+        if self.intim == 0xFF && !self.expired {
+            // once expired the timer counts down every cycle until rewritten
+            self.expired = true;
+            self.interval = 1;
+        }
+        self.prescale = self.interval - 1;
+    }
+
+    /// Read an I/O or timer register (address already mirrored down to
+    /// 0x00-0x1F).
+    pub fn read(&self, reg: u8) -> u8 {
+        return match reg & 0x07 {
+            SWCHA => self.swcha,
+            SWACNT => self.swacnt,
+            SWCHB => self.swchb,
+            SWBCNT => self.swbcnt,
+            INTIM | 0x06 => self.intim,
+            _ => {
+                if self.expired {
+                    0x80
+                } else {
+                    0
+                }
+            }
+        };
+    }
+
+    /// Write an I/O or timer register (address already mirrored down to
+    /// 0x00-0x1F). Bit 3 of a timer address only enables its interrupt,
+    /// which isn't wired up on the 2600.
+    pub fn write(&mut self, reg: u8, val: u8) {
+        if reg & TIM1T == TIM1T {
+            self.interval = [1, 8, 64, 1024][(reg & 0x03) as usize];
+            self.intim = val;
+            self.prescale = 0;
+            self.expired = false;
+            return;
+        }
+        match reg & 0x07 {
+            // the ports are inputs on the 2600; writes only set direction
+            SWACNT => self.swacnt = val,
+            SWBCNT => self.swbcnt = val,
+            _ => {}
+        }
+    }
+
+    /// CPU cycles per decrement of INTIM: the interval last written, or 1
+    /// once the timer has expired.
+    pub fn interval(&self) -> usize {
+        return self.interval;
+    }
+
+    pub fn set_joystick(&mut self, player: usize, joystick: Joystick) {
+        let bits = (!joystick.right as u8) << 3
+            | (!joystick.left as u8) << 2
+            | (!joystick.down as u8) << 1
+            | !joystick.up as u8;
+        let shift = if player == 0 { 4 } else { 0 };
+        self.swcha = (self.swcha & !(0x0F << shift)) | bits << shift;
+    }
+
+    /// Set or clear switch bits in SWCHB. Pass `on` as the bit's electrical
+    /// level: reset and select read 0 while held.
+    pub fn set_switch(&mut self, switch: u8, on: bool) {
+        if on {
+            self.swchb |= switch;
+        } else {
+            self.swchb &= !switch;
+        }
+    }
+
+    pub fn swchb(&self) -> u8 {
+        return self.swchb;
+    }
+
+    /// Set both joysticks at once from the levels SWCHA reads.
+    pub fn set_swcha(&mut self, val: u8) {
+        self.swcha = val;
+    }
+}
+
+impl Default for Riot {
+    fn default() -> Riot {
+        return Riot::new();
+    }
+}
+
+impl Snapshot for Riot {
+    fn save(&self, out: &mut Writer) {
+        out.bytes(&self.ram);
+        out.u8(self.intim);
+        out.usize(self.interval);
+        out.usize(self.prescale);
+        out.bool(self.expired);
+        out.u8(self.swcha);
+        out.u8(self.swacnt);
+        out.u8(self.swchb);
+        out.u8(self.swbcnt);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        input.fill(&mut self.ram)?;
+        self.intim = input.u8()?;
+        self.interval = input.usize()?;
+        self.prescale = input.usize()?;
+        if ![1, 8, 64, 1024].contains(&self.interval) || self.prescale >= self.interval {
+            return Err(StateError::Invalid("RIOT timer"));
+        }
+        self.expired = input.bool()?;
+        self.swcha = input.u8()?;
+        self.swacnt = input.u8()?;
+        self.swchb = input.u8()?;
+        self.swbcnt = input.u8()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_intervals() {
+        let mut riot = Riot::new();
+        riot.write(TIM64T, 2);
+        riot.tick();
+        assert_eq!(riot.read(INTIM), 1);
+        for _ in 0..64 {
+            riot.tick();
+        }
+        assert_eq!(riot.read(INTIM), 0);
+        assert_eq!(riot.read(TIMINT), 0);
+        for _ in 0..64 {
+            riot.tick();
+        }
+        assert_eq!(riot.read(INTIM), 0xFF);
+        assert_eq!(riot.read(TIMINT), 0x80);
+        // after expiring it counts down once per cycle
+        riot.tick();
+        assert_eq!(riot.read(INTIM), 0xFE);
+        assert_eq!(riot.interval(), 1);
+        riot.write(TIM8T, 1);
+        assert_eq!(riot.read(TIMINT), 0);
+        assert_eq!(riot.interval(), 8);
+    }
+
+    #[test]
+    fn test_joysticks() {
+        let mut riot = Riot::new();
+        riot.set_joystick(0, Joystick { up: true, ..Joystick::default() });
+        assert_eq!(riot.read(SWCHA), 0b1110_1111);
+        riot.set_joystick(1, Joystick { right: true, ..Joystick::default() });
+        assert_eq!(riot.read(SWCHA), 0b1110_0111);
+        riot.set_joystick(0, Joystick::default());
+        assert_eq!(riot.read(SWCHA), 0b1111_0111);
+    }
+
+    #[test]
+    fn test_switches() {
+        let mut riot = Riot::new();
+        assert_eq!(riot.read(SWCHB), 0b0000_1011);
+        riot.set_switch(SWITCH_RESET, false);
+        riot.set_switch(SWITCH_LEFT_DIFFICULTY, true);
+        assert_eq!(riot.read(SWCHB), 0b0100_1010);
+    }
+}
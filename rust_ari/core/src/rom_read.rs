@@ -0,0 +1,74 @@
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+use log::info;
+#[cfg(feature = "fs")]
+use log::warn;
+
+use crate::cartridge::Cartridge;
+use crate::error::Error;
+use crate::properties::{Overrides, PropertiesDb};
+#[cfg(feature = "fs")]
+use crate::properties::USER_PROPERTIES_FILE;
+
+#[cfg(feature = "fs")]
+pub fn get_file_as_byte_vec(filename: &String) -> Result<Vec<u8>, Error> {
+    return std::fs::read(filename).map_err(|e| Error::Io(PathBuf::from(filename), e));
+}
+
+/// Read a ROM and resolve its properties from the built-in database, with any
+/// `stella.pro` found next to the ROM or in the working directory on top,
+/// and then `overrides`.
+#[cfg(feature = "fs")]
+pub fn load_cartridge(filename: &String, overrides: &Overrides) -> Result<Cartridge, Error> {
+    let rom = get_file_as_byte_vec(filename)?;
+    return cartridge_from_file(filename, rom, overrides);
+}
+
+/// Like `load_cartridge`, for a ROM already read from `filename`, so a caller
+/// that needs the bytes first doesn't read the file twice.
+#[cfg(feature = "fs")]
+pub fn cartridge_from_file(filename: &String, rom: Vec<u8>, overrides: &Overrides) -> Result<Cartridge, Error> {
+    let mut db = PropertiesDb::builtin();
+    let rom_dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+    let mut candidates = vec![Path::new(USER_PROPERTIES_FILE).to_path_buf()];
+    let beside_rom = rom_dir.join(USER_PROPERTIES_FILE);
+    if !rom_dir.as_os_str().is_empty() && beside_rom != candidates[0] {
+        candidates.push(beside_rom);
+    }
+    for path in candidates {
+        if !path.is_file() {
+            continue;
+        }
+        match PropertiesDb::load(&path) {
+            Ok(user) => {
+                info!("loaded {} properties from {}", user.len(), path.display());
+                db.merge(user);
+            }
+            Err(e) => warn!("ignoring {}: {}", path.display(), e),
+        }
+    }
+
+    return cartridge_from(rom, &db, overrides);
+}
+
+/// Make a cartridge of a ROM already read, as a frontend that's handed the
+/// bytes does, with its properties from the built-in database and then
+/// `overrides`.
+pub fn cartridge_from_bytes(rom: Vec<u8>, overrides: &Overrides) -> Result<Cartridge, Error> {
+    return cartridge_from(rom, &PropertiesDb::builtin(), overrides);
+}
+
+fn cartridge_from(rom: Vec<u8>, db: &PropertiesDb, overrides: &Overrides) -> Result<Cartridge, Error> {
+    let mut properties = db.lookup(&rom);
+    overrides.apply(&mut properties);
+    let cartridge = Cartridge::load(rom, properties)?;
+    info!(
+        "cartridge {} ({}), bank type {}, format {}",
+        cartridge.properties.md5,
+        cartridge.properties.name,
+        cartridge.bank_type(),
+        cartridge.properties.format
+    );
+    return Ok(cartridge);
+}
@@ -0,0 +1,227 @@
+//! Save states: the complete machine as a versioned binary snapshot.
+//!
+//! A state starts with `MAGIC`, the format `VERSION` and the MD5 of the
+//! cartridge ROM it was saved with. Then each component writes its fields
+//! in a fixed order, integers little endian, through its `Snapshot` impl.
+//! Everything that affects emulation is included, so restoring a state and
+//! repeating the same input reproduces the same frames. Audio samples that
+//! haven't been taken yet are output rather than state and aren't saved.
+
+use std::fmt;
+
+pub const MAGIC: &[u8; 4] = b"R26S";
+
+/// Bumped whenever the layout changes; older states are rejected.
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    /// The data doesn't start with `MAGIC`.
+    NotAState,
+    /// The state was written in another format version.
+    Version(u16),
+    /// The state was saved with a different cartridge ROM.
+    WrongCartridge,
+    /// The data ends before the state does.
+    Truncated,
+    /// A field holds a value the machine can't be in.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::Version(version) => {
+                write!(f, "save state version {} isn't supported (expected {})", version, VERSION)
+            }
+            StateError::WrongCartridge => write!(f, "save state is for a different cartridge"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has an invalid {}", what),
+        };
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// A component that can be written to and restored from a save state.
+/// `load` must read exactly what `save` writes, in the same order.
+pub trait Snapshot {
+    fn save(&self, out: &mut Writer);
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError>;
+}
+
+/// Builds a save state.
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// Start a state for a machine running `rom`.
+    pub fn new(rom: &[u8]) -> Writer {
+        let mut out = Writer { bytes: Vec::new() };
+        out.bytes(MAGIC);
+        out.u16(VERSION);
+        out.bytes(&md5::compute(rom).0);
+        return out;
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        return self.bytes;
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.bytes.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.bytes.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Counters and addresses are `usize` in memory but always 64 bits in
+    /// a state.
+    pub fn usize(&mut self, val: usize) {
+        self.bytes.extend_from_slice(&(val as u64).to_le_bytes());
+    }
+
+    /// Raw bytes, whose length the reader must already know.
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.bytes.extend_from_slice(val);
+    }
+}
+
+/// Reads a save state back.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Check the header of `bytes` against `rom`, the cartridge the state
+    /// is being loaded into, and position the reader after it.
+    pub fn new(bytes: &'a [u8], rom: &[u8]) -> Result<Reader<'a>, StateError> {
+        let mut input = Reader { bytes, pos: 0 };
+        let mut magic = [0; 4];
+        input.fill(&mut magic).map_err(|_| StateError::NotAState)?;
+        if &magic != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = input.u16()?;
+        if version != VERSION {
+            return Err(StateError::Version(version));
+        }
+        let mut digest = [0; 16];
+        input.fill(&mut digest)?;
+        if digest != md5::compute(rom).0 {
+            return Err(StateError::WrongCartridge);
+        }
+        return Ok(input);
+    }
+
+    /// Fail unless the whole state has been read.
+    pub fn finish(&self) -> Result<(), StateError> {
+        if self.pos != self.bytes.len() {
+            return Err(StateError::Invalid("length"));
+        }
+        return Ok(());
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(StateError::Truncated);
+        }
+        let taken = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        return Ok(taken);
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        return match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        };
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.take(2)?;
+        return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        self.fill(&mut bytes)?;
+        return Ok(u32::from_le_bytes(bytes));
+    }
+
+    pub fn usize(&mut self) -> Result<usize, StateError> {
+        let mut bytes = [0; 8];
+        self.fill(&mut bytes)?;
+        return Ok(u64::from_le_bytes(bytes) as usize);
+    }
+
+    /// Read exactly enough bytes to fill `dst`.
+    pub fn fill(&mut self, dst: &mut [u8]) -> Result<(), StateError> {
+        dst.copy_from_slice(self.take(dst.len())?);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_values() {
+        let rom = [0xEA; 16];
+        let mut out = Writer::new(&rom);
+        out.u8(0x12);
+        out.bool(true);
+        out.u16(0x3456);
+        out.u32(0x789A_BCDE);
+        out.usize(1 << 40);
+        out.bytes(&[1, 2, 3]);
+        let bytes = out.into_bytes();
+
+        let mut input = Reader::new(&bytes, &rom).unwrap();
+        assert_eq!(input.u8(), Ok(0x12));
+        assert_eq!(input.bool(), Ok(true));
+        assert_eq!(input.u16(), Ok(0x3456));
+        assert_eq!(input.u32(), Ok(0x789A_BCDE));
+        assert_eq!(input.usize(), Ok(1 << 40));
+        let mut three = [0; 3];
+        input.fill(&mut three).unwrap();
+        assert_eq!(three, [1, 2, 3]);
+        assert_eq!(input.finish(), Ok(()));
+        assert_eq!(input.u8(), Err(StateError::Truncated));
+    }
+
+    #[test]
+    fn test_header_checks() {
+        let rom = [0xEA; 16];
+        let bytes = Writer::new(&rom).into_bytes();
+        assert!(Reader::new(&bytes, &rom).is_ok());
+        assert_eq!(Reader::new(&bytes, &[0; 16]).err(), Some(StateError::WrongCartridge));
+        assert_eq!(Reader::new(b"R2", &rom).err(), Some(StateError::NotAState));
+        assert_eq!(Reader::new(b"PNG!....", &rom).err(), Some(StateError::NotAState));
+        let mut newer = bytes.clone();
+        newer[4] = 99;
+        assert_eq!(Reader::new(&newer, &rom).err(), Some(StateError::Version(99)));
+        assert_eq!(Reader::new(&bytes[..10], &rom).err(), Some(StateError::Truncated));
+        let flag = [bytes.clone(), vec![2]].concat();
+        let mut input = Reader::new(&flag, &rom).unwrap();
+        assert_eq!(input.bool(), Err(StateError::Invalid("flag")));
+    }
+}
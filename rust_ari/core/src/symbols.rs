@@ -0,0 +1,90 @@
+//! Names for the TIA and RIOT registers, as used in 2600 source code.
+
+use crate::machine::{decode, Target};
+use crate::riot::{self, Access};
+use crate::tia;
+
+/// Base address of the RIOT's I/O and timer registers.
+const RIOT_BASE: u16 = 0x0280;
+
+/// The register name for an access to `addr`, if it hits a named register.
+/// The TIA has different registers for reads and writes at each address.
+pub fn name(addr: u16, write: bool) -> Option<&'static str> {
+    return match decode(addr, write) {
+        Target::Tia(reg) if write => tia::WRITE_NAMES.get(reg as usize).cloned(),
+        Target::Tia(reg) => tia::READ_NAMES.get(reg as usize).cloned(),
+        Target::Riot(reg) => {
+            let reg = riot_register(reg, write);
+            riot::NAMES
+                .iter()
+                .find(|&&(r, _, access)| r == reg && allows(access, write))
+                .map(|&(_, name, _)| name)
+        }
+        _ => None,
+    };
+}
+
+/// The canonical address of a register name, ignoring case, and whether
+/// it names a register that is read, written or both.
+pub fn lookup(name: &str) -> Option<(u16, Access)> {
+    let name = name.to_ascii_uppercase();
+    if let Some(reg) = tia::WRITE_NAMES.iter().position(|&n| n == name) {
+        return Some((reg as u16, Access::Write));
+    }
+    if let Some(reg) = tia::READ_NAMES.iter().position(|&n| n == name) {
+        return Some((reg as u16, Access::Read));
+    }
+    return riot::NAMES
+        .iter()
+        .find(|&&(_, n, _)| n == name)
+        .map(|&(reg, _, access)| (RIOT_BASE + reg as u16, access));
+}
+
+/// Fold a RIOT register number onto the one it mirrors, decoding the
+/// same bits the RIOT does.
+fn riot_register(reg: u8, write: bool) -> u8 {
+    if write && reg & riot::TIM1T == riot::TIM1T {
+        return riot::TIM1T | (reg & 0x03);
+    }
+    if !write && reg & 0x04 != 0 {
+        return reg & 0x05;
+    }
+    return reg & 0x07;
+}
+
+fn allows(access: Access, write: bool) -> bool {
+    return match access {
+        Access::Read => !write,
+        Access::Write => write,
+        Access::Both => true,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_follow_direction_and_mirrors() {
+        assert_eq!(name(0x0002, true), Some("WSYNC"));
+        assert_eq!(name(0x0042, true), Some("WSYNC"));
+        assert_eq!(name(0x0002, false), Some("CXP0FB"));
+        assert_eq!(name(0x003C, false), Some("INPT4"));
+        assert_eq!(name(0x0284, false), Some("INTIM"));
+        assert_eq!(name(0x0286, false), Some("INTIM"));
+        assert_eq!(name(0x0296, true), Some("TIM64T"));
+        assert_eq!(name(0x029E, true), Some("TIM64T"));
+        assert_eq!(name(0x0280, true), Some("SWCHA"));
+        assert_eq!(name(0x0080, false), None);
+        assert_eq!(name(0xF000, false), None);
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("colubk"), Some((0x09, Access::Write)));
+        assert_eq!(lookup("INPT4"), Some((0x0C, Access::Read)));
+        assert_eq!(lookup("INTIM"), Some((0x0284, Access::Read)));
+        assert_eq!(lookup("SWCHA"), Some((0x0280, Access::Both)));
+        assert_eq!(lookup("NOPE"), None);
+    }
+}
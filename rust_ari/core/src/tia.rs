@@ -0,0 +1,728 @@
+use crate::audio::{self, Audio};
+use crate::state::{Reader, Snapshot, StateError, Writer};
+
+/// Color clocks per scanline, including horizontal blank.
+pub const CLOCKS_PER_LINE: usize = 228;
+/// Color clocks of horizontal blank at the start of each scanline.
+pub const HBLANK_CLOCKS: usize = 68;
+/// Visible pixels per scanline.
+pub const VISIBLE_CLOCKS: usize = CLOCKS_PER_LINE - HBLANK_CLOCKS;
+/// Color clocks HMOVE adds to the horizontal blank when strobed during it.
+const HMOVE_BLANK_CLOCKS: usize = 8;
+/// Scanlines kept in the frame buffer; enough for PAL/SECAM frames.
+pub const MAX_LINES: usize = 312;
+/// Scanlines after which a frame is ended even if the kernel never sends VSYNC.
+const RUNAWAY_LINES: usize = 400;
+
+/* #region Write registers */
+pub const VSYNC: u8 = 0x00;
+pub const VBLANK: u8 = 0x01;
+pub const WSYNC: u8 = 0x02;
+pub const RSYNC: u8 = 0x03;
+pub const NUSIZ0: u8 = 0x04;
+pub const NUSIZ1: u8 = 0x05;
+pub const COLUP0: u8 = 0x06;
+pub const COLUP1: u8 = 0x07;
+pub const COLUPF: u8 = 0x08;
+pub const COLUBK: u8 = 0x09;
+pub const CTRLPF: u8 = 0x0A;
+pub const REFP0: u8 = 0x0B;
+pub const REFP1: u8 = 0x0C;
+pub const PF0: u8 = 0x0D;
+pub const PF1: u8 = 0x0E;
+pub const PF2: u8 = 0x0F;
+pub const RESP0: u8 = 0x10;
+pub const RESP1: u8 = 0x11;
+pub const RESM0: u8 = 0x12;
+pub const RESM1: u8 = 0x13;
+pub const RESBL: u8 = 0x14;
+pub const GRP0: u8 = 0x1B;
+pub const GRP1: u8 = 0x1C;
+pub const ENAM0: u8 = 0x1D;
+pub const ENAM1: u8 = 0x1E;
+pub const ENABL: u8 = 0x1F;
+pub const HMP0: u8 = 0x20;
+pub const HMP1: u8 = 0x21;
+pub const HMM0: u8 = 0x22;
+pub const HMM1: u8 = 0x23;
+pub const HMBL: u8 = 0x24;
+pub const VDELP0: u8 = 0x25;
+pub const VDELP1: u8 = 0x26;
+pub const VDELBL: u8 = 0x27;
+pub const RESMP0: u8 = 0x28;
+pub const RESMP1: u8 = 0x29;
+pub const HMOVE: u8 = 0x2A;
+pub const HMCLR: u8 = 0x2B;
+pub const CXCLR: u8 = 0x2C;
+
+/// Names of the write registers by number, as in the standard vcs.h.
+pub const WRITE_NAMES: [&str; 0x2D] = [
+    "VSYNC", "VBLANK", "WSYNC", "RSYNC", "NUSIZ0", "NUSIZ1", "COLUP0", "COLUP1",
+    "COLUPF", "COLUBK", "CTRLPF", "REFP0", "REFP1", "PF0", "PF1", "PF2",
+    "RESP0", "RESP1", "RESM0", "RESM1", "RESBL", "AUDC0", "AUDC1", "AUDF0",
+    "AUDF1", "AUDV0", "AUDV1", "GRP0", "GRP1", "ENAM0", "ENAM1", "ENABL",
+    "HMP0", "HMP1", "HMM0", "HMM1", "HMBL", "VDELP0", "VDELP1", "VDELBL",
+    "RESMP0", "RESMP1", "HMOVE", "HMCLR", "CXCLR",
+];
+/* #endregion */
+
+/* #region Read registers */
+pub const CXM0P: u8 = 0x00;
+pub const CXM1P: u8 = 0x01;
+pub const CXP0FB: u8 = 0x02;
+pub const CXP1FB: u8 = 0x03;
+pub const CXM0FB: u8 = 0x04;
+pub const CXM1FB: u8 = 0x05;
+pub const CXBLPF: u8 = 0x06;
+pub const CXPPMM: u8 = 0x07;
+pub const INPT0: u8 = 0x08;
+pub const INPT4: u8 = 0x0C;
+pub const INPT5: u8 = 0x0D;
+
+/// Names of the read registers by number. 0x0E and 0x0F aren't connected.
+pub const READ_NAMES: [&str; 0x0E] = [
+    "CXM0P", "CXM1P", "CXP0FB", "CXP1FB", "CXM0FB", "CXM1FB", "CXBLPF", "CXPPMM",
+    "INPT0", "INPT1", "INPT2", "INPT3", "INPT4", "INPT5",
+];
+/* #endregion */
+
+// Indices into the per-object position and motion arrays.
+const P0: usize = 0;
+const P1: usize = 1;
+const M0: usize = 2;
+const BL: usize = 4;
+
+/// Pixel offsets of the copies drawn for each NUSIZ number-size setting,
+/// and how many pixels wide each graphics bit is.
+const COPIES: [(&[usize], usize); 8] = [
+    (&[0], 1),
+    (&[0, 16], 1),
+    (&[0, 32], 1),
+    (&[0, 16, 32], 1),
+    (&[0, 64], 1),
+    (&[0], 2),
+    (&[0, 32, 64], 1),
+    (&[0], 4),
+];
+
+/// The Television Interface Adaptor: beam timing, the playfield, two players,
+/// two missiles and the ball, collision latches, fire buttons and audio.
+#[derive(Clone)]
+pub struct Tia {
+    pub clock: usize,
+    pub scanline: usize,
+    /// Set by a write to WSYNC; the CPU is halted until the end of the line.
+    pub wsync: bool,
+    /// Scanlines in the last completed frame.
+    pub frame_lines: usize,
+    pub frame_count: usize,
+    pub audio: Audio,
+    vsync: bool,
+    vblank: bool,
+    colup0: u8,
+    colup1: u8,
+    colupf: u8,
+    colubk: u8,
+    ctrlpf: u8,
+    /// PF0-PF2 as the 20 playfield cells of the left half, cell 0 in bit 0.
+    playfield: u32,
+    pf: [u8; 3],
+    nusiz: [u8; 2],
+    reflect: [bool; 2],
+    /// Graphics as last written, and the copies kept for vertical delay.
+    grp: [u8; 2],
+    grp_old: [u8; 2],
+    vdelp: [bool; 2],
+    enam: [bool; 2],
+    resmp: [bool; 2],
+    enabl: bool,
+    enabl_old: bool,
+    vdelbl: bool,
+    /// Horizontal position of each object, 0-159, and its HMxx motion.
+    pos: [usize; 5],
+    motion: [u8; 5],
+    /// HMOVE was strobed during this line's horizontal blank.
+    hmove_blank: bool,
+    /// Collision latches, laid out as the D7/D6 bits of CXM0P-CXPPMM.
+    collisions: [u8; 8],
+    fire: [bool; 2],
+    /// Fire presses held by the VBLANK input latch (D6).
+    fire_latched: [bool; 2],
+    input_latch: bool,
+    /// One `COLUxx` value per color clock, `CLOCKS_PER_LINE` wide.
+    frame: Vec<u8>,
+}
+
+impl Tia {
+    pub fn new() -> Tia {
+        Tia {
+            clock: 0,
+            scanline: 0,
+            wsync: false,
+            frame_lines: 0,
+            frame_count: 0,
+            audio: Audio::new(),
+            vsync: false,
+            vblank: false,
+            colup0: 0,
+            colup1: 0,
+            colupf: 0,
+            colubk: 0,
+            ctrlpf: 0,
+            playfield: 0,
+            pf: [0; 3],
+            nusiz: [0; 2],
+            reflect: [false; 2],
+            grp: [0; 2],
+            grp_old: [0; 2],
+            vdelp: [false; 2],
+            enam: [false; 2],
+            resmp: [false; 2],
+            enabl: false,
+            enabl_old: false,
+            vdelbl: false,
+            pos: [0; 5],
+            motion: [0; 5],
+            hmove_blank: false,
+            collisions: [0; 8],
+            fire: [false; 2],
+            fire_latched: [false; 2],
+            input_latch: false,
+            frame: vec![0; CLOCKS_PER_LINE * MAX_LINES],
+        }
+    }
+
+    /// Handle a write to one of the TIA registers (address already mirrored
+    /// down to 0x00-0x3F). Returns true when a new frame was started.
+    pub fn write(&mut self, reg: u8, val: u8) -> bool {
+        match reg {
+            VSYNC => {
+                let on = val & 0b0000_0010 != 0;
+                let started = on && !self.vsync;
+                self.vsync = on;
+                if started {
+                    self.end_frame();
+                }
+                return started;
+            }
+            VBLANK => {
+                self.vblank = val & 0b0000_0010 != 0;
+                self.input_latch = val & 0b0100_0000 != 0;
+                if !self.input_latch {
+                    self.fire_latched = [false; 2];
+                }
+            }
+            WSYNC => self.wsync = true,
+            NUSIZ0 | NUSIZ1 => self.nusiz[(reg - NUSIZ0) as usize] = val,
+            COLUP0 => self.colup0 = val,
+            COLUP1 => self.colup1 = val,
+            COLUPF => self.colupf = val,
+            COLUBK => self.colubk = val,
+            CTRLPF => self.ctrlpf = val,
+            REFP0 | REFP1 => self.reflect[(reg - REFP0) as usize] = val & 0b0000_1000 != 0,
+            PF0..=PF2 => {
+                self.pf[(reg - PF0) as usize] = val;
+                self.playfield = (self.pf[0] >> 4) as u32
+                    | (self.pf[1].reverse_bits() as u32) << 4
+                    | (self.pf[2] as u32) << 12;
+            }
+            RESP0 | RESP1 => self.pos[(reg - RESP0) as usize] = self.start_position(5),
+            RESM0 | RESM1 => self.pos[(reg - RESP0) as usize] = self.start_position(4),
+            RESBL => self.pos[BL] = self.start_position(4),
+            audio::AUDC0..=audio::AUDV1 => self.audio.write(reg, val),
+            GRP0 => {
+                self.grp[0] = val;
+                self.grp_old[1] = self.grp[1];
+            }
+            GRP1 => {
+                self.grp[1] = val;
+                self.grp_old[0] = self.grp[0];
+                self.enabl_old = self.enabl;
+            }
+            ENAM0 | ENAM1 => self.enam[(reg - ENAM0) as usize] = val & 0b0000_0010 != 0,
+            ENABL => self.enabl = val & 0b0000_0010 != 0,
+            HMP0..=HMBL => self.motion[(reg - HMP0) as usize] = val,
+            VDELP0 | VDELP1 => self.vdelp[(reg - VDELP0) as usize] = val & 1 != 0,
+            VDELBL => self.vdelbl = val & 1 != 0,
+            RESMP0 | RESMP1 => {
+                let player = (reg - RESMP0) as usize;
+                let locked = val & 0b0000_0010 != 0;
+                if self.resmp[player] && !locked {
+                    // a released missile starts at the middle of its player
+                    let center = [3, 3, 3, 3, 3, 6, 3, 10][(self.nusiz[player] & 7) as usize];
+                    self.pos[M0 + player] = (self.pos[player] + center) % VISIBLE_CLOCKS;
+                }
+                self.resmp[player] = locked;
+            }
+            HMOVE => {
+                for (pos, motion) in self.pos.iter_mut().zip(self.motion.iter()) {
+                    // the high nibble is a signed count of pixels to move left
+                    let left = (*motion as i8 >> 4) as isize;
+                    *pos = (*pos as isize - left).rem_euclid(VISIBLE_CLOCKS as isize) as usize;
+                }
+                if self.clock < HBLANK_CLOCKS {
+                    self.hmove_blank = true;
+                }
+            }
+            HMCLR => self.motion = [0; 5],
+            CXCLR => self.collisions = [0; 8],
+            _ => {}
+        }
+        return false;
+    }
+
+    /// Read one of the collision or input registers (address already
+    /// mirrored down to 0x00-0x0F). Only D7 and D6 are driven.
+    pub fn read(&self, reg: u8) -> u8 {
+        return match reg {
+            CXM0P..=CXPPMM => self.collisions[reg as usize],
+            INPT4 | INPT5 => {
+                let player = (reg - INPT4) as usize;
+                if self.fire[player] || self.fire_latched[player] {
+                    0
+                } else {
+                    0x80
+                }
+            }
+            _ => 0,
+        };
+    }
+
+    /// Press or release a joystick's fire button, read through INPT4/INPT5.
+    pub fn set_fire(&mut self, player: usize, pressed: bool) {
+        self.fire[player] = pressed;
+        if pressed && self.input_latch {
+            self.fire_latched[player] = true;
+        }
+    }
+
+    pub fn fire(&self, player: usize) -> bool {
+        return self.fire[player];
+    }
+
+    /// Where an object reset now first appears. Objects reset during
+    /// horizontal blank start at the left edge.
+    fn start_position(&self, delay: usize) -> usize {
+        if self.clock < HBLANK_CLOCKS {
+            return delay - 2;
+        }
+        return (self.clock - HBLANK_CLOCKS + delay) % VISIBLE_CLOCKS;
+    }
+
+    /// Advance the beam by one color clock, drawing the pixel under it.
+    /// Returns true when the beam wrapped into a new frame.
+    pub fn clock(&mut self) -> bool {
+        let color = if self.clock < HBLANK_CLOCKS {
+            0
+        } else {
+            let blank = self.vblank
+                || self.vsync
+                || (self.hmove_blank && self.clock < HBLANK_CLOCKS + HMOVE_BLANK_CLOCKS);
+            self.visible_pixel(self.clock - HBLANK_CLOCKS, blank)
+        };
+        if self.scanline < MAX_LINES {
+            self.frame[self.scanline * CLOCKS_PER_LINE + self.clock] = color;
+        }
+
+        if audio::PHASE0_CLOCKS.contains(&self.clock) {
+            self.audio.phase0();
+        } else if audio::PHASE1_CLOCKS.contains(&self.clock) {
+            self.audio.phase1();
+        }
+
+        self.clock += 1;
+        if self.clock == CLOCKS_PER_LINE {
+            self.clock = 0;
+            self.scanline += 1;
+            self.wsync = false;
+            self.hmove_blank = false;
+            if self.scanline >= RUNAWAY_LINES {
+                self.end_frame();
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Work out which objects cover pixel `x`, latch their collisions and
+    /// return the color that wins priority.
+    fn visible_pixel(&mut self, x: usize, blank: bool) -> u8 {
+        let pf = self.playfield_bit(x);
+        let bl = self.ball_bit(x);
+        let p0 = self.player_bit(P0, x);
+        let p1 = self.player_bit(P1, x);
+        let m0 = self.missile_bit(P0, x);
+        let m1 = self.missile_bit(P1, x);
+
+        let pairs = [
+            (CXM0P, m0 && p1, m0 && p0),
+            (CXM1P, m1 && p0, m1 && p1),
+            (CXP0FB, p0 && pf, p0 && bl),
+            (CXP1FB, p1 && pf, p1 && bl),
+            (CXM0FB, m0 && pf, m0 && bl),
+            (CXM1FB, m1 && pf, m1 && bl),
+            (CXBLPF, bl && pf, false),
+            (CXPPMM, p0 && p1, m0 && m1),
+        ];
+        for &(reg, d7, d6) in pairs.iter() {
+            if d7 {
+                self.collisions[reg as usize] |= 0x80;
+            }
+            if d6 {
+                self.collisions[reg as usize] |= 0x40;
+            }
+        }
+
+        if blank {
+            return 0;
+        }
+        let priority = self.ctrlpf & 0b0000_0100 != 0;
+        let score = self.ctrlpf & 0b0000_0010 != 0;
+        let pf_color = if score && !priority {
+            if x < VISIBLE_CLOCKS / 2 { self.colup0 } else { self.colup1 }
+        } else {
+            self.colupf
+        };
+        if priority && (pf || bl) {
+            return if pf { pf_color } else { self.colupf };
+        }
+        if p0 || m0 {
+            return self.colup0;
+        }
+        if p1 || m1 {
+            return self.colup1;
+        }
+        if pf {
+            return pf_color;
+        }
+        if bl {
+            return self.colupf;
+        }
+        return self.colubk;
+    }
+
+    fn playfield_bit(&self, x: usize) -> bool {
+        let mut cell = x / 4;
+        if cell >= 20 {
+            cell = if self.ctrlpf & 1 != 0 { 39 - cell } else { cell - 20 };
+        }
+        return self.playfield & (1 << cell) != 0;
+    }
+
+    /// Offset of `x` into the copy of an object that covers it, if any.
+    fn copy_offset(&self, object: usize, nusiz: u8, width: usize, x: usize) -> Option<usize> {
+        let offset = (x + VISIBLE_CLOCKS - self.pos[object]) % VISIBLE_CLOCKS;
+        let (copies, _) = COPIES[(nusiz & 7) as usize];
+        return copies
+            .iter()
+            .find(|&&start| offset >= start && offset < start + width)
+            .map(|start| offset - start);
+    }
+
+    fn player_bit(&self, player: usize, x: usize) -> bool {
+        let (_, scale) = COPIES[(self.nusiz[player] & 7) as usize];
+        let graphics = if self.vdelp[player] { self.grp_old[player] } else { self.grp[player] };
+        if graphics == 0 {
+            return false;
+        }
+        return match self.copy_offset(player, self.nusiz[player], 8 * scale, x) {
+            Some(offset) => {
+                let bit = offset / scale;
+                let bit = if self.reflect[player] { bit } else { 7 - bit };
+                graphics & (1 << bit) != 0
+            }
+            None => false,
+        };
+    }
+
+    fn missile_bit(&self, player: usize, x: usize) -> bool {
+        if !self.enam[player] || self.resmp[player] {
+            return false;
+        }
+        let width = 1 << ((self.nusiz[player] >> 4) & 3);
+        return self.copy_offset(M0 + player, self.nusiz[player], width, x).is_some();
+    }
+
+    fn ball_bit(&self, x: usize) -> bool {
+        let enabled = if self.vdelbl { self.enabl_old } else { self.enabl };
+        if !enabled {
+            return false;
+        }
+        let width = 1 << ((self.ctrlpf >> 4) & 3);
+        return self.copy_offset(BL, 0, width, x).is_some();
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_lines = self.scanline;
+        self.frame_count += 1;
+        self.scanline = 0;
+    }
+
+    /// The `COLUxx` values of the last frame, `CLOCKS_PER_LINE` per line.
+    pub fn frame(&self) -> &[u8] {
+        return &self.frame;
+    }
+
+    /// The `COLUxx` value drawn at a position of the last frame.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        return self.frame[y * CLOCKS_PER_LINE + x];
+    }
+}
+
+impl Default for Tia {
+    fn default() -> Tia {
+        return Tia::new();
+    }
+}
+
+impl Snapshot for Tia {
+    fn save(&self, out: &mut Writer) {
+        out.usize(self.clock);
+        out.usize(self.scanline);
+        out.bool(self.wsync);
+        out.usize(self.frame_lines);
+        out.usize(self.frame_count);
+        self.audio.save(out);
+        out.bool(self.vsync);
+        out.bool(self.vblank);
+        out.bytes(&[self.colup0, self.colup1, self.colupf, self.colubk, self.ctrlpf]);
+        out.u32(self.playfield);
+        out.bytes(&self.pf);
+        out.bytes(&self.nusiz);
+        out.bytes(&self.grp);
+        out.bytes(&self.grp_old);
+        for flags in &[self.reflect, self.vdelp, self.enam, self.resmp, self.fire, self.fire_latched] {
+            out.bool(flags[0]);
+            out.bool(flags[1]);
+        }
+        out.bool(self.enabl);
+        out.bool(self.enabl_old);
+        out.bool(self.vdelbl);
+        for &pos in &self.pos {
+            out.usize(pos);
+        }
+        out.bytes(&self.motion);
+        out.bool(self.hmove_blank);
+        out.bytes(&self.collisions);
+        out.bool(self.input_latch);
+        out.bytes(&self.frame);
+    }
+
+    fn load(&mut self, input: &mut Reader) -> Result<(), StateError> {
+        self.clock = input.usize()?;
+        self.scanline = input.usize()?;
+        if self.clock >= CLOCKS_PER_LINE {
+            return Err(StateError::Invalid("TIA clock"));
+        }
+        self.wsync = input.bool()?;
+        self.frame_lines = input.usize()?;
+        self.frame_count = input.usize()?;
+        self.audio.load(input)?;
+        self.vsync = input.bool()?;
+        self.vblank = input.bool()?;
+        let mut colors = [0; 5];
+        input.fill(&mut colors)?;
+        let [colup0, colup1, colupf, colubk, ctrlpf] = colors;
+        self.colup0 = colup0;
+        self.colup1 = colup1;
+        self.colupf = colupf;
+        self.colubk = colubk;
+        self.ctrlpf = ctrlpf;
+        self.playfield = input.u32()?;
+        input.fill(&mut self.pf)?;
+        input.fill(&mut self.nusiz)?;
+        input.fill(&mut self.grp)?;
+        input.fill(&mut self.grp_old)?;
+        for flags in [
+            &mut self.reflect,
+            &mut self.vdelp,
+            &mut self.enam,
+            &mut self.resmp,
+            &mut self.fire,
+            &mut self.fire_latched,
+        ]
+        .iter_mut()
+        {
+            flags[0] = input.bool()?;
+            flags[1] = input.bool()?;
+        }
+        self.enabl = input.bool()?;
+        self.enabl_old = input.bool()?;
+        self.vdelbl = input.bool()?;
+        for pos in &mut self.pos {
+            *pos = input.usize()?;
+            if *pos >= VISIBLE_CLOCKS {
+                return Err(StateError::Invalid("object position"));
+            }
+        }
+        input.fill(&mut self.motion)?;
+        self.hmove_blank = input.bool()?;
+        input.fill(&mut self.collisions)?;
+        self.input_latch = input.bool()?;
+        input.fill(&mut self.frame)?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_lines(tia: &mut Tia, lines: usize) {
+        for _ in 0..lines * CLOCKS_PER_LINE {
+            tia.clock();
+        }
+    }
+
+    #[test]
+    fn test_vsync_ends_frame() {
+        let mut tia = Tia::new();
+        run_lines(&mut tia, 262);
+        assert_eq!(tia.write(VSYNC, 0x02), true);
+        assert_eq!(tia.frame_lines, 262);
+        assert_eq!(tia.frame_count, 1);
+        assert_eq!(tia.scanline, 0);
+        // holding VSYNC on doesn't start further frames
+        assert_eq!(tia.write(VSYNC, 0x02), false);
+        assert_eq!(tia.write(VSYNC, 0x00), false);
+    }
+
+    #[test]
+    fn test_wsync_released_at_end_of_line() {
+        let mut tia = Tia::new();
+        tia.write(WSYNC, 0);
+        assert_eq!(tia.wsync, true);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.wsync, false);
+        assert_eq!(tia.scanline, 1);
+    }
+
+    #[test]
+    fn test_background_outside_hblank() {
+        let mut tia = Tia::new();
+        tia.write(COLUBK, 0x1E);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS - 1, 0), 0);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS, 0), 0x1E);
+        tia.write(VBLANK, 0x02);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS, 1), 0);
+    }
+
+    #[test]
+    fn test_audio_samples_per_line() {
+        let mut tia = Tia::new();
+        run_lines(&mut tia, 10);
+        assert_eq!(tia.audio.take_samples().len(), 10 * audio::SAMPLES_PER_LINE);
+    }
+
+    #[test]
+    fn test_runaway_frame() {
+        let mut tia = Tia::new();
+        run_lines(&mut tia, RUNAWAY_LINES);
+        assert_eq!(tia.frame_count, 1);
+        assert_eq!(tia.frame_lines, RUNAWAY_LINES);
+    }
+
+    /// Clock the TIA to color clock `clock` of the current line.
+    fn run_to(tia: &mut Tia, clock: usize) {
+        while tia.clock != clock {
+            tia.clock();
+        }
+    }
+
+    #[test]
+    fn test_playfield_halves() {
+        let mut tia = Tia::new();
+        tia.write(COLUPF, 0x44);
+        tia.write(PF0, 0x10);
+        tia.write(PF2, 0x80);
+        run_lines(&mut tia, 1);
+        // PF0 bit 4 is the leftmost cell, PF2 bit 7 the one just left of center
+        assert_eq!(tia.pixel(HBLANK_CLOCKS, 0), 0x44);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 4, 0), 0);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 76, 0), 0x44);
+        // the right half repeats the left unless reflected
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 80, 0), 0x44);
+        tia.write(CTRLPF, 0x01);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 80, 1), 0x44);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 156, 1), 0x44);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 84, 1), 0);
+    }
+
+    #[test]
+    fn test_player_position_and_copies() {
+        let mut tia = Tia::new();
+        tia.write(COLUP0, 0x1E);
+        tia.write(GRP0, 0b1000_0001);
+        tia.write(NUSIZ0, 0x01);
+        run_to(&mut tia, HBLANK_CLOCKS + 40);
+        tia.write(RESP0, 0);
+        run_to(&mut tia, 0);
+        run_lines(&mut tia, 1);
+        let left = HBLANK_CLOCKS + 45;
+        assert_eq!(tia.pixel(left, 1), 0x1E);
+        assert_eq!(tia.pixel(left + 1, 1), 0);
+        assert_eq!(tia.pixel(left + 7, 1), 0x1E);
+        assert_eq!(tia.pixel(left + 16, 1), 0x1E);
+        assert_eq!(tia.pixel(left + 32, 1), 0);
+    }
+
+    #[test]
+    fn test_hmove_and_extended_blank() {
+        let mut tia = Tia::new();
+        tia.write(COLUBK, 0x0E);
+        tia.write(ENABL, 0x02);
+        tia.write(COLUPF, 0x88);
+        run_to(&mut tia, HBLANK_CLOCKS + 20);
+        tia.write(RESBL, 0);
+        run_to(&mut tia, 0);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 24, 1), 0x88);
+        tia.write(HMBL, 0x30);
+        tia.write(HMOVE, 0);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 21, 2), 0x88);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + 24, 2), 0x0E);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS, 2), 0);
+        assert_eq!(tia.pixel(HBLANK_CLOCKS + HMOVE_BLANK_CLOCKS, 2), 0x0E);
+    }
+
+    #[test]
+    fn test_vertical_delay() {
+        let mut tia = Tia::new();
+        tia.write(VDELP0, 1);
+        tia.write(GRP0, 0xFF);
+        assert_eq!(tia.player_bit(P0, tia.pos[P0]), false);
+        tia.write(GRP1, 0);
+        assert_eq!(tia.player_bit(P0, tia.pos[P0]), true);
+    }
+
+    #[test]
+    fn test_collisions_latch_until_cleared() {
+        let mut tia = Tia::new();
+        tia.write(GRP0, 0xFF);
+        tia.write(PF0, 0xF0);
+        run_lines(&mut tia, 1);
+        assert_eq!(tia.read(CXP0FB), 0x80);
+        assert_eq!(tia.read(CXPPMM), 0);
+        tia.write(CXCLR, 0);
+        assert_eq!(tia.read(CXP0FB), 0);
+    }
+
+    #[test]
+    fn test_fire_button_latch() {
+        let mut tia = Tia::new();
+        assert_eq!(tia.read(INPT4), 0x80);
+        tia.set_fire(0, true);
+        assert_eq!(tia.read(INPT4), 0);
+        tia.write(VBLANK, 0x40);
+        tia.set_fire(0, true);
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(INPT4), 0);
+        assert_eq!(tia.read(INPT5), 0x80);
+        tia.write(VBLANK, 0);
+        assert_eq!(tia.read(INPT4), 0x80);
+    }
+}
@@ -0,0 +1,136 @@
+//! Per-instruction execution traces in the layout of nestest.log, so a run
+//! can be diffed line by line against a reference emulator's log:
+//!
+//! ```text
+//! F000  A9 00     LDA #$00                        A:00 X:00 Y:00 P:24 SP:FD TIA:  0, 21 CYC:7
+//! ```
+//!
+//! Each line is the state before the instruction executes. Where nestest
+//! gives the PPU's scanline and dot, `TIA:` gives the scanline and color
+//! clock. Lines are written straight to stderr rather than through `log`, so
+//! that builds with `log`'s lower levels compiled out, as release builds of
+//! the frontend are, still trace.
+
+use std::io::{self, Write};
+
+use crate::debugger::parse_number;
+use crate::disasm;
+use crate::machine::Machine;
+
+/// Column the registers start in, as in nestest.log.
+const REGISTERS_COLUMN: usize = 48;
+
+/// Which instructions get traced: all of them, or those whose address is in
+/// one of a set of ranges. Addresses are compared on the 13 lines the 2600
+/// decodes, so `$F000-$F0FF` also matches code running at `$1000`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tracer {
+    ranges: Vec<(u16, u16)>,
+}
+
+impl Tracer {
+    /// A tracer for every instruction.
+    pub fn new() -> Tracer {
+        return Tracer::default();
+    }
+
+    /// Only trace instructions from `first` to `last` inclusive, along with
+    /// any other ranges added.
+    pub fn add_range(&mut self, first: u16, last: u16) {
+        self.ranges.push((first & 0x1FFF, last & 0x1FFF));
+    }
+
+    /// Add a range written as `first-last`, or a single address, in the
+    /// debugger's number syntax.
+    pub fn add_range_text(&mut self, text: &str) -> Result<(), String> {
+        let mut ends = text.splitn(2, '-');
+        let first = parse_number(ends.next().unwrap_or(""))?;
+        let last = match ends.next() {
+            Some(last) => parse_number(last)?,
+            None => first,
+        };
+        if first > 0xFFFF || last > 0xFFFF || first > last {
+            return Err(format!("invalid address range: {}", text));
+        }
+        self.add_range(first as u16, last as u16);
+        return Ok(());
+    }
+
+    pub fn matches(&self, pc: u16) -> bool {
+        let pc = pc & 0x1FFF;
+        return self.ranges.is_empty() || self.ranges.iter().any(|&(first, last)| pc >= first && pc <= last);
+    }
+}
+
+/// The trace line for the instruction `machine` is about to execute.
+pub fn line(machine: &Machine) -> String {
+    let cpu = &machine.cpu;
+    let tia = machine.tia();
+    let read = |addr: u16| machine.board.peek(addr);
+    let instruction = disasm::instruction(&read, cpu.pc as u16).to_string();
+    return format!(
+        "{:<width$}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} TIA:{:3},{:3} CYC:{}",
+        instruction,
+        cpu.a_reg,
+        cpu.x_reg,
+        cpu.y_reg,
+        cpu.flags,
+        cpu.s_pnt,
+        tia.scanline,
+        tia.clock,
+        machine.board.cycles,
+        width = REGISTERS_COLUMN
+    );
+}
+
+/// Write the line for the instruction `machine` is about to execute to
+/// stderr if it has a tracer that selects it.
+pub fn record(machine: &Machine) {
+    if let Some(tracer) = &machine.tracer {
+        if tracer.matches(machine.cpu.pc as u16) {
+            // a closed stderr isn't worth stopping the emulation for
+            let _ = writeln!(io::stderr().lock(), "{}", line(machine));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::properties::Properties;
+
+    #[test]
+    fn test_line() {
+        let mut rom = vec![0; 0x1000];
+        // LDA #$00; STA WSYNC
+        rom[..4].copy_from_slice(&[0xA9, 0x00, 0x85, 0x02]);
+        rom[0xFFD] = 0xF0;
+        let mut machine = Machine::new(Cartridge::new(rom, Properties::new("")));
+        assert_eq!(
+            line(&machine),
+            "F000  A9 00     LDA #$00                        A:00 X:00 Y:00 P:24 SP:FD TIA:  0, 21 CYC:7"
+        );
+        machine.step_instruction().unwrap();
+        assert_eq!(
+            line(&machine),
+            "F002  85 02     STA WSYNC                       A:00 X:00 Y:00 P:26 SP:FD TIA:  0, 27 CYC:9"
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut tracer = Tracer::new();
+        assert!(tracer.matches(0xF123));
+        tracer.add_range_text("$F000-$F0FF").unwrap();
+        tracer.add_range_text("0xF800").unwrap();
+        assert!(tracer.matches(0xF000));
+        assert!(tracer.matches(0x10FF));
+        assert!(!tracer.matches(0xF100));
+        assert!(tracer.matches(0xF800));
+        assert!(!tracer.matches(0xF801));
+        assert!(tracer.add_range_text("$F100-$F000").is_err());
+        assert!(tracer.add_range_text("$F000-").is_err());
+        assert!(tracer.add_range_text("nope").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use crate::properties::DisplayFormat;
+use crate::tia::CLOCKS_PER_LINE;
+
+/// An RGB triple for each of the 128 colors the TIA can produce, indexed by
+/// `COLUxx >> 1` (high nibble hue, bits 1-3 luma).
+pub type Palette = [[u8; 3]; 128];
+
+/// Frames with at least this many scanlines are taken to be PAL/SECAM.
+const PAL_DETECT_LINES: usize = 287;
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum TvStandard {
+    NTSC,
+    PAL,
+    SECAM,
+}
+
+impl TvStandard {
+    /// Nominal number of scanlines per frame.
+    pub fn scanlines(&self) -> usize {
+        match self {
+            TvStandard::NTSC => 262,
+            TvStandard::PAL | TvStandard::SECAM => 312,
+        }
+    }
+
+    /// Nominal field rate in Hz.
+    pub fn frame_rate(&self) -> u64 {
+        match self {
+            TvStandard::NTSC => 60,
+            TvStandard::PAL | TvStandard::SECAM => 50,
+        }
+    }
+
+    /// The scanlines a TV shows, as the first and how many, counting from
+    /// the start of VSYNC. This is the usual picture with a few lines of
+    /// blanking and overscan either side, for games that draw a little
+    /// more than the standard asks.
+    pub fn visible_lines(&self) -> (usize, usize) {
+        match self {
+            TvStandard::NTSC => (31, 210),
+            TvStandard::PAL | TvStandard::SECAM => (37, 250),
+        }
+    }
+
+    /// How many times wider than tall a color clock's pixel is shown. An
+    /// NTSC set spreads the TIA's 160 visible clocks over about 1.71 times
+    /// the width their lines take; a PAL set fits 288 lines where NTSC fits
+    /// 240, so its pixels come out a fifth wider again.
+    pub fn pixel_aspect(&self) -> f64 {
+        match self {
+            TvStandard::NTSC => 12.0 / 7.0,
+            TvStandard::PAL | TvStandard::SECAM => 12.0 / 7.0 * 288.0 / 240.0,
+        }
+    }
+
+    /// The TIA's color clock in Hz, set by the console's crystal.
+    pub fn color_clock(&self) -> u64 {
+        match self {
+            TvStandard::NTSC => 3_579_545,
+            TvStandard::PAL | TvStandard::SECAM => 3_546_894,
+        }
+    }
+
+    /// How long a frame of `lines` scanlines takes on the real console.
+    /// Games set their own line count, so an NTSC game drawing 262 lines
+    /// runs at about 59.92 frames a second. Before the first frame has
+    /// ended, the nominal count is used.
+    pub fn frame_duration(&self, lines: usize) -> Duration {
+        let lines = if lines == 0 { self.scanlines() } else { lines };
+        let clocks = (lines * CLOCKS_PER_LINE) as u64;
+        return Duration::from_nanos(clocks * 1_000_000_000 / self.color_clock());
+    }
+
+    pub fn palette(&self) -> &'static Palette {
+        match self {
+            TvStandard::NTSC => &NTSC_PALETTE,
+            TvStandard::PAL => &PAL_PALETTE,
+            TvStandard::SECAM => &SECAM_PALETTE,
+        }
+    }
+
+    /// Guess the standard from the number of scanlines in a frame. SECAM has
+    /// the same timing as PAL, so it can only be selected explicitly.
+    pub fn detect(scanlines: usize) -> TvStandard {
+        if scanlines >= PAL_DETECT_LINES {
+            TvStandard::PAL
+        } else {
+            TvStandard::NTSC
+        }
+    }
+
+    /// Whether a frame of `lines` scanlines shows without color. A PAL set
+    /// can't decode color when the line count is odd.
+    pub fn color_loss(&self, lines: usize) -> bool {
+        return *self == TvStandard::PAL && lines % 2 == 1;
+    }
+
+    /// The standard forced by a cartridge's `Display.Format`, if any.
+    pub fn from_format(format: DisplayFormat) -> Option<TvStandard> {
+        match format {
+            DisplayFormat::AUTO => None,
+            DisplayFormat::NTSC | DisplayFormat::NTSC50 => Some(TvStandard::NTSC),
+            DisplayFormat::PAL | DisplayFormat::PAL60 => Some(TvStandard::PAL),
+            DisplayFormat::SECAM | DisplayFormat::SECAM60 => Some(TvStandard::SECAM),
+        }
+    }
+}
+
+/// Build a `Palette` from 0xRRGGBB values.
+const fn unpack(colors: [u32; 128]) -> Palette {
+    let mut palette = [[0u8; 3]; 128];
+    let mut i = 0;
+    while i < 128 {
+        palette[i] = [(colors[i] >> 16) as u8, (colors[i] >> 8) as u8, colors[i] as u8];
+        i += 1;
+    }
+    return palette;
+}
+
+pub static NTSC_PALETTE: Palette = unpack([
+    0x000000, 0x4a4a4a, 0x6f6f6f, 0x8e8e8e, 0xaaaaaa, 0xc0c0c0, 0xd6d6d6, 0xececec,
+    0x484800, 0x69690f, 0x86861d, 0xa2a22a, 0xbbbb35, 0xd2d240, 0xe8e84a, 0xfcfc54,
+    0x7c2c00, 0x904811, 0xa26221, 0xb47a30, 0xc3903d, 0xd2a44a, 0xdfb755, 0xecc860,
+    0x901c00, 0xa33915, 0xb55328, 0xc66c3a, 0xd5824a, 0xe39759, 0xf0aa67, 0xfcbc74,
+    0x940000, 0xa71a1a, 0xb83232, 0xc84848, 0xd65c5c, 0xe46f6f, 0xf08080, 0xfc9090,
+    0x840064, 0x97197a, 0xa8308f, 0xb846a2, 0xc659b3, 0xd46cc3, 0xe07cd2, 0xec8ce0,
+    0x500084, 0x68199a, 0x7d30ad, 0x9246c0, 0xa459d0, 0xb56ce0, 0xc57cee, 0xd48cfc,
+    0x140090, 0x331aa3, 0x4e32b5, 0x6848c6, 0x7f5cd5, 0x956fe3, 0xa980f0, 0xbc90fc,
+    0x000094, 0x181aa7, 0x2d32b8, 0x4248c8, 0x545cd6, 0x656fe4, 0x7580f0, 0x8490fc,
+    0x001c88, 0x183b9d, 0x2d57b0, 0x4272c2, 0x548ad2, 0x65a0e1, 0x75b5ef, 0x84c8fc,
+    0x003064, 0x185080, 0x2d6d98, 0x4288b0, 0x54a0c5, 0x65b7d9, 0x75cceb, 0x84e0fc,
+    0x004030, 0x18624e, 0x2d8169, 0x429e82, 0x54b899, 0x65d1ae, 0x75e7c2, 0x84fcd4,
+    0x004400, 0x1a661a, 0x328432, 0x48a048, 0x5cba5c, 0x6fd26f, 0x80e880, 0x90fc90,
+    0x143c00, 0x355f18, 0x527e2d, 0x6e9c42, 0x87b754, 0x9ed065, 0xb4e775, 0xc8fc84,
+    0x303800, 0x505916, 0x6d762b, 0x88923e, 0xa0ab4f, 0xb7c25f, 0xccd86e, 0xe0ec7c,
+    0x482c00, 0x694d14, 0x866a26, 0xa28638, 0xbb9f47, 0xd2b656, 0xe8cc63, 0xfce070,
+]);
+
+pub static PAL_PALETTE: Palette = unpack([
+    0x000000, 0x2b2b2b, 0x525252, 0x767676, 0x979797, 0xb6b6b6, 0xd2d2d2, 0xececec,
+    0x000000, 0x2b2b2b, 0x525252, 0x767676, 0x979797, 0xb6b6b6, 0xd2d2d2, 0xececec,
+    0x805800, 0x96711a, 0xab8732, 0xbe9c48, 0xcfaf5c, 0xdfc06f, 0xeed180, 0xfce090,
+    0x445c00, 0x5e791a, 0x769332, 0x8cac48, 0xa0c25c, 0xb3d76f, 0xc4ea80, 0xd4fc90,
+    0x703400, 0x89511a, 0xa06b32, 0xb68448, 0xc99a5c, 0xdcaf6f, 0xecc280, 0xfcd490,
+    0x006414, 0x1a8035, 0x329852, 0x48b06e, 0x5cc587, 0x6fd99e, 0x80ebb4, 0x90fcc8,
+    0x700014, 0x891a35, 0xa03252, 0xb6486e, 0xc95c87, 0xdc6f9e, 0xec80b4, 0xfc90c8,
+    0x005c5c, 0x1a7676, 0x328e8e, 0x48a4a4, 0x5cb8b8, 0x6fcbcb, 0x80dcdc, 0x90ecec,
+    0x70005c, 0x841a74, 0x963289, 0xa8489e, 0xb75cb0, 0xc66fc1, 0xd380d1, 0xe090e0,
+    0x003c70, 0x195a89, 0x2f75a0, 0x448eb6, 0x57a5c9, 0x68badc, 0x79ceec, 0x88e0fc,
+    0x580070, 0x6e1a89, 0x8332a0, 0x9648b6, 0xa75cc9, 0xb76fdc, 0xc680ec, 0xd490fc,
+    0x002070, 0x193f89, 0x2f5aa0, 0x4474b6, 0x578bc9, 0x68a1dc, 0x79b5ec, 0x88c8fc,
+    0x340080, 0x4a1a96, 0x5f32ab, 0x7248be, 0x835ccf, 0x936fdf, 0xa280ee, 0xb090fc,
+    0x000088, 0x1a1a9d, 0x3232b0, 0x4848c2, 0x5c5cd2, 0x6f6fe1, 0x8080ef, 0x9090fc,
+    0x000000, 0x2b2b2b, 0x525252, 0x767676, 0x979797, 0xb6b6b6, 0xd2d2d2, 0xececec,
+    0x000000, 0x2b2b2b, 0x525252, 0x767676, 0x979797, 0xb6b6b6, 0xd2d2d2, 0xececec,
+]);
+
+/// SECAM only decodes the luma bits, each of which maps to one of 8 colors.
+pub static SECAM_PALETTE: Palette = unpack([
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(TvStandard::detect(262), TvStandard::NTSC);
+        assert_eq!(TvStandard::detect(270), TvStandard::NTSC);
+        assert_eq!(TvStandard::detect(312), TvStandard::PAL);
+    }
+
+    #[test]
+    fn test_color_loss() {
+        assert_eq!(TvStandard::PAL.color_loss(311), true);
+        assert_eq!(TvStandard::PAL.color_loss(312), false);
+        assert_eq!(TvStandard::NTSC.color_loss(263), false);
+    }
+
+    #[test]
+    fn test_frame_duration() {
+        assert_eq!(TvStandard::NTSC.frame_duration(262), Duration::from_nanos(16_688_154));
+        assert_eq!(TvStandard::NTSC.frame_duration(0), TvStandard::NTSC.frame_duration(262));
+        assert_eq!(TvStandard::PAL.frame_duration(312), Duration::from_nanos(20_055_857));
+        assert!(TvStandard::NTSC.frame_duration(270) > TvStandard::NTSC.frame_duration(262));
+    }
+
+    #[test]
+    fn test_from_format() {
+        assert_eq!(TvStandard::from_format(DisplayFormat::AUTO), None);
+        assert_eq!(TvStandard::from_format(DisplayFormat::PAL60), Some(TvStandard::PAL));
+        assert_eq!(TvStandard::from_format(DisplayFormat::SECAM), Some(TvStandard::SECAM));
+    }
+}
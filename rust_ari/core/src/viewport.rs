@@ -0,0 +1,182 @@
+//! Fitting the picture to a window: cropping the frame to what a TV shows,
+//! widening the TIA's pixels to their shape on screen and scaling it up.
+
+use crate::properties::Properties;
+use crate::tia::{CLOCKS_PER_LINE, HBLANK_CLOCKS, VISIBLE_CLOCKS};
+use crate::tv::TvStandard;
+
+/// A rectangle of the frame, in color clocks and scanlines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Crop {
+    /// The whole frame, with horizontal blanking, VSYNC and overscan.
+    pub fn full(standard: TvStandard) -> Crop {
+        return Crop { x: 0, y: 0, width: CLOCKS_PER_LINE, height: standard.scanlines() };
+    }
+
+    /// The part of the frame a TV shows: past horizontal blanking, over the
+    /// lines the cartridge's properties give or else the standard's usual
+    /// ones.
+    pub fn visible(standard: TvStandard, properties: &Properties) -> Crop {
+        let (first, lines) = standard.visible_lines();
+        return Crop {
+            x: HBLANK_CLOCKS,
+            y: properties.y_start.map_or(first, |y| y as usize),
+            width: VISIBLE_CLOCKS,
+            height: properties.height.map_or(lines, |height| height as usize).max(1),
+        };
+    }
+
+    /// Copy this part of `frame`, RGBA `CLOCKS_PER_LINE` pixels wide, into a
+    /// buffer of its own. Lines past the end of the frame are black.
+    pub fn apply(&self, frame: &[u8]) -> Vec<u8> {
+        let mut out = vec![0; self.width * self.height * 4];
+        for (row, line) in out.chunks_exact_mut(self.width * 4).enumerate() {
+            let start = ((self.y + row) * CLOCKS_PER_LINE + self.x) * 4;
+            if let Some(src) = frame.get(start..start + self.width * 4) {
+                line.copy_from_slice(src);
+            }
+        }
+        return out;
+    }
+}
+
+/// How the picture is scaled up to the window.
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    /// By a whole number of times its height, each pixel the nearest one
+    /// in the picture, for sharp even lines.
+    INTEGER,
+    /// To fill as much of the window as it can, blending neighbouring
+    /// pixels.
+    SMOOTH,
+}
+
+/// Where the picture goes in a window, centered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Fit a picture of `width` by `height` pixels, each `aspect` times as wide
+/// as it is tall, into a window. Integer scaling doesn't go below the
+/// picture's own size unless the window is smaller, when it's squeezed in.
+pub fn fit(width: usize, height: usize, aspect: f64, window_width: usize, window_height: usize, scaling: Scaling) -> Placement {
+    let shown_width = width as f64 * aspect;
+    let fits = (window_width as f64 / shown_width).min(window_height as f64 / height as f64);
+    let factor = match scaling {
+        Scaling::INTEGER => fits.floor().max(1.0),
+        Scaling::SMOOTH => fits,
+    };
+    let placed_width = ((shown_width * factor).round() as usize).clamp(1, window_width.max(1));
+    let placed_height = ((height as f64 * factor).round() as usize).clamp(1, window_height.max(1));
+    return Placement {
+        x: (window_width - placed_width) / 2,
+        y: (window_height - placed_height) / 2,
+        width: placed_width,
+        height: placed_height,
+    };
+}
+
+/// Draw `picture`, RGBA `width` pixels wide, into `window`, RGBA
+/// `window_width` pixels wide, at `placement`, with black around it.
+pub fn scale(picture: &[u8], width: usize, window: &mut [u8], window_width: usize, placement: Placement, scaling: Scaling) {
+    let height = picture.len() / 4 / width;
+    for pixel in window.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[0x00, 0x00, 0x00, 0xFF]);
+    }
+    if height == 0 || window_width == 0 {
+        return;
+    }
+    // where each column and line of the placement samples the picture
+    let source = |i: usize, placed: usize, size: usize| (i as f64 + 0.5) * size as f64 / placed as f64 - 0.5;
+    let columns: Vec<f64> = (0..placement.width).map(|x| source(x, placement.width, width)).collect();
+    let at = |x: usize, y: usize| &picture[(y * width + x) * 4..(y * width + x) * 4 + 4];
+
+    for row in 0..placement.height {
+        let y = source(row, placement.height, height);
+        let start = ((placement.y + row) * window_width + placement.x) * 4;
+        let line = match window.get_mut(start..start + placement.width * 4) {
+            Some(line) => line,
+            None => break,
+        };
+        for (pixel, &x) in line.chunks_exact_mut(4).zip(columns.iter()) {
+            match scaling {
+                Scaling::INTEGER => {
+                    let nearest = |v: f64, size: usize| (v.round().max(0.0) as usize).min(size - 1);
+                    pixel.copy_from_slice(at(nearest(x, width), nearest(y, height)));
+                }
+                Scaling::SMOOTH => {
+                    let (x, y) = (x.clamp(0.0, (width - 1) as f64), y.clamp(0.0, (height - 1) as f64));
+                    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+                    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+                    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+                    for (channel, value) in pixel.iter_mut().enumerate() {
+                        let top = at(x0, y0)[channel] as f64 * (1.0 - fx) + at(x1, y0)[channel] as f64 * fx;
+                        let bottom = at(x0, y1)[channel] as f64 * (1.0 - fx) + at(x1, y1)[channel] as f64 * fx;
+                        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop() {
+        let mut properties = Properties::new("");
+        let crop = Crop::visible(TvStandard::NTSC, &properties);
+        assert_eq!(crop, Crop { x: 68, y: 31, width: 160, height: 210 });
+        properties.y_start = Some(40);
+        properties.height = Some(192);
+        assert_eq!(Crop::visible(TvStandard::PAL, &properties), Crop { x: 68, y: 40, width: 160, height: 192 });
+        assert_eq!(Crop::full(TvStandard::PAL), Crop { x: 0, y: 0, width: 228, height: 312 });
+
+        let mut frame = vec![0; CLOCKS_PER_LINE * 2 * 4];
+        frame[(CLOCKS_PER_LINE + 69) * 4] = 7;
+        let out = Crop { x: 68, y: 1, width: 2, height: 2 }.apply(&frame);
+        // the second line is past the end of the frame
+        assert_eq!(out, vec![0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_fit() {
+        // 160 by 210 with 12/7 wide pixels is about 274 by 210
+        let integer = fit(160, 210, 12.0 / 7.0, 1280, 720, Scaling::INTEGER);
+        assert_eq!(integer, Placement { x: 228, y: 45, width: 823, height: 630 });
+        let smooth = fit(160, 210, 12.0 / 7.0, 1280, 720, Scaling::SMOOTH);
+        assert_eq!(smooth, Placement { x: 170, y: 0, width: 940, height: 720 });
+        // a window too small gets the picture squeezed in
+        let small = fit(160, 210, 1.0, 100, 100, Scaling::INTEGER);
+        assert_eq!((small.width, small.height), (100, 100));
+    }
+
+    #[test]
+    fn test_scale() {
+        let picture = [10, 10, 10, 255, 50, 50, 50, 255];
+        let mut window = vec![1; 6 * 2 * 4];
+        let placement = Placement { x: 1, y: 0, width: 4, height: 2 };
+        scale(&picture, 2, &mut window, 6, placement, Scaling::INTEGER);
+        let line: Vec<u8> = window[..24].chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(line, vec![0, 10, 10, 50, 50, 0]);
+        assert_eq!(window[..24], window[24..]);
+        assert_eq!(window[3], 0xFF);
+
+        scale(&picture, 2, &mut window, 6, placement, Scaling::SMOOTH);
+        let line: Vec<u8> = window[..24].chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(line, vec![0, 10, 20, 40, 50, 0]);
+    }
+}
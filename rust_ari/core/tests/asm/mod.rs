@@ -0,0 +1,410 @@
+//! A small two-pass 6502 assembler for the test programs in `tests/roms`, so
+//! they're built from their source on every run rather than checked in as
+//! binaries. It knows the documented NMOS opcodes and little else:
+//!
+//! - `name:` defines a label and `name = expr` a constant
+//! - `.org expr` moves where the lines after it go; `.byte` and `.word` lay
+//!   down data
+//! - expressions are numbers (`$FF`, `%1010`, `255`, `'A'`), names and `*`,
+//!   the address of the line, joined with `+`, `-` and `|`; a leading `<` or
+//!   `>` takes the low or high byte of the whole expression
+//! - an address that fits in zero page uses the zero page form if there is
+//!   one; `a:expr` asks for the absolute form
+//!
+//! The opcode table is its own rather than the CPU's, so that a mistake in
+//! one isn't hidden by the same mistake in the other.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Imp,
+    Acc,
+    Imm,
+    Zp,
+    Zpx,
+    Zpy,
+    Abs,
+    Absx,
+    Absy,
+    Ind,
+    Indx,
+    Indy,
+    Rel,
+}
+
+use Mode::*;
+
+#[rustfmt::skip]
+const OPCODES: &[(&str, Mode, u8)] = &[
+    ("ADC", Imm, 0x69), ("ADC", Zp, 0x65), ("ADC", Zpx, 0x75), ("ADC", Abs, 0x6D),
+    ("ADC", Absx, 0x7D), ("ADC", Absy, 0x79), ("ADC", Indx, 0x61), ("ADC", Indy, 0x71),
+    ("AND", Imm, 0x29), ("AND", Zp, 0x25), ("AND", Zpx, 0x35), ("AND", Abs, 0x2D),
+    ("AND", Absx, 0x3D), ("AND", Absy, 0x39), ("AND", Indx, 0x21), ("AND", Indy, 0x31),
+    ("ASL", Acc, 0x0A), ("ASL", Zp, 0x06), ("ASL", Zpx, 0x16), ("ASL", Abs, 0x0E), ("ASL", Absx, 0x1E),
+    ("BCC", Rel, 0x90), ("BCS", Rel, 0xB0), ("BEQ", Rel, 0xF0), ("BMI", Rel, 0x30),
+    ("BNE", Rel, 0xD0), ("BPL", Rel, 0x10), ("BVC", Rel, 0x50), ("BVS", Rel, 0x70),
+    ("BIT", Zp, 0x24), ("BIT", Abs, 0x2C),
+    ("BRK", Imp, 0x00),
+    ("CLC", Imp, 0x18), ("CLD", Imp, 0xD8), ("CLI", Imp, 0x58), ("CLV", Imp, 0xB8),
+    ("CMP", Imm, 0xC9), ("CMP", Zp, 0xC5), ("CMP", Zpx, 0xD5), ("CMP", Abs, 0xCD),
+    ("CMP", Absx, 0xDD), ("CMP", Absy, 0xD9), ("CMP", Indx, 0xC1), ("CMP", Indy, 0xD1),
+    ("CPX", Imm, 0xE0), ("CPX", Zp, 0xE4), ("CPX", Abs, 0xEC),
+    ("CPY", Imm, 0xC0), ("CPY", Zp, 0xC4), ("CPY", Abs, 0xCC),
+    ("DEC", Zp, 0xC6), ("DEC", Zpx, 0xD6), ("DEC", Abs, 0xCE), ("DEC", Absx, 0xDE),
+    ("DEX", Imp, 0xCA), ("DEY", Imp, 0x88),
+    ("EOR", Imm, 0x49), ("EOR", Zp, 0x45), ("EOR", Zpx, 0x55), ("EOR", Abs, 0x4D),
+    ("EOR", Absx, 0x5D), ("EOR", Absy, 0x59), ("EOR", Indx, 0x41), ("EOR", Indy, 0x51),
+    ("INC", Zp, 0xE6), ("INC", Zpx, 0xF6), ("INC", Abs, 0xEE), ("INC", Absx, 0xFE),
+    ("INX", Imp, 0xE8), ("INY", Imp, 0xC8),
+    ("JMP", Abs, 0x4C), ("JMP", Ind, 0x6C), ("JSR", Abs, 0x20),
+    ("LDA", Imm, 0xA9), ("LDA", Zp, 0xA5), ("LDA", Zpx, 0xB5), ("LDA", Abs, 0xAD),
+    ("LDA", Absx, 0xBD), ("LDA", Absy, 0xB9), ("LDA", Indx, 0xA1), ("LDA", Indy, 0xB1),
+    ("LDX", Imm, 0xA2), ("LDX", Zp, 0xA6), ("LDX", Zpy, 0xB6), ("LDX", Abs, 0xAE), ("LDX", Absy, 0xBE),
+    ("LDY", Imm, 0xA0), ("LDY", Zp, 0xA4), ("LDY", Zpx, 0xB4), ("LDY", Abs, 0xAC), ("LDY", Absx, 0xBC),
+    ("LSR", Acc, 0x4A), ("LSR", Zp, 0x46), ("LSR", Zpx, 0x56), ("LSR", Abs, 0x4E), ("LSR", Absx, 0x5E),
+    ("NOP", Imp, 0xEA),
+    ("ORA", Imm, 0x09), ("ORA", Zp, 0x05), ("ORA", Zpx, 0x15), ("ORA", Abs, 0x0D),
+    ("ORA", Absx, 0x1D), ("ORA", Absy, 0x19), ("ORA", Indx, 0x01), ("ORA", Indy, 0x11),
+    ("PHA", Imp, 0x48), ("PHP", Imp, 0x08), ("PLA", Imp, 0x68), ("PLP", Imp, 0x28),
+    ("ROL", Acc, 0x2A), ("ROL", Zp, 0x26), ("ROL", Zpx, 0x36), ("ROL", Abs, 0x2E), ("ROL", Absx, 0x3E),
+    ("ROR", Acc, 0x6A), ("ROR", Zp, 0x66), ("ROR", Zpx, 0x76), ("ROR", Abs, 0x6E), ("ROR", Absx, 0x7E),
+    ("RTI", Imp, 0x40), ("RTS", Imp, 0x60),
+    ("SBC", Imm, 0xE9), ("SBC", Zp, 0xE5), ("SBC", Zpx, 0xF5), ("SBC", Abs, 0xED),
+    ("SBC", Absx, 0xFD), ("SBC", Absy, 0xF9), ("SBC", Indx, 0xE1), ("SBC", Indy, 0xF1),
+    ("SEC", Imp, 0x38), ("SED", Imp, 0xF8), ("SEI", Imp, 0x78),
+    ("STA", Zp, 0x85), ("STA", Zpx, 0x95), ("STA", Abs, 0x8D), ("STA", Absx, 0x9D),
+    ("STA", Absy, 0x99), ("STA", Indx, 0x81), ("STA", Indy, 0x91),
+    ("STX", Zp, 0x86), ("STX", Zpy, 0x96), ("STX", Abs, 0x8E),
+    ("STY", Zp, 0x84), ("STY", Zpx, 0x94), ("STY", Abs, 0x8C),
+    ("TAX", Imp, 0xAA), ("TAY", Imp, 0xA8), ("TSX", Imp, 0xBA),
+    ("TXA", Imp, 0x8A), ("TXS", Imp, 0x9A), ("TYA", Imp, 0x98),
+];
+
+fn opcode(mnemonic: &str, mode: Mode) -> Option<u8> {
+    return OPCODES
+        .iter()
+        .find(|(name, m, _)| *name == mnemonic && *m == mode)
+        .map(|(_, _, opcode)| *opcode);
+}
+
+fn length(mode: Mode) -> u16 {
+    return match mode {
+        Imp | Acc => 1,
+        Abs | Absx | Absy | Ind => 3,
+        _ => 2,
+    };
+}
+
+/// An assembled program: a 64K image and where its labels ended up.
+pub struct Program {
+    /// Zero wherever the source put nothing.
+    pub image: Vec<u8>,
+    symbols: HashMap<String, i64>,
+    /// The source line each instruction came from, by its address.
+    lines: HashMap<u16, (usize, String)>,
+}
+
+impl Program {
+    /// The value of a label or constant, which the source must define.
+    pub fn symbol(&self, name: &str) -> u16 {
+        return *self.symbols.get(name).unwrap_or_else(|| panic!("no symbol {}", name)) as u16;
+    }
+
+    /// The source line of the instruction at `addr`, to point at a failing
+    /// test.
+    pub fn line_at(&self, addr: u16) -> String {
+        return match self.lines.get(&addr) {
+            Some((number, text)) => format!("line {}: {}", number, text.trim()),
+            None => "no instruction there".to_string(),
+        };
+    }
+}
+
+/// What a line of source asks for, once its label is taken off.
+enum Statement {
+    Nothing,
+    Constant(String, String),
+    Org(String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Instruction(String, Mode, String),
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    return matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+/// Split `line` into its label, if any, and what follows it.
+fn parse_line(line: &str) -> Result<(Option<String>, Statement), String> {
+    let code = match line.find(';') {
+        Some(at) if !line[..at].ends_with('\'') => &line[..at],
+        _ => line,
+    };
+    let mut code = code.trim();
+    let mut label = None;
+    if let Some(at) = code.find(':') {
+        if is_name(&code[..at]) {
+            label = Some(code[..at].to_string());
+            code = code[at + 1..].trim();
+        }
+    }
+    if code.is_empty() {
+        return Ok((label, Statement::Nothing));
+    }
+    if let Some(at) = code.find('=') {
+        let name = code[..at].trim();
+        if !is_name(name) || label.is_some() {
+            return Err(format!("bad constant: {}", code));
+        }
+        return Ok((None, Statement::Constant(name.to_string(), code[at + 1..].trim().to_string())));
+    }
+
+    let (word, rest) = match code.find(char::is_whitespace) {
+        Some(at) => (&code[..at], code[at..].trim()),
+        None => (code, ""),
+    };
+    let list = || rest.split(',').map(|item| item.trim().to_string()).collect();
+    let statement = match word.to_ascii_lowercase().as_str() {
+        ".org" => Statement::Org(rest.to_string()),
+        ".byte" => Statement::Bytes(list()),
+        ".word" => Statement::Words(list()),
+        _ => {
+            let mnemonic = word.to_ascii_uppercase();
+            let (mode, operand) = parse_operand(&mnemonic, rest)?;
+            Statement::Instruction(mnemonic, mode, operand)
+        }
+    };
+    return Ok((label, statement));
+}
+
+/// Work out an instruction's addressing mode from the shape of its operand.
+/// Zero page and absolute are told apart later, once the address is known,
+/// and an absolute mode is returned only when it's asked for with `a:`.
+fn parse_operand(mnemonic: &str, operand: &str) -> Result<(Mode, String), String> {
+    let upper = operand.to_ascii_uppercase().replace(' ', "");
+    let inner = |start: usize, end: usize| operand.replace(' ', "")[start..upper.len() - end].to_string();
+    if upper.is_empty() {
+        let mode = if opcode(mnemonic, Acc).is_some() { Acc } else { Imp };
+        return Ok((mode, String::new()));
+    }
+    if upper == "A" && opcode(mnemonic, Acc).is_some() {
+        return Ok((Acc, String::new()));
+    }
+    if upper.starts_with('#') {
+        return Ok((Imm, inner(1, 0)));
+    }
+    if upper.starts_with('(') {
+        if upper.ends_with(",X)") {
+            return Ok((Indx, inner(1, 3)));
+        }
+        if upper.ends_with("),Y") {
+            return Ok((Indy, inner(1, 3)));
+        }
+        if upper.ends_with(')') {
+            return Ok((Ind, inner(1, 1)));
+        }
+        return Err(format!("bad operand: {}", operand));
+    }
+    if opcode(mnemonic, Rel).is_some() {
+        return Ok((Rel, inner(0, 0)));
+    }
+    let absolute = upper.starts_with("A:");
+    let start = if absolute { 2 } else { 0 };
+    let (zero_page, abs, end) = if upper.ends_with(",X") {
+        (Zpx, Absx, 2)
+    } else if upper.ends_with(",Y") {
+        (Zpy, Absy, 2)
+    } else {
+        (Zp, Abs, 0)
+    };
+    return Ok((if absolute { abs } else { zero_page }, inner(start, end)));
+}
+
+/// The absolute mode for a zero page one.
+fn widen(mode: Mode) -> Mode {
+    return match mode {
+        Zp => Abs,
+        Zpx => Absx,
+        Zpy => Absy,
+        other => other,
+    };
+}
+
+/// Evaluate `expr` at `pc`, giving `None` if it names something not defined
+/// yet.
+fn evaluate(expr: &str, pc: i64, symbols: &HashMap<String, i64>) -> Result<Option<i64>, String> {
+    let expr = expr.trim();
+    if let Some(rest) = expr.strip_prefix('<') {
+        return Ok(evaluate(rest, pc, symbols)?.map(|value| value & 0xFF));
+    }
+    if let Some(rest) = expr.strip_prefix('>') {
+        return Ok(evaluate(rest, pc, symbols)?.map(|value| (value >> 8) & 0xFF));
+    }
+
+    let mut total = Some(0);
+    let mut op = '+';
+    let mut rest = expr;
+    loop {
+        let end = rest.find(['+', '-', '|']).unwrap_or(rest.len());
+        // a character literal may be one of the operators
+        let end = if rest.starts_with('\'') { 3.min(rest.len()) } else { end };
+        let term = term(rest[..end].trim(), pc, symbols)?;
+        total = match (total, term) {
+            (Some(total), Some(term)) => Some(match op {
+                '+' => total + term,
+                '-' => total - term,
+                _ => total | term,
+            }),
+            _ => None,
+        };
+        rest = rest[end..].trim_start();
+        match rest.chars().next() {
+            Some(next) => {
+                op = next;
+                rest = &rest[1..];
+            }
+            None => return Ok(total),
+        }
+    }
+}
+
+fn term(text: &str, pc: i64, symbols: &HashMap<String, i64>) -> Result<Option<i64>, String> {
+    let number = |digits: &str, radix| i64::from_str_radix(digits, radix).map_err(|_| format!("bad number: {}", text));
+    if text == "*" {
+        return Ok(Some(pc));
+    }
+    if let Some(hex) = text.strip_prefix('$') {
+        return number(hex, 16).map(Some);
+    }
+    if let Some(binary) = text.strip_prefix('%') {
+        return number(binary, 2).map(Some);
+    }
+    if text.len() == 3 && text.starts_with('\'') && text.ends_with('\'') {
+        return Ok(Some(text.as_bytes()[1] as i64));
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return number(text, 10).map(Some);
+    }
+    if is_name(text) {
+        return Ok(symbols.get(text).copied());
+    }
+    return Err(format!("bad expression: {}", text));
+}
+
+/// Assemble `source`, or say which line is wrong and why.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut statements = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let (label, statement) = parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        statements.push((number + 1, line, label, statement));
+    }
+
+    // The first pass places every line, settling on zero page or absolute
+    // for each instruction as it goes: zero page only if the address is
+    // already known to fit.
+    let mut symbols = HashMap::new();
+    let mut modes = Vec::new();
+    let mut pc: i64 = 0;
+    for (number, _, label, statement) in &statements {
+        let at = |e: String| format!("line {}: {}", number, e);
+        if let Some(label) = label {
+            if symbols.insert(label.clone(), pc).is_some() {
+                return Err(at(format!("{} defined twice", label)));
+            }
+        }
+        let mut mode = Imp;
+        match statement {
+            Statement::Nothing => {}
+            Statement::Constant(name, expr) => {
+                let value = evaluate(expr, pc, &symbols).map_err(at)?;
+                let value = value.ok_or_else(|| at(format!("{} uses a name defined after it", name)))?;
+                if symbols.insert(name.clone(), value).is_some() {
+                    return Err(at(format!("{} defined twice", name)));
+                }
+            }
+            Statement::Org(expr) => {
+                pc = evaluate(expr, pc, &symbols).map_err(at)?.ok_or_else(|| at(".org needs a known address".to_string()))?;
+            }
+            Statement::Bytes(items) => pc += items.len() as i64,
+            Statement::Words(items) => pc += 2 * items.len() as i64,
+            Statement::Instruction(mnemonic, parsed, operand) => {
+                mode = *parsed;
+                if matches!(mode, Zp | Zpx | Zpy) {
+                    let value = evaluate(operand, pc, &symbols).map_err(at)?;
+                    let fits = matches!(value, Some(value) if (0..0x100).contains(&value));
+                    if !fits || opcode(mnemonic, mode).is_none() {
+                        mode = widen(mode);
+                    }
+                }
+                if opcode(mnemonic, mode).is_none() {
+                    return Err(at(format!("{} has no {:?} mode", mnemonic, mode)));
+                }
+                pc += length(mode) as i64;
+            }
+        }
+        modes.push(mode);
+    }
+
+    // The second pass lays the bytes down, with every name now known.
+    let mut image = vec![0; 0x10000];
+    let mut lines = HashMap::new();
+    let mut pc: i64 = 0;
+    for ((number, line, _, statement), mode) in statements.iter().zip(modes) {
+        let at = |e: String| format!("line {}: {}", number, e);
+        let value = |expr: &str, pc: i64| -> Result<i64, String> {
+            return evaluate(expr, pc, &symbols).map_err(at)?.ok_or_else(|| at(format!("undefined name in {}", expr)));
+        };
+        let mut bytes = Vec::new();
+        match statement {
+            Statement::Nothing | Statement::Constant(..) => {}
+            Statement::Org(expr) => pc = value(expr, pc)?,
+            Statement::Bytes(items) => {
+                for item in items {
+                    bytes.push(value(item, pc)? as u8);
+                }
+            }
+            Statement::Words(items) => {
+                for item in items {
+                    let word = value(item, pc)?;
+                    bytes.extend(&[word as u8, (word >> 8) as u8]);
+                }
+            }
+            Statement::Instruction(mnemonic, _, operand) => {
+                lines.insert(pc as u16, (*number, line.to_string()));
+                bytes.push(opcode(mnemonic, mode).unwrap());
+                match mode {
+                    Imp | Acc => {}
+                    Rel => {
+                        let offset = value(operand, pc)? - (pc + 2);
+                        if !(-128..128).contains(&offset) {
+                            return Err(at(format!("branch out of range by {}", offset)));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    _ if length(mode) == 2 => {
+                        let byte = value(operand, pc)?;
+                        if !(-128..0x100).contains(&byte) {
+                            return Err(at(format!("{} doesn't fit in a byte", operand)));
+                        }
+                        bytes.push(byte as u8);
+                    }
+                    _ => {
+                        let word = value(operand, pc)?;
+                        bytes.extend(&[word as u8, (word >> 8) as u8]);
+                    }
+                }
+            }
+        }
+        for byte in bytes {
+            if !(0..0x10000).contains(&pc) {
+                return Err(at("past the end of memory".to_string()));
+            }
+            image[pc as usize] = byte;
+            pc += 1;
+        }
+    }
+    return Ok(Program { image, symbols, lines });
+}
@@ -0,0 +1,127 @@
+//! Self-checking 6502 test programs in the style of Klaus Dormann's suite,
+//! run against the CPU on a flat 64K bus: a functional test of every
+//! documented opcode and addressing mode, and Bruce Clark's decimal mode
+//! test as Dormann's suite has it, over every pair of operands.
+//!
+//! Both are assembled from their source in `tests/roms` on every run, by the
+//! assembler in `tests/asm`. Like Dormann's, they stop in a jump or branch to
+//! itself: at the end when they pass, and right after the failing check when
+//! they don't.
+
+mod asm;
+
+use std::path::PathBuf;
+
+use rustari_core::bus::FlatBus;
+use rustari_core::cpu::Cpu;
+
+use asm::Program;
+
+/// Give up on a test that hasn't trapped after this many instructions.
+const MAX_STEPS: usize = 200_000_000;
+
+/// Assemble a test program from `tests/roms`.
+fn assemble(name: &str) -> Program {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(name);
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    return asm::assemble(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+}
+
+/// Run until the program traps in a jump or branch to itself, and return
+/// that address.
+fn run_to_trap(cpu: &mut Cpu, bus: &mut FlatBus) -> usize {
+    for _ in 0..MAX_STEPS {
+        let pc = cpu.pc;
+        if let Err(fault) = cpu.execute_step(bus) {
+            panic!("{}", fault);
+        }
+        if cpu.pc == pc {
+            return pc;
+        }
+    }
+    panic!("no trap after {} instructions, pc = {:04X}", MAX_STEPS, cpu.pc);
+}
+
+/// Load `program` and run it from its `start` label to a trap, leaving the
+/// CPU there.
+fn run(program: &Program) -> (Cpu, FlatBus) {
+    let mut bus = FlatBus::new();
+    bus.memory.copy_from_slice(&program.image);
+    let mut cpu = Cpu::new();
+    cpu.pc = program.symbol("start") as usize;
+    run_to_trap(&mut cpu, &mut bus);
+    return (cpu, bus);
+}
+
+#[test]
+fn functional_test() {
+    let program = assemble("6502_functional_test.a65");
+    let (cpu, _) = run(&program);
+    let trap = cpu.pc as u16;
+    assert_eq!(trap, program.symbol("success"), "trapped at {:04X}, {}", trap, program.line_at(trap));
+}
+
+#[test]
+fn decimal_test() {
+    let program = assemble("6502_decimal_test.a65");
+    let (cpu, bus) = run(&program);
+    let trap = cpu.pc as u16;
+    assert_eq!(trap, program.symbol("done"), "trapped at {:04X}, {}", trap, program.line_at(trap));
+    let byte = |name| bus.memory[program.symbol(name) as usize];
+    assert_eq!(
+        byte("ERROR"),
+        0,
+        "wrong result for {:02X} and {:02X} with the carry {}",
+        byte("N1"),
+        byte("N2"),
+        if cpu.y_reg == 1 { "set" } else { "clear" }
+    );
+}
+
+#[test]
+fn trap_detection() {
+    let mut bus = FlatBus::new();
+    // LDX #3; DEX; BNE -3; JMP $0205
+    let program = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x02];
+    bus.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::new();
+    cpu.pc = 0x200;
+    assert_eq!(run_to_trap(&mut cpu, &mut bus), 0x205);
+    assert_eq!(cpu.x_reg, 0);
+}
+
+#[test]
+fn assembler() {
+    let program = asm::assemble(
+        "
+        ZP = $10
+                .org $0200
+        start:  lda #<target        ; immediate, low byte
+                sta ZP,x            ; zero page, x
+                lda a:ZP            ; absolute, asked for
+                ldx target,y        ; absolute, y
+                jmp (target)
+                asl
+        back:   bne back
+                bcc target
+        target: .byte 1, 'A', ZP+1
+                .word start, *
+        ",
+    )
+    .unwrap();
+    let start = program.symbol("start") as usize;
+    assert_eq!(
+        &program.image[start..start + 25],
+        &[
+            0xA9, 0x12, 0x95, 0x10, 0xAD, 0x10, 0x00, 0xBE, 0x12, 0x02, 0x6C, 0x12, 0x02, 0x0A, 0xD0, 0xFE, 0x90,
+            0x00, 0x01, 0x41, 0x11, 0x00, 0x02, 0x15, 0x02
+        ][..]
+    );
+    assert_eq!(program.line_at(0x0200), "line 4: start:  lda #<target        ; immediate, low byte");
+
+    assert!(asm::assemble("lda ($10),x").is_err());
+    assert!(asm::assemble("stx $1234,y").is_err());
+    assert!(asm::assemble("beq far\n.org $0300\nfar: rts").is_err());
+    assert!(asm::assemble("jmp nowhere").is_err());
+}
+
@@ -0,0 +1,240 @@
+//! Golden-image regression tests: boot ROMs headlessly with scripted input
+//! and compare the frames they draw against references checked in under
+//! `tests/golden`.
+//!
+//! Every `CHECK_EVERY` frames, and after the last, the MD5 of the TIA's
+//! frame buffer is compared with the case's `<name>.txt`, and the last
+//! frame is compared with `<name>.png`. Running with `UPDATE_GOLDENS=1`
+//! rewrites the references instead, after a change meant to alter what is
+//! drawn. See `tests/golden/README.md`.
+
+use std::path::{Path, PathBuf};
+
+use rustari_core::capture;
+use rustari_core::headless;
+use rustari_core::movie::frame_hash;
+use rustari_core::riot::Joystick;
+use rustari_core::{Cartridge, Machine};
+
+/// Frames between the hashes compared.
+const CHECK_EVERY: usize = 30;
+
+/// Frames a homebrew ROM runs for unless its script says otherwise.
+const HOMEBREW_FRAMES: usize = 120;
+
+fn golden_dir() -> PathBuf {
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+}
+
+fn updating() -> bool {
+    return std::env::var("UPDATE_GOLDENS").is_ok_and(|val| val == "1");
+}
+
+/* #region scripts */
+
+/// Input held over a range of frames, written as `first-last keys...`, or
+/// `frame keys...` for one frame. Keys are `up`, `down`, `left`, `right`
+/// and `fire` for the left joystick and `reset`, `select` and `bw` for the
+/// console. Frames count from 1.
+struct Hold {
+    first: usize,
+    last: usize,
+    keys: Vec<String>,
+}
+
+const KEYS: [&str; 8] = ["up", "down", "left", "right", "fire", "reset", "select", "bw"];
+
+/// Holds separated by `;` or new lines; `#` starts a comment. A line
+/// `frames N` sets how many frames to run.
+struct Script {
+    frames: Option<usize>,
+    holds: Vec<Hold>,
+}
+
+impl Script {
+    fn parse(text: &str) -> Result<Script, String> {
+        let mut script = Script { frames: None, holds: Vec::new() };
+        for clause in text.lines().flat_map(|line| line.split('#').next().unwrap().split(';')) {
+            let mut words = clause.split_whitespace();
+            let range = match words.next() {
+                Some(range) => range,
+                None => continue,
+            };
+            let number = |text: &str| text.parse::<usize>().map_err(|_| format!("not a frame: {}", text));
+            if range == "frames" {
+                script.frames = Some(number(words.next().unwrap_or(""))?);
+                continue;
+            }
+            let mut ends = range.splitn(2, '-');
+            let first = number(ends.next().unwrap())?;
+            let last = match ends.next() {
+                Some(last) => number(last)?,
+                None => first,
+            };
+            let keys: Vec<String> = words.map(str::to_string).collect();
+            if let Some(key) = keys.iter().find(|key| !KEYS.contains(&key.as_str())) {
+                return Err(format!("unknown key: {}", key));
+            }
+            script.holds.push(Hold { first, last, keys });
+        }
+        return Ok(script);
+    }
+
+    /// Set the input for `frame` on `machine`.
+    fn apply(&self, frame: usize, machine: &mut Machine) {
+        let held = |name: &str| {
+            self.holds
+                .iter()
+                .any(|hold| frame >= hold.first && frame <= hold.last && hold.keys.iter().any(|key| key == name))
+        };
+        machine.set_joystick(0, Joystick {
+            up: held("up"),
+            down: held("down"),
+            left: held("left"),
+            right: held("right"),
+        });
+        machine.set_fire(0, held("fire"));
+        machine.set_reset_switch(held("reset"));
+        machine.set_select_switch(held("select"));
+        machine.set_color_switch(!held("bw"));
+    }
+}
+
+/* #endregion */
+
+/// Run `rom` for `frames` frames with `script`'s input and check it against
+/// the references for `name`, or rewrite them when updating. Returns what
+/// doesn't match.
+fn check(name: &str, rom: Vec<u8>, frames: usize, script: &Script) -> Vec<String> {
+    let mut machine = Machine::new(Cartridge::from_rom(rom));
+    let mut hashes = String::new();
+    let mut frame = 0;
+    let output = headless::run_with(&mut machine, frames, None, None, &mut |machine| {
+        frame += 1;
+        script.apply(frame, machine);
+        machine.run_frame()?;
+        if frame % CHECK_EVERY == 0 || frame == frames {
+            let hash: String = frame_hash(machine).iter().map(|byte| format!("{:02x}", byte)).collect();
+            hashes += &format!("{} {}\n", frame, hash);
+        }
+        return Ok(());
+    });
+    let output = match output {
+        Ok(output) => output,
+        Err(fault) => return vec![format!("{}: CPU fault in frame {}: {}", name, frame, fault)],
+    };
+
+    let dir = golden_dir();
+    let (txt, png) = (dir.join(format!("{}.txt", name)), dir.join(format!("{}.png", name)));
+    if updating() {
+        std::fs::create_dir_all(txt.parent().unwrap()).unwrap();
+        std::fs::write(&txt, &hashes).unwrap();
+        capture::write_png(&png, output.width, &output.frame).unwrap();
+        eprintln!("updated {} and {}", txt.display(), png.display());
+        return Vec::new();
+    }
+
+    let mut failures = Vec::new();
+    match std::fs::read_to_string(&txt) {
+        Ok(golden) => {
+            let differ = golden.lines().zip(hashes.lines()).find(|(golden, actual)| golden != actual);
+            if let Some((golden, actual)) = differ {
+                failures.push(format!("{}: expected frame {}, got frame {}", name, golden, actual));
+            } else if golden.lines().count() != hashes.lines().count() {
+                failures.push(format!("{}: {} hashes checked in, {} computed", name, golden.lines().count(), hashes.lines().count()));
+            }
+        }
+        Err(_) => failures.push(format!("{}: no golden hashes at {}", name, txt.display())),
+    }
+    match read_png(&png) {
+        Some((width, pixels)) => {
+            let rgb: Vec<u8> = output.frame.chunks_exact(4).flat_map(|p| p[..3].to_vec()).collect();
+            if width != output.width || pixels != rgb {
+                let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.actual.png", name));
+                capture::write_png(&actual, output.width, &output.frame).unwrap();
+                failures.push(format!("{}: last frame differs from {}; it was written to {}", name, png.display(), actual.display()));
+            }
+        }
+        None => failures.push(format!("{}: no golden image at {}", name, png.display())),
+    }
+    return failures;
+}
+
+/// A PNG's width and its pixels as RGB.
+fn read_png(path: &Path) -> Option<(usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(std::fs::File::open(path).ok()?);
+    let (info, mut reader) = decoder.read_info().ok()?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).ok()?;
+    let rgb = match info.color_type {
+        png::ColorType::RGBA => pixels.chunks_exact(4).flat_map(|p| p[..3].to_vec()).collect(),
+        png::ColorType::RGB => pixels,
+        _ => return None,
+    };
+    return Some((info.width as usize, rgb));
+}
+
+fn assert_golden(failures: Vec<String>) {
+    assert!(
+        failures.is_empty(),
+        "{}\nif the change is intended, rerun with UPDATE_GOLDENS=1 and review the new images",
+        failures.join("\n")
+    );
+}
+
+fn bundled(file: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join(file);
+    return std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+}
+
+#[test]
+fn test_hello() {
+    let script = Script::parse("").unwrap();
+    assert_golden(check("hello", bundled("hello.a26"), 60, &script));
+}
+
+#[test]
+fn test_vsync() {
+    let script = Script::parse("").unwrap();
+    assert_golden(check("vsync", bundled("vsync.a26"), 60, &script));
+}
+
+#[test]
+fn test_space_invaders() {
+    // start a game, then move right while firing and come back
+    let script = Script::parse("30-35 reset; 180-260 right fire; 280-330 left").unwrap();
+    assert_golden(check("spaceInvaders", bundled("spaceInvaders.a26"), 360, &script));
+}
+
+/// Every ROM in `tests/golden/homebrew`, with the input in a `.script` file
+/// of the same name if there is one.
+#[test]
+fn test_homebrew() {
+    let dir = golden_dir().join("homebrew");
+    let mut roms: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+        Err(_) => return,
+    };
+    roms.retain(|path| path.extension().is_some_and(|ext| ext == "a26" || ext == "bin"));
+    roms.sort();
+
+    let mut failures = Vec::new();
+    for path in roms {
+        let script = std::fs::read_to_string(path.with_extension("script")).unwrap_or_default();
+        let script = Script::parse(&script).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let name = format!("homebrew/{}", path.file_stem().unwrap().to_string_lossy());
+        let frames = script.frames.unwrap_or(HOMEBREW_FRAMES);
+        failures.extend(check(&name, std::fs::read(&path).unwrap(), frames, &script));
+    }
+    assert_golden(failures);
+}
+
+#[test]
+fn test_script() {
+    let script = Script::parse("frames 90\n10-20 up fire # start\n25 reset; 30-31 bw").unwrap();
+    assert_eq!(script.frames, Some(90));
+    assert_eq!(script.holds.len(), 3);
+    assert_eq!((script.holds[1].first, script.holds[1].last), (25, 25));
+    assert!(Script::parse("1-2 jump").is_err());
+    assert!(Script::parse("x-2 up").is_err());
+}
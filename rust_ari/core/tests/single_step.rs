@@ -0,0 +1,419 @@
+//! Per-instruction conformance tests in the format of the community
+//! SingleStepTests (Tom Harte) 6502 vectors. Each vector gives the
+//! registers and RAM before and after one instruction, and every bus access
+//! the instruction makes, cycle by cycle.
+//!
+//! Running the real vectors is opt-in: they aren't distributed with rustARI,
+//! so a plain `cargo test` only runs the checks of the runner itself below
+//! and lists `single_step_vectors` as ignored. It doesn't check the CPU's
+//! cycles against SingleStepTests unless the vectors are fetched into
+//! `tests/single_step` (see the README there) and the test is asked for:
+//!
+//! cargo test -p rustari-core --test single_step -- --ignored
+//!
+//! It fails if run without any vectors; opcodes whose file is missing are
+//! skipped.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::path::PathBuf;
+
+use rustari_core::bus::Bus;
+use rustari_core::cpu::{Cpu, OPCODES};
+
+/// Stop listing failures after this many; the count still covers them all.
+const MAX_REPORTED: usize = 20;
+
+/* #region JSON */
+
+/// Just enough JSON for the vector files: no literals, no escapes beyond
+/// `\"`, `\\` and `\/`, and integer numbers only.
+#[derive(Debug)]
+enum Json {
+    Number(i64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(text: &'a str) -> Result<Json, String> {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_space();
+        if parser.pos != parser.text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        return Ok(value);
+    }
+
+    fn error(&self, what: &str) -> String {
+        return format!("{} at byte {}", what, self.pos);
+    }
+
+    fn skip_space(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_space();
+        return self.text.get(self.pos).cloned();
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        return Ok(());
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        return match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(_) => self.number(),
+            None => Err(self.error("unexpected end")),
+        };
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut members = BTreeMap::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.insert(key, self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => break,
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+        self.pos += 1;
+        return Ok(Json::Object(members));
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => break,
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+        self.pos += 1;
+        return Ok(Json::Array(items));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.text.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.text.get(self.pos) {
+                        Some(&c) if c == b'"' || c == b'\\' || c == b'/' => bytes.push(c),
+                        _ => return Err(self.error("unsupported escape")),
+                    }
+                }
+                Some(&c) => bytes.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        return String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"));
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        if self.text[self.pos] == b'-' {
+            self.pos += 1;
+        }
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        return digits.parse().map(Json::Number).map_err(|_| self.error("expected a value"));
+    }
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Result<&Json, String> {
+        return match self {
+            Json::Object(members) => members.get(key).ok_or(format!("missing \"{}\"", key)),
+            _ => Err(format!("expected an object holding \"{}\"", key)),
+        };
+    }
+
+    fn items(&self) -> Result<&[Json], String> {
+        return match self {
+            Json::Array(items) => Ok(items),
+            _ => Err("expected an array".to_string()),
+        };
+    }
+
+    fn int(&self) -> Result<usize, String> {
+        return match self {
+            Json::Number(n) if *n >= 0 => Ok(*n as usize),
+            _ => Err(format!("expected an unsigned number, found {:?}", self)),
+        };
+    }
+
+    fn str(&self) -> Result<&str, String> {
+        return match self {
+            Json::Str(s) => Ok(s),
+            _ => Err(format!("expected a string, found {:?}", self)),
+        };
+    }
+}
+
+/* #endregion */
+
+/* #region Vectors */
+
+struct State {
+    pc: usize,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(usize, u8)>,
+}
+
+#[derive(PartialEq)]
+struct Access {
+    addr: u16,
+    val: u8,
+    write: bool,
+}
+
+impl fmt::Debug for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.write { "W" } else { "R" };
+        return write!(f, "{} {:04X}={:02X}", kind, self.addr, self.val);
+    }
+}
+
+struct Vector {
+    name: String,
+    initial: State,
+    expected: State,
+    cycles: Vec<Access>,
+}
+
+impl State {
+    fn parse(json: &Json) -> Result<State, String> {
+        let byte = |key: &str| -> Result<u8, String> { Ok(json.get(key)?.int()? as u8) };
+        let mut ram = Vec::new();
+        for cell in json.get("ram")?.items()? {
+            let cell = cell.items()?;
+            ram.push((cell[0].int()?, cell[1].int()? as u8));
+        }
+        return Ok(State {
+            pc: json.get("pc")?.int()?,
+            s: byte("s")?,
+            a: byte("a")?,
+            x: byte("x")?,
+            y: byte("y")?,
+            p: byte("p")?,
+            ram,
+        });
+    }
+}
+
+impl Vector {
+    fn parse(json: &Json) -> Result<Vector, String> {
+        let mut cycles = Vec::new();
+        for cycle in json.get("cycles")?.items()? {
+            let cycle = cycle.items()?;
+            cycles.push(Access {
+                addr: cycle[0].int()? as u16,
+                val: cycle[1].int()? as u8,
+                write: match cycle[2].str()? {
+                    "read" => false,
+                    "write" => true,
+                    other => return Err(format!("unknown access \"{}\"", other)),
+                },
+            });
+        }
+        return Ok(Vector {
+            name: json.get("name")?.str()?.to_string(),
+            initial: State::parse(json.get("initial")?)?,
+            expected: State::parse(json.get("final")?)?,
+            cycles,
+        });
+    }
+}
+
+fn parse_vectors(text: &str) -> Result<Vec<Vector>, String> {
+    let json = Parser::parse(text)?;
+    return json.items()?.iter().map(Vector::parse).collect();
+}
+
+/* #endregion */
+
+/// 64K of RAM that records every access made to it.
+struct LoggingBus {
+    memory: Vec<u8>,
+    log: Vec<Access>,
+}
+
+impl Bus for LoggingBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.memory[addr as usize];
+        self.log.push(Access { addr, val, write: false });
+        return val;
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+        self.log.push(Access { addr, val, write: true });
+    }
+}
+
+/// Run one vector, returning a description of everything that didn't match.
+fn run_vector(vector: &Vector) -> Option<String> {
+    let initial = &vector.initial;
+    let mut bus = LoggingBus {
+        memory: vec![0; 0x10000],
+        log: Vec::new(),
+    };
+    for &(addr, val) in &initial.ram {
+        bus.memory[addr] = val;
+    }
+    let mut cpu = Cpu::new();
+    cpu.pc = initial.pc;
+    cpu.s_pnt = initial.s;
+    cpu.a_reg = initial.a;
+    cpu.x_reg = initial.x;
+    cpu.y_reg = initial.y;
+    cpu.flags = initial.p;
+    let opcode = bus.memory[initial.pc];
+
+    if let Err(fault) = cpu.execute_step(&mut bus) {
+        return Some(format!("opcode {:02X}, vector \"{}\": {}", opcode, vector.name, fault));
+    }
+
+    let expected = &vector.expected;
+    let mut errors = String::new();
+    let registers = [
+        ("pc", cpu.pc, expected.pc),
+        ("s", cpu.s_pnt as usize, expected.s as usize),
+        ("a", cpu.a_reg as usize, expected.a as usize),
+        ("x", cpu.x_reg as usize, expected.x as usize),
+        ("y", cpu.y_reg as usize, expected.y as usize),
+        ("p", cpu.flags as usize, expected.p as usize),
+    ];
+    for &(name, actual, wanted) in registers.iter() {
+        if actual != wanted {
+            write!(errors, " {} is {:02X}, expected {:02X};", name, actual, wanted).unwrap();
+        }
+    }
+    for &(addr, wanted) in &expected.ram {
+        if bus.memory[addr] != wanted {
+            write!(errors, " [{:04X}] is {:02X}, expected {:02X};", addr, bus.memory[addr], wanted).unwrap();
+        }
+    }
+    if bus.log != vector.cycles {
+        write!(errors, " bus accesses were {:?}, expected {:?};", bus.log, vector.cycles).unwrap();
+    }
+    if cpu.cycles != vector.cycles.len() {
+        write!(errors, " took {} cycles, expected {};", cpu.cycles, vector.cycles.len()).unwrap();
+    }
+
+    if errors.is_empty() {
+        return None;
+    }
+    return Some(format!("opcode {:02X}, vector \"{}\":{}", opcode, vector.name, errors));
+}
+
+#[test]
+#[ignore = "opt-in: needs the SingleStepTests vectors, which aren't distributed, in tests/single_step"]
+fn single_step_vectors() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/single_step");
+    let mut failures = Vec::new();
+    let mut run = 0;
+    // only the documented opcodes are implemented
+    for opcode in (0..OPCODES.len()).filter(|&op| OPCODES[op].is_some()) {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let vectors = parse_vectors(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        for vector in &vectors {
+            failures.extend(run_vector(vector));
+        }
+        run += vectors.len();
+    }
+    assert!(run > 0, "no vectors found in {}; see tests/single_step/README.md", dir.display());
+    let shown: Vec<&str> = failures.iter().take(MAX_REPORTED).map(|f| f.as_str()).collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} vectors failed:\n{}",
+        failures.len(),
+        run,
+        shown.join("\n")
+    );
+}
+
+/// LDA #$12 at 0x1000, taken from the shape of the real files.
+const LDA_VECTOR: &str = r#"[{
+    "name": "a9 12 00",
+    "initial": {"pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                "ram": [[4096, 169], [4097, 18], [4098, 0]]},
+    "final": {"pc": 4098, "s": 253, "a": 18, "x": 0, "y": 0, "p": 36,
+              "ram": [[4096, 169], [4097, 18], [4098, 0]]},
+    "cycles": [[4096, 169, "read"], [4097, 18, "read"]]
+}]"#;
+
+#[test]
+fn runner_passes_matching_vector() {
+    let vectors = parse_vectors(LDA_VECTOR).unwrap();
+    assert_eq!(vectors.len(), 1);
+    assert_eq!(run_vector(&vectors[0]), None);
+}
+
+#[test]
+fn runner_reports_opcode_and_vector() {
+    let mut vectors = parse_vectors(LDA_VECTOR).unwrap();
+    vectors[0].expected.a = 0x13;
+    vectors[0].cycles.pop();
+    let failure = run_vector(&vectors[0]).unwrap();
+    assert!(failure.starts_with("opcode A9, vector \"a9 12 00\":"), "{}", failure);
+    assert!(failure.contains("a is 12, expected 13"), "{}", failure);
+    assert!(failure.contains("took 2 cycles, expected 1"), "{}", failure);
+}
+
+#[test]
+fn parser_rejects_malformed_files() {
+    assert!(parse_vectors("[{\"name\": \"a9\"}]").is_err());
+    assert!(parse_vectors("[1, 2").is_err());
+    assert!(parse_vectors("[true]").is_err());
+    assert!(Parser::parse("{\"a\": [1, -2, \"x\\\"y\"]}").is_ok());
+}
@@ -0,0 +1,463 @@
+//! A libretro core: the emulator built as a shared library for RetroArch and
+//! other libretro frontends to load and drive, one `retro_run` a frame.
+//!
+//! The frontend hands over the ROM's contents, polls input before each
+//! frame and takes the picture, cropped to what a TV shows, as XRGB8888,
+//! and the audio as 16 bit stereo at the TIA's own rate, which it resamples.
+//! Core options pick the TV standard and the palette. Only joysticks are
+//! emulated, so there are no options for other controllers.
+
+pub mod ffi;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_uint, c_void};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+
+use rustari_core::emulator::Emulator;
+use rustari_core::properties::{Controller, Overrides, Properties};
+use rustari_core::riot::Joystick;
+use rustari_core::rom_read;
+use rustari_core::tv::TvStandard;
+use rustari_core::viewport::Crop;
+
+use crate::ffi::*;
+
+/// The core options, each with its description and values, the first of
+/// which is the default.
+const VARIABLES: &[(&str, &str)] = &[
+    ("rustari_tv\0", "TV standard; auto|NTSC|PAL|SECAM\0"),
+    ("rustari_palette\0", "Palette; standard|NTSC|PAL|SECAM\0"),
+];
+
+/// The core options as picked in the frontend. `auto` and `standard` come
+/// out as `None`, for what the cartridge's properties or TV standard give.
+#[derive(Default)]
+struct Options {
+    tv: Option<TvStandard>,
+    /// The TV standard whose colors to draw with.
+    palette: Option<TvStandard>,
+}
+
+/// The game loaded and what's kept between frames for it.
+struct Game {
+    emulator: Emulator,
+    properties: Properties,
+    /// The standard the frontend was last given the timing of.
+    announced: TvStandard,
+    /// Stopped at a CPU fault, already reported, until reset or a state
+    /// is loaded.
+    halted: bool,
+    video: Vec<u32>,
+    /// Interleaved left and right samples.
+    audio: Vec<i16>,
+}
+
+impl Game {
+    fn apply(&mut self, options: &Options) {
+        let standard = options.tv.or_else(|| TvStandard::from_format(self.properties.format));
+        self.emulator.set_standard(standard);
+        self.emulator.set_palette(options.palette.map(|standard| *standard.palette()));
+    }
+
+    fn av_info(&self) -> SystemAvInfo {
+        let standard = self.emulator.standard();
+        let crop = Crop::visible(standard, &self.properties);
+        return SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: crop.width as c_uint,
+                base_height: crop.height as c_uint,
+                max_width: crop.width as c_uint,
+                max_height: crop.height.max(TvStandard::PAL.scanlines()) as c_uint,
+                aspect_ratio: (crop.width as f64 * standard.pixel_aspect() / crop.height as f64) as f32,
+            },
+            timing: SystemTiming {
+                fps: 1.0 / standard.frame_duration(standard.scanlines()).as_secs_f64(),
+                sample_rate: self.emulator.sample_rate() as f64,
+            },
+        };
+    }
+}
+
+/// The frontend's callbacks and the devices plugged into each port. They're
+/// copied out of `FRONTEND` to be called, so that a frontend calling back
+/// into the core from one doesn't find the lock held.
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+    log: Option<LogPrintfFn>,
+    ports: [c_uint; 2],
+}
+
+/// The callbacks and the game running, shared by the entry points.
+struct Frontend {
+    callbacks: Callbacks,
+    /// Boxed so the RAM handed out by `retro_get_memory_data` stays put.
+    game: Option<Box<Game>>,
+}
+
+static FRONTEND: Mutex<Frontend> = Mutex::new(Frontend {
+    callbacks: Callbacks {
+        environment: None,
+        video_refresh: None,
+        audio_batch: None,
+        input_poll: None,
+        input_state: None,
+        log: None,
+        ports: [RETRO_DEVICE_JOYPAD; 2],
+    },
+    game: None,
+});
+
+fn frontend() -> MutexGuard<'static, Frontend> {
+    // a panic while it was held leaves nothing half changed worth refusing
+    return FRONTEND.lock().unwrap_or_else(|e| e.into_inner());
+}
+
+/// A copy of the callbacks, taken without keeping the lock.
+fn callbacks() -> Callbacks {
+    return frontend().callbacks;
+}
+
+impl Callbacks {
+    fn environment(&self, cmd: c_uint, data: *mut c_void) -> bool {
+        return self.environment.is_some_and(|environment| environment(cmd, data));
+    }
+
+    /// The value picked for the option `key`, given with its NUL.
+    fn variable<T: FromStr>(&self, key: &str) -> Option<T> {
+        let mut variable = Variable { key: key.as_ptr() as *const c_char, value: std::ptr::null() };
+        if !self.environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void)
+            || variable.value.is_null()
+        {
+            return None;
+        }
+        let value = unsafe { CStr::from_ptr(variable.value) }.to_string_lossy();
+        return value.to_uppercase().parse().ok();
+    }
+
+    fn options(&self) -> Options {
+        return Options {
+            tv: self.variable(VARIABLES[0].0),
+            palette: self.variable(VARIABLES[1].0),
+        };
+    }
+
+    /// Whether the button `id` of the joypad in `port` is held.
+    fn pressed(&self, port: usize, id: c_uint) -> bool {
+        return match self.input_state {
+            Some(input_state) if self.ports[port] == RETRO_DEVICE_JOYPAD => {
+                input_state(port as c_uint, RETRO_DEVICE_JOYPAD, 0, id) != 0
+            }
+            _ => false,
+        };
+    }
+
+    /// Log through the frontend, or to stderr if it has no log interface.
+    fn log(&self, level: c_uint, message: &str) {
+        match (self.log, CString::new(message)) {
+            (Some(log), Ok(message)) => unsafe { log(level, "%s\n\0".as_ptr() as *const c_char, message.as_ptr()) },
+            _ => eprintln!("{}", message),
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    return RETRO_API_VERSION;
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = SystemInfo {
+            library_name: "rustARI\0".as_ptr() as *const c_char,
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: "a26|bin\0".as_ptr() as *const c_char,
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    if let (Some(info), Some(game)) = (info.as_mut(), &frontend().game) {
+        *info = game.av_info();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    frontend().callbacks.environment = Some(environment);
+    let callbacks = callbacks();
+
+    let mut variables: Vec<Variable> = VARIABLES
+        .iter()
+        .map(|(key, value)| Variable { key: key.as_ptr() as *const c_char, value: value.as_ptr() as *const c_char })
+        .collect();
+    variables.push(Variable { key: std::ptr::null(), value: std::ptr::null() });
+    callbacks.environment(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+
+    let mut log = LogCallback { log: None };
+    if callbacks.environment(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, &mut log as *mut LogCallback as *mut c_void) {
+        frontend().callbacks.log = log.log;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    frontend().callbacks.video_refresh = Some(video_refresh);
+}
+
+/// Audio goes to the frontend a frame at a time, through the batch callback.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_batch: AudioSampleBatchFn) {
+    frontend().callbacks.audio_batch = Some(audio_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    frontend().callbacks.input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    frontend().callbacks.input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(port: c_uint, device: c_uint) {
+    if let Some(plugged) = frontend().callbacks.ports.get_mut(port as usize) {
+        *plugged = device;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    frontend().game = None;
+}
+
+/// # Safety
+///
+/// `info` must be null or point to a `retro_game_info` whose `data` holds
+/// `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(info: *const GameInfo) -> bool {
+    let callbacks = callbacks();
+    let rom = match info.as_ref() {
+        Some(info) if !info.data.is_null() => std::slice::from_raw_parts(info.data as *const u8, info.size).to_vec(),
+        _ => {
+            callbacks.log(RETRO_LOG_ERROR, "no ROM was given");
+            return false;
+        }
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !callbacks.environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+        callbacks.log(RETRO_LOG_ERROR, "the frontend can't show XRGB8888 pixels");
+        return false;
+    }
+
+    let cartridge = match rom_read::cartridge_from_bytes(rom, &Overrides::default()) {
+        Ok(cartridge) => cartridge,
+        Err(e) => {
+            callbacks.log(RETRO_LOG_ERROR, &e.to_string());
+            return false;
+        }
+    };
+    let properties = cartridge.properties.clone();
+    callbacks.log(RETRO_LOG_INFO, &format!("loaded {} ({}), bank type {}", properties.name, properties.md5, cartridge.bank_type()));
+    for controller in &[properties.left_controller, properties.right_controller] {
+        if *controller != Controller::JOYSTICK {
+            callbacks.log(RETRO_LOG_WARN, &format!("only joysticks are emulated; the {} controller will read as one", controller));
+        }
+    }
+
+    let emulator = Emulator::new(cartridge);
+    let mut game = Game {
+        announced: emulator.standard(),
+        emulator,
+        properties,
+        halted: false,
+        video: Vec::new(),
+        audio: Vec::new(),
+    };
+    game.apply(&callbacks.options());
+    game.announced = game.emulator.standard();
+    frontend().game = Some(Box::new(game));
+    return true;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    return false;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    frontend().game = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    return match frontend().game.as_ref().map(|game| game.emulator.standard()) {
+        Some(TvStandard::PAL) | Some(TvStandard::SECAM) => RETRO_REGION_PAL,
+        _ => RETRO_REGION_NTSC,
+    };
+}
+
+/// Power cycle the console, as the frontend's reset does.
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(game) = &mut frontend().game {
+        game.emulator.machine.reset();
+        game.halted = false;
+    }
+}
+
+/// Run a frame: read the joypads, pick up changed core options, and hand
+/// the frontend the picture and the audio.
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    // the game is taken out for the frame so that the lock isn't held
+    // while calling the frontend
+    let mut game = match frontend().game.take() {
+        Some(game) => game,
+        None => return,
+    };
+    let callbacks = callbacks();
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    let mut updated = false;
+    if callbacks.environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+        game.apply(&callbacks.options());
+    }
+
+    let machine = &mut game.emulator.machine;
+    for player in 0..2 {
+        let joystick = Joystick {
+            up: callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_UP),
+            down: callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_DOWN),
+            left: callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_LEFT),
+            right: callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_RIGHT),
+        };
+        machine.set_joystick(player, joystick);
+        let fire = callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_B) || callbacks.pressed(player, RETRO_DEVICE_ID_JOYPAD_A);
+        machine.set_fire(player, fire);
+    }
+    machine.set_select_switch(callbacks.pressed(0, RETRO_DEVICE_ID_JOYPAD_SELECT));
+    machine.set_reset_switch(callbacks.pressed(0, RETRO_DEVICE_ID_JOYPAD_START));
+
+    if !game.halted {
+        if let Err(fault) = game.emulator.run_frame() {
+            callbacks.log(RETRO_LOG_ERROR, &format!("{}; halted until reset", rustari_core::Error::Cpu(fault)));
+            game.halted = true;
+        }
+    }
+    let standard = game.emulator.standard();
+    if standard != game.announced {
+        let mut av_info = game.av_info();
+        callbacks.environment(RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO, &mut av_info as *mut SystemAvInfo as *mut c_void);
+        game.announced = standard;
+    }
+
+    let crop = Crop::visible(standard, &game.properties);
+    let picture = crop.apply(game.emulator.frame());
+    game.video.clear();
+    game.video.extend(picture.chunks_exact(4).map(|rgba| u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]])));
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(game.video.as_ptr() as *const c_void, crop.width as c_uint, crop.height as c_uint, crop.width * 4);
+    }
+
+    game.audio.clear();
+    for sample in game.emulator.take_audio() {
+        // 0-240 up to most of the 16 bit range, silence staying at 0
+        let sample = (sample as i16) << 7;
+        game.audio.extend_from_slice(&[sample, sample]);
+    }
+    if let Some(audio_batch) = callbacks.audio_batch {
+        audio_batch(game.audio.as_ptr(), game.audio.len() / 2);
+    }
+    frontend().game = Some(game);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    return frontend().game.as_ref().map_or(0, |game| game.emulator.machine.save_state().len());
+}
+
+/// # Safety
+///
+/// `data` must be null or point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match &frontend().game {
+        Some(game) => game.emulator.machine.save_state(),
+        None => return false,
+    };
+    if data.is_null() || size < state.len() {
+        return false;
+    }
+    std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+    return true;
+}
+
+/// # Safety
+///
+/// `data` must be null or point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    let result = match &mut frontend().game {
+        Some(game) => game.emulator.machine.load_state(state).map(|()| game.halted = false),
+        None => return false,
+    };
+    if let Err(e) = result {
+        callbacks().log(RETRO_LOG_ERROR, &format!("failed to load state: {}", e));
+        return false;
+    }
+    return true;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// The 128 bytes of RIOT RAM, for cheats and achievements.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    return match (&mut frontend().game, id) {
+        (Some(game), RETRO_MEMORY_SYSTEM_RAM) => game.emulator.machine.board.riot.ram.as_mut_ptr() as *mut c_void,
+        _ => std::ptr::null_mut(),
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    return match (&frontend().game, id) {
+        (Some(game), RETRO_MEMORY_SYSTEM_RAM) => game.emulator.machine.board.riot.ram.len(),
+        _ => 0,
+    };
+}
@@ -0,0 +1,273 @@
+//! A stand-in libretro frontend: it drives the core through the same entry
+//! points RetroArch would and keeps what the core hands back to check.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_uint, c_void};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use rustari_libretro::ffi::*;
+use rustari_libretro::*;
+
+/// What the frontend was given and what it answers with.
+#[derive(Default)]
+struct Recorded {
+    /// Core options picked, by key.
+    options: HashMap<String, CString>,
+    options_updated: bool,
+    /// Core options the core declared, with their descriptions.
+    variables: Vec<(String, String)>,
+    pixel_format: Option<c_uint>,
+    av_info: Option<SystemAvInfo>,
+    /// The last frame: width, height and XRGB8888 pixels.
+    frame: Option<(usize, usize, Vec<u32>)>,
+    audio_frames: usize,
+    /// Joypad buttons held, by port.
+    held: Vec<(c_uint, c_uint)>,
+    polls: usize,
+}
+
+/// Tests share the core, as they share the process, so they take turns.
+static TURN: Mutex<()> = Mutex::new(());
+
+static RECORDED: Mutex<Option<Recorded>> = Mutex::new(None);
+
+fn recorded() -> MutexGuard<'static, Option<Recorded>> {
+    return RECORDED.lock().unwrap_or_else(|e| e.into_inner());
+}
+
+fn with<T>(f: impl FnOnce(&mut Recorded) -> T) -> T {
+    return f(recorded().get_or_insert_with(Recorded::default));
+}
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    unsafe {
+        return match cmd {
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+                let format = *(data as *const c_uint);
+                with(|r| r.pixel_format = Some(format));
+                true
+            }
+            RETRO_ENVIRONMENT_SET_VARIABLES => {
+                let mut variable = data as *const Variable;
+                while !(*variable).key.is_null() {
+                    let key = CStr::from_ptr((*variable).key).to_string_lossy().into_owned();
+                    let value = CStr::from_ptr((*variable).value).to_string_lossy().into_owned();
+                    with(|r| r.variables.push((key, value)));
+                    variable = variable.add(1);
+                }
+                true
+            }
+            RETRO_ENVIRONMENT_GET_VARIABLE => {
+                let variable = &mut *(data as *mut Variable);
+                let key = CStr::from_ptr(variable.key).to_string_lossy().into_owned();
+                // the string stays in `options` until the option is changed
+                variable.value = with(|r| r.options.get(&key).map_or(std::ptr::null(), |value| value.as_ptr()));
+                !variable.value.is_null()
+            }
+            RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+                *(data as *mut bool) = with(|r| std::mem::take(&mut r.options_updated));
+                true
+            }
+            RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO => {
+                let av_info = *(data as *const SystemAvInfo);
+                with(|r| r.av_info = Some(av_info));
+                true
+            }
+            _ => false,
+        };
+    }
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let (width, height) = (width as usize, height as usize);
+    assert_eq!(pitch, width * 4);
+    let pixels = unsafe { std::slice::from_raw_parts(data as *const u32, width * height) };
+    with(|r| r.frame = Some((width, height, pixels.to_vec())));
+}
+
+extern "C" fn audio_sample(_left: i16, _right: i16) {
+    panic!("audio should come in batches");
+}
+
+extern "C" fn audio_batch(_data: *const i16, frames: usize) -> usize {
+    with(|r| r.audio_frames += frames);
+    return frames;
+}
+
+extern "C" fn input_poll() {
+    with(|r| r.polls += 1);
+    // frontends may call into the core from a callback, which mustn't find
+    // it locked
+    retro_get_region();
+}
+
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    assert_eq!(device, RETRO_DEVICE_JOYPAD);
+    return with(|r| r.held.contains(&(port, id))) as i16;
+}
+
+/// Start the core afresh, with `options` picked, and load `rom` from the
+/// ROMs bundled with the emulator.
+fn start(rom: &str, options: &[(&str, &str)]) -> bool {
+    *recorded() = Some(Recorded::default());
+    with(|r| {
+        for (key, value) in options {
+            r.options.insert(key.to_string(), CString::new(*value).unwrap());
+        }
+    });
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+    retro_set_controller_port_device(0, RETRO_DEVICE_JOYPAD);
+    retro_set_controller_port_device(1, RETRO_DEVICE_JOYPAD);
+
+    let bytes = std::fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join(rom)).unwrap();
+    let info = GameInfo { path: std::ptr::null(), data: bytes.as_ptr() as *const c_void, size: bytes.len(), meta: std::ptr::null() };
+    return unsafe { retro_load_game(&info) };
+}
+
+fn stop() {
+    retro_unload_game();
+    retro_deinit();
+}
+
+fn av_info() -> SystemAvInfo {
+    let mut info = std::mem::MaybeUninit::<SystemAvInfo>::zeroed();
+    unsafe {
+        retro_get_system_av_info(info.as_mut_ptr());
+        return info.assume_init();
+    }
+}
+
+fn frame() -> Vec<u32> {
+    return with(|r| r.frame.clone()).unwrap().2;
+}
+
+#[test]
+fn test_system_info() {
+    let mut info = std::mem::MaybeUninit::<SystemInfo>::zeroed();
+    let info = unsafe {
+        retro_get_system_info(info.as_mut_ptr());
+        info.assume_init()
+    };
+    let text = |text: *const c_char| unsafe { CStr::from_ptr(text) }.to_str().unwrap().to_string();
+    assert_eq!(text(info.library_name), "rustARI");
+    assert_eq!(text(info.valid_extensions), "a26|bin");
+    assert!(!info.need_fullpath);
+    assert_eq!(retro_api_version(), RETRO_API_VERSION);
+}
+
+#[test]
+fn test_runs_a_game() {
+    let _turn = TURN.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(start("spaceInvaders.a26", &[]));
+    let keys: Vec<String> = with(|r| r.variables.iter().map(|(key, _)| key.clone()).collect());
+    assert_eq!(keys, ["rustari_tv", "rustari_palette"]);
+    assert_eq!(with(|r| r.pixel_format), Some(RETRO_PIXEL_FORMAT_XRGB8888));
+
+    let info = av_info();
+    assert_eq!((info.geometry.base_width, info.geometry.base_height), (160, 210));
+    assert!((info.timing.fps - 59.92).abs() < 0.01);
+    assert_eq!(retro_get_region(), RETRO_REGION_NTSC);
+
+    for _ in 0..60 {
+        retro_run();
+    }
+    let (width, height, pixels) = with(|r| r.frame.clone()).unwrap();
+    assert_eq!((width, height), (160, 210));
+    // something other than the background was drawn
+    assert!(pixels.iter().any(|&pixel| pixel != pixels[0]));
+    assert!(pixels.iter().all(|&pixel| pixel >> 24 == 0));
+    // two samples a line, a frame of lines each run
+    let audio_frames = with(|r| r.audio_frames);
+    assert!((59 * 2 * 262..=61 * 2 * 262).contains(&audio_frames));
+    assert_eq!(with(|r| r.polls), 60);
+    assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), 128);
+    assert!(!retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM).is_null());
+    stop();
+    assert!(retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM).is_null());
+}
+
+#[test]
+fn test_save_states() {
+    let _turn = TURN.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(start("spaceInvaders.a26", &[]));
+    for _ in 0..30 {
+        retro_run();
+    }
+    let mut state = vec![0u8; retro_serialize_size()];
+    assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+    assert!(!unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len() - 1) });
+
+    // hold fire and move, so the frames after differ from a run without
+    with(|r| r.held = vec![(0, RETRO_DEVICE_ID_JOYPAD_B), (0, RETRO_DEVICE_ID_JOYPAD_LEFT)]);
+    for _ in 0..20 {
+        retro_run();
+    }
+    let after = frame();
+
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+    for _ in 0..20 {
+        retro_run();
+    }
+    assert_eq!(frame(), after);
+
+    let garbage = [0u8; 16];
+    assert!(!unsafe { retro_unserialize(garbage.as_ptr() as *const c_void, garbage.len()) });
+    stop();
+}
+
+#[test]
+fn test_core_options() {
+    let _turn = TURN.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(start("spaceInvaders.a26", &[("rustari_tv", "PAL")]));
+    let info = av_info();
+    assert_eq!(info.geometry.base_height, 250);
+    assert!((info.timing.fps - 49.86).abs() < 0.01);
+    assert_eq!(retro_get_region(), RETRO_REGION_PAL);
+    retro_run();
+    assert_eq!(with(|r| r.frame.clone()).unwrap().1, 250);
+
+    // back to the cartridge's own standard, announced to the frontend
+    with(|r| {
+        r.options.insert("rustari_tv".to_string(), CString::new("auto").unwrap());
+        r.options_updated = true;
+    });
+    retro_run();
+    let announced = with(|r| r.av_info).unwrap();
+    assert_eq!(announced.geometry.base_height, 210);
+    assert_eq!(retro_get_region(), RETRO_REGION_NTSC);
+
+    // the palette changes the colors of the same frame but not the standard
+    let mut state = vec![0u8; retro_serialize_size()];
+    assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+    retro_run();
+    let ntsc = frame();
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+    with(|r| {
+        r.options.insert("rustari_palette".to_string(), CString::new("SECAM").unwrap());
+        r.options_updated = true;
+    });
+    retro_run();
+    assert_ne!(frame(), ntsc);
+    assert_eq!(retro_get_region(), RETRO_REGION_NTSC);
+    stop();
+}
+
+#[test]
+fn test_rejects_what_it_cant_load() {
+    let _turn = TURN.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(start("spaceInvaders.a26", &[]));
+    stop();
+    assert!(!unsafe { retro_load_game(std::ptr::null()) });
+    let empty = GameInfo { path: std::ptr::null(), data: [0u8; 0].as_ptr() as *const c_void, size: 0, meta: std::ptr::null() };
+    assert!(!unsafe { retro_load_game(&empty) });
+    assert_eq!(retro_serialize_size(), 0);
+    retro_run();
+}
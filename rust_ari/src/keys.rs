@@ -0,0 +1,150 @@
+//! Key bindings: which key does what in the window. Each action has a
+//! default, which the `[keys]` table of the config file can change, giving
+//! keys by the names winit has for them.
+
+use winit::event::VirtualKeyCode as Key;
+
+/// The keys that can be bound, by name.
+const KEY_NAMES: &[(&str, Key)] = &[
+   ("Key0", Key::Key0), ("Key1", Key::Key1), ("Key2", Key::Key2), ("Key3", Key::Key3),
+   ("Key4", Key::Key4), ("Key5", Key::Key5), ("Key6", Key::Key6), ("Key7", Key::Key7),
+   ("Key8", Key::Key8), ("Key9", Key::Key9), ("A", Key::A), ("B", Key::B), ("C", Key::C),
+   ("D", Key::D), ("E", Key::E), ("F", Key::F), ("G", Key::G), ("H", Key::H), ("I", Key::I),
+   ("J", Key::J), ("K", Key::K), ("L", Key::L), ("M", Key::M), ("N", Key::N), ("O", Key::O),
+   ("P", Key::P), ("Q", Key::Q), ("R", Key::R), ("S", Key::S), ("T", Key::T), ("U", Key::U),
+   ("V", Key::V), ("W", Key::W), ("X", Key::X), ("Y", Key::Y), ("Z", Key::Z), ("F1", Key::F1),
+   ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4), ("F5", Key::F5), ("F6", Key::F6),
+   ("F7", Key::F7), ("F8", Key::F8), ("F9", Key::F9), ("F10", Key::F10), ("F11", Key::F11),
+   ("F12", Key::F12), ("Escape", Key::Escape), ("Tab", Key::Tab), ("Back", Key::Back),
+   ("Return", Key::Return), ("Space", Key::Space), ("Insert", Key::Insert),
+   ("Delete", Key::Delete), ("Home", Key::Home), ("End", Key::End), ("PageUp", Key::PageUp),
+   ("PageDown", Key::PageDown), ("Up", Key::Up), ("Down", Key::Down), ("Left", Key::Left),
+   ("Right", Key::Right), ("Pause", Key::Pause), ("Numpad0", Key::Numpad0),
+   ("Numpad1", Key::Numpad1), ("Numpad2", Key::Numpad2), ("Numpad3", Key::Numpad3),
+   ("Numpad4", Key::Numpad4), ("Numpad5", Key::Numpad5), ("Numpad6", Key::Numpad6),
+   ("Numpad7", Key::Numpad7), ("Numpad8", Key::Numpad8), ("Numpad9", Key::Numpad9),
+   ("Add", Key::Add), ("Subtract", Key::Subtract), ("Multiply", Key::Multiply),
+   ("Divide", Key::Divide), ("Decimal", Key::Decimal), ("NumpadEnter", Key::NumpadEnter),
+   ("Grave", Key::Grave), ("Minus", Key::Minus), ("Equals", Key::Equals),
+   ("LBracket", Key::LBracket), ("RBracket", Key::RBracket), ("Backslash", Key::Backslash),
+   ("Semicolon", Key::Semicolon), ("Apostrophe", Key::Apostrophe), ("Comma", Key::Comma),
+   ("Period", Key::Period), ("Slash", Key::Slash), ("LShift", Key::LShift),
+   ("RShift", Key::RShift), ("LControl", Key::LControl), ("RControl", Key::RControl),
+   ("LAlt", Key::LAlt), ("RAlt", Key::RAlt),
+];
+
+/// The key bound to each action.
+pub struct Bindings {
+   pub up: Key,
+   pub down: Key,
+   pub left: Key,
+   pub right: Key,
+   pub fire: Key,
+   pub select: Key,
+   pub reset: Key,
+   pub color: Key,
+   pub bw: Key,
+   pub adjust: Key,
+   pub adjust_up: Key,
+   pub adjust_down: Key,
+   pub save_state: Key,
+   pub next_slot: Key,
+   pub load_state: Key,
+   pub rewind: Key,
+   pub pause: Key,
+   pub advance: Key,
+   pub slower: Key,
+   pub faster: Key,
+   pub normal_speed: Key,
+   pub fast_forward: Key,
+   pub overlay: Key,
+   pub effects: Key,
+   pub fullscreen: Key,
+   pub screenshot: Key,
+   pub quit: Key,
+}
+
+impl Default for Bindings {
+   fn default() -> Bindings {
+       Bindings {
+           up: Key::Up,
+           down: Key::Down,
+           left: Key::Left,
+           right: Key::Right,
+           fire: Key::Space,
+           select: Key::F1,
+           reset: Key::F2,
+           color: Key::F3,
+           bw: Key::F4,
+           adjust: Key::Tab,
+           adjust_up: Key::PageUp,
+           adjust_down: Key::PageDown,
+           save_state: Key::F5,
+           next_slot: Key::F6,
+           load_state: Key::F7,
+           rewind: Key::Back,
+           pause: Key::P,
+           advance: Key::Period,
+           slower: Key::LBracket,
+           faster: Key::RBracket,
+           normal_speed: Key::Backslash,
+           fast_forward: Key::Grave,
+           overlay: Key::F9,
+           effects: Key::F10,
+           fullscreen: Key::F11,
+           screenshot: Key::F12,
+           quit: Key::Escape,
+       }
+   }
+}
+
+impl Bindings {
+   /// The defaults, with the keys given for actions, as pairs of an action
+   /// and the name of a key, bound instead.
+   pub fn new(keys: &[(String, String)]) -> Result<Bindings, String> {
+       let mut bindings = Bindings::default();
+       for (action, name) in keys {
+           let key = KEY_NAMES
+               .iter()
+               .find(|(key, _)| key.eq_ignore_ascii_case(name))
+               .map(|&(_, key)| key)
+               .ok_or(format!("unknown key for {}: {}", action, name))?;
+           *bindings.action(action).ok_or(format!("unknown action: {}", action))? = key;
+       }
+       Ok(bindings)
+   }
+
+   fn action(&mut self, name: &str) -> Option<&mut Key> {
+       let key = match name {
+           "up" => &mut self.up,
+           "down" => &mut self.down,
+           "left" => &mut self.left,
+           "right" => &mut self.right,
+           "fire" => &mut self.fire,
+           "select" => &mut self.select,
+           "reset" => &mut self.reset,
+           "color" => &mut self.color,
+           "bw" => &mut self.bw,
+           "adjust" => &mut self.adjust,
+           "adjust_up" => &mut self.adjust_up,
+           "adjust_down" => &mut self.adjust_down,
+           "save_state" => &mut self.save_state,
+           "next_slot" => &mut self.next_slot,
+           "load_state" => &mut self.load_state,
+           "rewind" => &mut self.rewind,
+           "pause" => &mut self.pause,
+           "advance" => &mut self.advance,
+           "slower" => &mut self.slower,
+           "faster" => &mut self.faster,
+           "normal_speed" => &mut self.normal_speed,
+           "fast_forward" => &mut self.fast_forward,
+           "overlay" => &mut self.overlay,
+           "effects" => &mut self.effects,
+           "fullscreen" => &mut self.fullscreen,
+           "screenshot" => &mut self.screenshot,
+           "quit" => &mut self.quit,
+           _ => return None,
+       };
+       Some(key)
+   }
+}
@@ -1,137 +1,1172 @@
-extern crate strum;
-#[macro_use]
-extern crate strum_macros;
-
 use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use std::time::{Duration, Instant};
 
-use std::time::Instant;
+mod keys;
+mod overlay;
 
-use log::error;
+use log::{error, info};
 use pixels::{wgpu::Surface, Error, Pixels, SurfaceTexture};
 use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
+use winit::event::Event;
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, Window, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
+use rustari_core::{audio, capture, disasm, headless, palette, rom_read, tia, Cartridge, Machine};
+use rustari_core::capture::{Video, VideoFormat};
+use rustari_core::config::{Config, Settings, CONFIG_FILE};
+use rustari_core::crt::{self, Phosphor};
+use rustari_core::debugger::Debugger;
+use rustari_core::emulator::DETECT_AFTER_FRAMES;
+use rustari_core::error::Fault;
+use rustari_core::movie::{Movie, Player};
+use rustari_core::pacing::Pacer;
+use rustari_core::palette::{Adjustment, Adjustments, ColorTable};
+use rustari_core::properties::{self, Controller, Properties};
+use rustari_core::rewind::Rewind;
+use rustari_core::riot::Joystick;
+use rustari_core::trace::Tracer;
+use rustari_core::tv::{Palette, TvStandard};
+use rustari_core::viewport::{self, Crop, Scaling};
+
+use keys::Bindings;
+use overlay::Overlay;
+
+/// Save state slots, numbered 0 to SLOTS - 1.
+const SLOTS: usize = 10;
+
+/// Frames kept for rewinding: three minutes at 60 frames a second, unless
+/// they take more than `REWIND_SIZE` bytes.
+const REWIND_FRAMES: usize = 3 * 60 * 60;
+/// Memory rewinding may take. A busy picture changes most of the TIA's frame
+/// buffer every frame, so at worst that's a minute's worth.
+const REWIND_SIZE: usize = 256 << 20;
+
+/// Speeds stepped through with `[` and `]`, relative to the real console.
+/// `None` runs as fast as the host can.
+const SPEEDS: [Option<f64>; 6] = [Some(0.25), Some(0.5), Some(1.0), Some(2.0), Some(4.0), None];
+
+/// The index of normal speed in `SPEEDS`.
+const NORMAL_SPEED: usize = 2;
+
+/// The window's size, as a multiple of the picture's, unless `--scale` or
+/// the size it was left at says otherwise.
+const DEFAULT_SCALE: u32 = 3;
 
-mod rom_read;
-mod mem_load;
-mod atari;
+/// The file in the config directory the window's size is kept in.
+const WINDOW_SIZE_FILE: &str = "window-size";
 
-const TARGET_FPS: u64 = 30;
+/// The longest a redraw spends running frames, so a host that can't keep up,
+/// or an uncapped speed, still leaves time to draw and handle input.
+const DRAW_BUDGET: Duration = Duration::from_millis(15);
 
-/// Representation of the application state. In this example, a box will bounce around the screen.
+/// Representation of the application state: the TV standard frames are shown
+/// in, the colors used to draw them, how they're fitted to the window and
+/// the CRT effects over them, the debug overlay, the save state
+/// slot in use, the frames to rewind through, any movie being recorded or
+/// played, any video being captured, the speed frames are paced at and
+/// whether the machine is paused or stopped by a CPU fault.
 struct World {
-   box_x: i16,
-   box_y: i16,
-   velocity_x: i16,
-   velocity_y: i16,
+   standard: TvStandard,
+   auto_detect: bool,
+   /// The `.pal` file's palette for each standard, read once at start.
+   custom_palettes: Option<[Palette; 3]>,
+   adjustments: Adjustments,
+   adjusting: Adjustment,
+   colors: ColorTable,
+   /// The last frame, one pixel per color clock.
+   picture: Vec<u8>,
+   /// The cartridge's properties, for the lines it shows.
+   properties: Properties,
+   /// The picture as shown, cropped and with any effects, from the last
+   /// frame run.
+   shown: Vec<u8>,
+   /// Work out `shown` again on the next draw, even if no frame is run.
+   dirty: bool,
+   /// Show the whole frame rather than what a TV would.
+   overscan: bool,
+   scaling: Scaling,
+   phosphor: Option<Phosphor>,
+   /// How much the gaps between scanlines are darkened.
+   scanlines: Option<f32>,
+   composite: bool,
+   /// Whether the CRT effects are on, switched with F10.
+   effects: bool,
+   overlay: Overlay,
+   rom: PathBuf,
+   /// Where save states go, if not next to the ROM.
+   save_dir: Option<PathBuf>,
+   slot: usize,
+   rewind: Rewind,
+   rewinding: bool,
+   session: Option<Session>,
+   capture: Option<VideoCapture>,
+   /// Save the next frame drawn as a PNG.
+   screenshot: bool,
+   /// Stopped at a CPU fault until rewound.
+   halted: bool,
+   paused: bool,
+   /// Run one frame while paused.
+   advance: bool,
+   pacer: Pacer,
+   /// The index in `SPEEDS` picked with `[` and `]`.
+   speed: usize,
+   /// Running uncapped while the fast-forward key is held.
+   fast_forward: bool,
+}
+
+/// Read a `.pal` file and take its palette for each TV standard, logging
+/// why the built-in palettes are used instead if it can't be.
+fn load_palettes(path: &Path) -> Option<[Palette; 3]> {
+   let bytes = std::fs::read(path)
+       .map_err(|e| error!("using the built-in palette: {}: {}", path.display(), e))
+       .ok()?;
+   let parse = |standard| {
+       palette::parse_pal(&bytes, standard)
+           .map_err(|e| error!("using the built-in palette: {}", e))
+           .ok()
+   };
+   Some([parse(TvStandard::NTSC)?, parse(TvStandard::PAL)?, parse(TvStandard::SECAM)?])
+}
+
+/// How a speed from `SPEEDS` is shown: `x2`, or `uncapped`.
+fn speed_name(speed: Option<f64>) -> String {
+   match speed {
+      Some(speed) => format!("x{}", speed),
+      None => "uncapped".to_string(),
+   }
 }
 
 impl World {
-   /// Create a new `World` instance that can draw a moving box.
-   fn new() -> Self {
-       Self {
-           box_x: 24,
-           box_y: 16,
-           velocity_x: 1,
-           velocity_y: 1,
+   /// Create a new `World`, detecting the TV standard unless one is forced.
+   fn new(standard: Option<TvStandard>, properties: Properties, settings: &Settings, options: &Options, session: Option<Session>) -> Self {
+       let initial = standard.unwrap_or(TvStandard::NTSC);
+       let mut rewind = Rewind::new(REWIND_FRAMES);
+       rewind.set_max_size(REWIND_SIZE);
+       let mut world = Self {
+           standard: initial,
+           auto_detect: standard.is_none(),
+           custom_palettes: settings.palette.as_deref().and_then(load_palettes),
+           adjustments: Adjustments::default(),
+           adjusting: Adjustment::HUE,
+           colors: ColorTable::new(initial.palette(), initial, &Adjustments::default()),
+           picture: Vec::new(),
+           properties: properties,
+           shown: Vec::new(),
+           dirty: true,
+           overscan: settings.overscan.unwrap_or(false),
+           scaling: settings.scaling.unwrap_or(Scaling::INTEGER),
+           phosphor: settings.phosphor.map(Phosphor::new),
+           scanlines: settings.scanlines,
+           composite: settings.composite.unwrap_or(false),
+           effects: true,
+           overlay: Overlay::new(),
+           rom: PathBuf::from(&options.rom),
+           save_dir: settings.save_dir.clone(),
+           slot: 0,
+           rewind: rewind,
+           rewinding: false,
+           session: session,
+           capture: options.video.clone().map(|path| VideoCapture::new(path, options.sample_rate, settings.volume)),
+           screenshot: false,
+           halted: false,
+           paused: options.paused,
+           advance: false,
+           pacer: Pacer::new(),
+           speed: NORMAL_SPEED,
+           fast_forward: false,
+       };
+       world.update_colors();
+       world
+   }
+
+   /// Rebuild the color table after the standard, palette or adjustments change.
+   fn update_colors(&mut self) {
+       let custom = self.custom_palettes.as_ref().map(|palettes| &palettes[self.standard as usize]);
+       let palette = custom.unwrap_or(self.standard.palette());
+       self.colors = ColorTable::new(palette, self.standard, &self.adjustments);
+       self.dirty = true;
+   }
+
+   /// Step the selected palette adjustment up or down.
+   fn adjust(&mut self, steps: i32) {
+       let value = self.adjustments.step(self.adjusting, steps);
+       self.overlay.show(format!("{} {:.2}", self.adjusting, value));
+       self.update_colors();
+   }
+
+   /// The file for the current save state slot: the ROM's path with the
+   /// extension `.st0` to `.st9`, moved to the save state directory if
+   /// there is one.
+   fn state_path(&self) -> PathBuf {
+       let path = self.rom.with_extension(format!("st{}", self.slot));
+       match (&self.save_dir, path.file_name()) {
+           (Some(dir), Some(name)) => dir.join(name),
+           _ => path,
+       }
+   }
+
+   fn save_state(&mut self, machine: &Machine) {
+       let path = self.state_path();
+       if let Some(dir) = &self.save_dir {
+           if let Err(e) = std::fs::create_dir_all(dir) {
+               error!("failed to create {}: {}", dir.display(), e);
+           }
+       }
+       match std::fs::write(&path, machine.save_state()) {
+           Ok(()) => self.overlay.show(format!("saved state {}", self.slot)),
+           Err(e) => {
+               error!("failed to save state to {}: {}", path.display(), e);
+               self.overlay.show(format!("failed to save state {}", self.slot));
+           }
+       }
+   }
+
+   fn load_state(&mut self, machine: &mut Machine) {
+       let path = self.state_path();
+       let result = std::fs::read(&path)
+           .map_err(|e| e.to_string())
+           .and_then(|state| machine.load_state(&state).map_err(|e| e.to_string()));
+       match result {
+           Ok(()) => self.overlay.show(format!("loaded state {}", self.slot)),
+           Err(e) => {
+               error!("failed to load state from {}: {}", path.display(), e);
+               self.overlay.show(format!("failed to load state {}", self.slot));
+           }
+       }
+   }
+
+   fn next_slot(&mut self) {
+       self.slot = (self.slot + 1) % SLOTS;
+       self.overlay.show(format!("state slot {}", self.slot));
+   }
+
+   /// The first of `romname-1.png`, `romname-2.png`... next to the ROM that
+   /// doesn't exist yet.
+   fn screenshot_path(&self) -> PathBuf {
+       let stem = self.rom.file_stem().unwrap_or_default().to_string_lossy();
+       (1..)
+           .map(|n| self.rom.with_file_name(format!("{}-{}.png", stem, n)))
+           .find(|path| !path.exists())
+           .unwrap()
+   }
+
+   fn save_screenshot(&self, picture: &[u8], width: usize) {
+       let path = self.screenshot_path();
+       match capture::write_png(&path, width, picture) {
+           Ok(()) => println!("wrote {}", path.display()),
+           Err(e) => error!("failed to save screenshot to {}: {}", path.display(), e),
+       }
+   }
+
+   /// Stop at a CPU fault, handing the machine to the debugger when there's
+   /// a terminal to run it in. Once the debugger is quit the machine runs on,
+   /// unless it's still at the fault; then it stays halted until rewound.
+   fn fault(&mut self, fault: Fault, machine: &mut Machine) {
+       eprintln!("{}", rustari_core::Error::Cpu(fault));
+       if io::stdin().is_terminal() {
+           eprintln!("entering the debugger; quit it to carry on");
+           let stdin = io::stdin();
+           if let Err(e) = Debugger::new().repl(machine, &mut stdin.lock(), &mut io::stdout()) {
+               eprintln!("debugger: {}", e);
+           }
+           if machine.cpu.pc as u16 != fault.pc() {
+               return;
+           }
        }
+       eprintln!("halted; hold Backspace to rewind or press Escape to quit");
+       self.halted = true;
+   }
+
+   /// The part of the frame shown.
+   fn crop(&self) -> Crop {
+       match self.overscan {
+           true => Crop::full(self.standard),
+           false => Crop::visible(self.standard, &self.properties),
+       }
+   }
+
+   /// The size of the picture at one line a pixel, widened to its shape on
+   /// screen.
+   fn shown_size(&self) -> LogicalSize<f64> {
+       let crop = self.crop();
+       LogicalSize::new(crop.width as f64 * self.standard.pixel_aspect(), crop.height as f64)
+   }
+
+   /// Pick the speed `steps` along `SPEEDS` from the current one.
+   fn change_speed(&mut self, steps: i32) {
+       self.speed = (self.speed as i32 + steps).clamp(0, SPEEDS.len() as i32 - 1) as usize;
+       self.overlay.show(format!("speed {}", speed_name(SPEEDS[self.speed])));
+       self.update_speed();
+   }
+
+   /// Pause or resume.
+   fn toggle_pause(&mut self) {
+       self.paused = !self.paused;
+       self.overlay.show(if self.paused { "paused" } else { "resumed" }.to_string());
    }
 
-   /// Update the `World` internal state; bounce the box around the screen.
-   fn update(&mut self) {
-       if self.box_x <= 0 || self.box_x + BOX_SIZE > WIDTH as i16 {
-           self.velocity_x *= -1;
+   /// The window title: the name, and whether the machine is paused or
+   /// running at other than normal speed, which a status message only says
+   /// for a moment.
+   fn title(&self) -> String {
+       let mut title = "RUSTARI".to_string();
+       if self.paused {
+           title.push_str(" - paused");
+       } else if self.speed != NORMAL_SPEED {
+           title.push_str(&format!(" - speed {}", speed_name(SPEEDS[self.speed])));
        }
-       if self.box_y <= 0 || self.box_y + BOX_SIZE > HEIGHT as i16 {
-           self.velocity_y *= -1;
+       title
+   }
+
+   /// Pace frames at the speed picked, or uncapped while fast forwarding.
+   fn update_speed(&mut self) {
+       let speed = if self.fast_forward { None } else { SPEEDS[self.speed] };
+       if speed != self.pacer.speed() {
+           self.pacer.set_speed(speed);
        }
+   }
 
-       self.box_x += self.velocity_x;
-       self.box_y += self.velocity_y;
+   /// When the event loop should next wake up to run a frame, or to take
+   /// down a status message.
+   fn control_flow(&self) -> ControlFlow {
+       let message_ends = self.overlay.message_ends();
+       if (self.halted || self.paused) && !self.rewinding {
+           return message_ends.map_or(ControlFlow::Wait, ControlFlow::WaitUntil);
+       }
+       match (self.pacer.next(), message_ends) {
+           (Some(next), Some(ends)) => ControlFlow::WaitUntil(next.min(ends)),
+           (Some(next), None) => ControlFlow::WaitUntil(next),
+           (None, _) => ControlFlow::Poll,
+       }
    }
 
-   /// Draw the `World` state to the frame buffer.
+   /// Run the machine for a frame, or go back one while rewinding, and
+   /// capture it.
+   fn step(&mut self, machine: &mut Machine) {
+       if self.rewinding {
+           // at the oldest frame kept, stay there
+           self.rewind.step_back(machine);
+           self.halted = false;
+       } else {
+           let result = match &mut self.session {
+               Some(session) => session.run_frame(machine),
+               None => machine.run_frame(),
+           };
+           match result {
+               Ok(()) => self.rewind.record(machine, machine.tia().frame_count),
+               Err(fault) => self.fault(fault, machine),
+           }
+       }
+       // there's no audio output yet, other than to video
+       let samples = machine.board.tia.audio.take_samples();
+
+       let lines = machine.tia().frame_lines;
+       if self.auto_detect && machine.tia().frame_count > DETECT_AFTER_FRAMES {
+           let detected = TvStandard::detect(lines);
+           if detected != self.standard {
+               self.standard = detected;
+               self.update_colors();
+           }
+       }
+       let settled = !self.auto_detect || machine.tia().frame_count > DETECT_AFTER_FRAMES;
+       if let (Some(capture), true) = (&mut self.capture, settled) {
+           self.picture.resize(WIDTH as usize * self.standard.scanlines() * 4, 0);
+           self.colors.render(machine.tia(), self.standard.color_loss(lines), &mut self.picture);
+           capture.frame(self.standard, &self.picture, &samples);
+       }
+   }
+
+   /// Run the frames that are due, or go back through them while
+   /// rewinding, and draw the latest to the frame buffer, `window_width`
+   /// pixels wide. While paused, only a frame asked for with frame advance
+   /// is run.
    ///
    /// Assumes the default texture format: [`wgpu::TextureFormat::Rgba8UnormSrgb`]
-   fn draw(&self, frame: &mut [u8], atari: &mut atari::Atari, timer: &mut usize) {
-      
-       for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-           
-           let x = (i % WIDTH as usize) as i16;
-           let y = (i / WIDTH as usize) as i16;
-
-           let mut rgba = if atari.read_mem(0x09) == 0x30 {
-               [0xff, 0x00, 0x00, 0xff]
-           } else {
-               [0x00, 0x00, 0x00, 0xff]
+   fn draw(&mut self, frame: &mut [u8], window_width: usize, machine: &mut Machine) {
+
+       let start = Instant::now();
+       let mut stepped = false;
+       while start.elapsed() < DRAW_BUDGET {
+           let now = Instant::now();
+           if self.halted && !self.rewinding {
+               break;
+           }
+           if self.paused && !self.rewinding {
+               if self.advance {
+                   self.advance = false;
+                   self.step(machine);
+                   stepped = true;
+               }
+               break;
+           }
+           if !self.pacer.due(now) {
+               break;
+           }
+           self.step(machine);
+           stepped = true;
+           self.pacer.ran(self.standard.frame_duration(machine.tia().frame_lines), now);
+       }
+
+       let crop = self.crop();
+       if stepped || self.dirty {
+           self.dirty = false;
+           let lines = machine.tia().frame_lines;
+           self.picture.resize(WIDTH as usize * self.standard.scanlines() * 4, 0);
+           self.colors.render(machine.tia(), self.standard.color_loss(lines), &mut self.picture);
+           self.shown = crop.apply(&self.picture);
+           if self.effects {
+               // the glow fades a frame at a time, not a draw at a time
+               if let (Some(phosphor), true) = (&mut self.phosphor, stepped) {
+                   phosphor.apply(&mut self.shown);
+               }
+               if self.composite {
+                   crt::composite(&mut self.shown, crop.width);
+               }
+           }
+       }
+
+       // captured as the TIA drew it, without effects or the overlay
+       if self.screenshot {
+           self.screenshot = false;
+           self.save_screenshot(&crop.apply(&self.picture), crop.width);
+       }
+       let mut shown = self.shown.clone();
+       self.overlay.draw(machine, &mut shown, crop.width);
+
+       let window_height = frame.len() / 4 / window_width;
+       let aspect = self.standard.pixel_aspect();
+       let placement = viewport::fit(crop.width, crop.height, aspect, window_width, window_height, self.scaling);
+       viewport::scale(&shown, crop.width, frame, window_width, placement, self.scaling);
+       if let (Some(darkness), true) = (self.scanlines, self.effects) {
+           crt::scanlines(frame, window_width, placement, crop.height, darkness);
+       }
+   }
+}
+
+/// A video captured from the frames drawn, started once the TV standard has
+/// settled so that its size is right.
+struct VideoCapture {
+   /// Where the video will go, until it has been started.
+   path: Option<PathBuf>,
+   /// The rate to write the audio at, if not the TIA's.
+   sample_rate: Option<u32>,
+   /// How loud the audio is, if not as generated.
+   volume: Option<f32>,
+   video: Option<Video>,
+}
+
+impl VideoCapture {
+   fn new(path: PathBuf, sample_rate: Option<u32>, volume: Option<f32>) -> VideoCapture {
+       VideoCapture { path: Some(path), sample_rate: sample_rate, volume: volume, video: None }
+   }
+
+   /// Add a frame rendered in `standard`, `WIDTH` pixels wide, and the audio
+   /// generated with it. After an error nothing more is captured.
+   fn frame(&mut self, standard: TvStandard, rgba: &[u8], samples: &[u8]) {
+       if let Some(path) = self.path.take() {
+           match Video::create(&path, WIDTH as usize, rgba.len() / 4 / WIDTH as usize, standard, self.sample_rate) {
+               Ok(video) => self.video = Some(video),
+               Err(e) => error!("failed to start video {}: {}", path.display(), e),
+           }
+       }
+       if let Some(video) = &mut self.video {
+           let mut samples = samples.to_vec();
+           if let Some(volume) = self.volume {
+               audio::set_volume(&mut samples, volume);
+           }
+           if let Err(e) = video.frame(rgba).and_then(|()| video.audio(&samples)) {
+               error!("failed to write video: {}", e);
+               self.video = None;
+           }
+       }
+   }
+
+   /// Finish the video, reporting the files written. Returns whether all
+   /// went well.
+   fn finish(self) -> bool {
+       let video = match self.video {
+           Some(video) => video,
+           // never started, or stopped by an error already reported
+           None => {
+               if let Some(path) = &self.path {
+                   eprintln!("no frames were captured for {}", path.display());
+               }
+               return self.path.is_some();
+           }
+       };
+       match video.finish() {
+           Ok(paths) => {
+               for path in paths {
+                   println!("wrote {}", path.display());
+               }
+               true
+           }
+           Err(e) => {
+               eprintln!("failed to write video: {}", e);
+               false
+           }
+       }
+   }
+}
+
+/// A movie being recorded, to be written out when the emulator stops, or
+/// played back.
+enum Session {
+   Recording(Movie, PathBuf),
+   Playing(Player),
+}
+
+impl Session {
+   /// Set up the movie `options` ask for, putting `machine` where one being
+   /// played starts. Recording starts from the state loaded with `--state`,
+   /// or else from power on.
+   fn start(options: &Options, machine: &mut Machine) -> Result<Option<Session>, String> {
+       if let Some(path) = &options.play {
+           let bytes = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+           let movie = Movie::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+           info!("playing {} frames from {}", movie.len(), path.display());
+           return Ok(Some(Session::Playing(Player::new(movie, machine)?)));
+       }
+       if let Some(path) = &options.record {
+           let movie = match options.state {
+               Some(_) => Movie::from_state(machine),
+               None => Movie::from_power_on(machine),
            };
+           return Ok(Some(Session::Recording(movie, path.clone())));
+       }
+       Ok(None)
+   }
 
-           if x == 68 || y == 37 || y == 229 {
-              rgba = [0x00, 0xff, 0x00, 0xff];
+   /// Run a frame, recording its input or feeding it the movie's.
+   fn run_frame(&mut self, machine: &mut Machine) -> Result<(), Fault> {
+       match self {
+           Session::Recording(movie, _) => movie.record_frame(machine),
+           Session::Playing(player) => {
+               let finished = player.finished();
+               if let Some(divergence) = player.play_frame(machine)? {
+                   error!("{}", divergence);
+               }
+               if !finished && player.finished() {
+                   info!("movie finished after {} frames", player.played());
+               }
+               Ok(())
            }
-           
-           if *timer > atari.cycles * 3 {
-               atari.execute_step();
+       }
+   }
+
+   /// Write a recording out, or report how playback went. Returns whether
+   /// all went well.
+   fn finish(self) -> bool {
+       match self {
+           Session::Recording(movie, path) => match std::fs::write(&path, movie.to_bytes()) {
+               Ok(()) => {
+                   println!("recorded {} frames to {}", movie.len(), path.display());
+                   true
+               }
+               Err(e) => {
+                   eprintln!("failed to write {}: {}", path.display(), e);
+                   false
+               }
+           },
+           // a divergence was logged when it happened
+           Session::Playing(player) => {
+               if player.divergence().is_none() {
+                   println!("movie matched for {} frames", player.played());
+               }
+               player.divergence().is_none()
            }
-           *timer = *timer + 1;
+       }
+   }
+}
+
+const USAGE: &str = "usage: rust_ari [run | headless | debug | disasm | info] <rom> [options]
+try rust_ari --help for the options";
 
-           pixel.copy_from_slice(&rgba);
+const HELP: &str = "rust_ari: an Atari 2600 emulator
+
+usage: rust_ari [COMMAND] <rom> [options]
+
+commands:
+  run         play in a window (the default)
+  headless    run without a window, writing the last frame, audio and RAM
+  debug       debug from a prompt in the terminal
+  disasm      print a disassembly of the ROM
+  info        print the detected bank type, MD5 and TV standard
+
+config (all but disasm):
+  --config FILE           settings from FILE rather than config.toml in the
+                          config directory; the options below override them
+
+cartridge (all but disasm):
+  --tv STANDARD           NTSC, PAL or SECAM, rather than detecting it
+  --cart TYPE             bank switching: 2K, 4K, F8, F8SC, F6, F6SC, F4, F4SC,
+                          FA, FE, E0 or 3F
+  --left CONTROLLER       the controller in each port: JOYSTICK, PADDLES,
+  --right CONTROLLER      BOOSTERGRIP, DRIVING, KEYBOARD, TRAKBALL or GENESIS
+
+colors (run, headless):
+  --palette FILE.pal      colors from a 128 or 256 color .pal file
+
+window (run):
+  --scale N               open the window N times the picture's size, rather
+                          than the size it was left at
+  --scaling MODE          integer, for whole multiples of the picture's height
+                          (the default), or smooth, to fill the window
+  --overscan              show the whole frame, blanking and all, rather than
+                          what a TV would
+  --fullscreen            fill the screen; F11 switches
+  --paused                start paused; P pauses and resumes
+  --save-dir DIR          keep save states in DIR rather than beside the ROM
+
+CRT effects (run; F10 switches them off and on):
+  --phosphor DECAY        let each frame glow into the next, keeping DECAY of
+                          its brightness, from 0 to 1; 0.5 hides most flicker
+  --scanlines DARKNESS    darken the gaps between lines, from 0 to 1
+  --composite             blur colors along the lines like an NTSC composite
+                          signal
+
+headless:
+  --frames N              frames to run; a played movie's length by default
+  --out PREFIX            write PREFIX.ppm, .wav and .ram (the ROM's name by default)
+  --screenshot FILE.png   also save the last frame as a PNG
+
+audio (run, headless):
+  --volume V              scale the audio written, from 0 for silence to 1
+
+capture (run, headless):
+  --video FILE            capture to a .gif, .y4m or .avi, with a .wav beside it
+  --sample-rate HZ        resample the audio written, which is otherwise at the
+                          TIA's rate of about 31.4 kHz
+
+movies and states (run, headless; --state in debug too):
+  --state FILE            start from a save state
+  --record MOVIE          record the input to MOVIE
+  --play MOVIE            play MOVIE back, checking each frame
+
+tracing (run, headless, debug):
+  --trace                 log every instruction executed to stderr
+  --trace-range FIRST-LAST
+                          only instructions in this range, e.g. '$F000-$F0FF';
+                          repeatable
+
+  -h, --help              show this help
+";
+
+/// What to do with the ROM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+   Run,
+   Headless,
+   Debug,
+   Disasm,
+   Info,
+}
+
+impl Command {
+   fn parse(name: &str) -> Option<Command> {
+       match name {
+           "run" => Some(Command::Run),
+           "headless" => Some(Command::Headless),
+           "debug" => Some(Command::Debug),
+           "disasm" => Some(Command::Disasm),
+           "info" => Some(Command::Info),
+           _ => None,
+       }
+   }
+
+   fn name(self) -> &'static str {
+       match self {
+           Command::Run => "run",
+           Command::Headless => "headless",
+           Command::Debug => "debug",
+           Command::Disasm => "disasm",
+           Command::Info => "info",
        }
    }
 }
 
+/// The commands each option applies to.
+const APPLIES: &[(&str, &[Command])] = &[
+   ("--config", &[Command::Run, Command::Headless, Command::Debug, Command::Info]),
+   ("--tv", &[Command::Run, Command::Headless, Command::Debug, Command::Info]),
+   ("--cart", &[Command::Run, Command::Headless, Command::Debug, Command::Info]),
+   ("--left", &[Command::Run, Command::Headless, Command::Debug, Command::Info]),
+   ("--right", &[Command::Run, Command::Headless, Command::Debug, Command::Info]),
+   ("--palette", &[Command::Run, Command::Headless]),
+   ("--scale", &[Command::Run]),
+   ("--scaling", &[Command::Run]),
+   ("--overscan", &[Command::Run]),
+   ("--phosphor", &[Command::Run]),
+   ("--scanlines", &[Command::Run]),
+   ("--composite", &[Command::Run]),
+   ("--fullscreen", &[Command::Run]),
+   ("--save-dir", &[Command::Run]),
+   ("--volume", &[Command::Run, Command::Headless]),
+   ("--paused", &[Command::Run]),
+   ("--frames", &[Command::Headless]),
+   ("--out", &[Command::Headless]),
+   ("--screenshot", &[Command::Headless]),
+   ("--video", &[Command::Run, Command::Headless]),
+   ("--sample-rate", &[Command::Run, Command::Headless]),
+   ("--state", &[Command::Run, Command::Headless, Command::Debug]),
+   ("--record", &[Command::Run, Command::Headless]),
+   ("--play", &[Command::Run, Command::Headless]),
+   ("--trace", &[Command::Run, Command::Headless, Command::Debug]),
+   ("--trace-range", &[Command::Run, Command::Headless, Command::Debug]),
+];
 
-fn main() {
+/// Command line options.
+struct Options {
+   command: Command,
+   rom: String,
+   /// A config file to use rather than the user's.
+   config: Option<PathBuf>,
+   /// The settings given, over those in the config file.
+   settings: Settings,
+   paused: bool,
+   frames: Option<usize>,
+   out: Option<PathBuf>,
+   tracer: Option<Tracer>,
+   state: Option<PathBuf>,
+   record: Option<PathBuf>,
+   play: Option<PathBuf>,
+   video: Option<PathBuf>,
+   sample_rate: Option<u32>,
+   screenshot: Option<PathBuf>,
+}
+
+/// Parse a property value the way `stella.pro` spells it, in any case.
+fn parse_property<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+   value.to_uppercase().parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
 
-   let args: Vec<String> = env::args().collect();
+/// Parse a number from 0 to 1.
+fn parse_fraction(flag: &str, value: &str) -> Result<f32, String> {
+   value
+       .parse()
+       .ok()
+       .filter(|n| (0.0..=1.0).contains(n))
+       .ok_or(format!("{} needs a number from 0 to 1, not {}", flag, value))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+   let command = args.first().and_then(|arg| Command::parse(arg));
+   let args = if command.is_some() { &args[1..] } else { args };
+   let command = command.unwrap_or(Command::Run);
+   let mut positional = Vec::new();
+   let mut given = Vec::new();
+   let mut config = None;
+   let mut settings = Settings::default();
+   let mut paused = false;
+   let mut frames = None;
+   let mut out = None;
+   let mut tracer = None;
+   let mut state = None;
+   let mut record = None;
+   let mut play = None;
+   let mut video = None;
+   let mut sample_rate = None;
+   let mut screenshot = None;
 
-   assert_eq!(args.len(), 2, "wrong number of arguments provided! provide a filename only");
-   let filename = &args[1];
+   let mut iter = args.iter();
+   while let Some(arg) = iter.next() {
+       if !arg.starts_with("--") {
+           positional.push(arg.clone());
+           continue;
+       }
+       let flag = arg.as_str();
+       if !APPLIES.iter().any(|(name, _)| *name == flag) {
+           return Err(format!("unknown option: {}", flag));
+       }
+       given.push(flag);
+       let mut value = || iter.next().ok_or(format!("{} needs a value", flag));
+       match flag {
+           "--config" => config = Some(PathBuf::from(value()?)),
+           "--tv" => settings.tv = Some(parse_property(flag, value()?)?),
+           "--cart" => settings.cart = Some(parse_property(flag, value()?)?),
+           "--left" => settings.controllers[0] = Some(parse_property(flag, value()?)?),
+           "--right" => settings.controllers[1] = Some(parse_property(flag, value()?)?),
+           "--palette" => settings.palette = Some(PathBuf::from(value()?)),
+           "--scale" => {
+               let n = value()?;
+               settings.scale = Some(n.parse().ok().filter(|&n| n > 0).ok_or(format!("invalid scale: {}", n))?);
+           }
+           "--scaling" => settings.scaling = Some(parse_property(flag, value()?)?),
+           "--overscan" => settings.overscan = Some(true),
+           "--phosphor" => settings.phosphor = Some(parse_fraction(flag, value()?)?),
+           "--scanlines" => settings.scanlines = Some(parse_fraction(flag, value()?)?),
+           "--composite" => settings.composite = Some(true),
+           "--fullscreen" => settings.fullscreen = Some(true),
+           "--volume" => settings.volume = Some(parse_fraction(flag, value()?)?),
+           "--save-dir" => settings.save_dir = Some(PathBuf::from(value()?)),
+           "--paused" => paused = true,
+           "--frames" => {
+               let n = value()?;
+               frames = Some(n.parse().map_err(|_| format!("invalid frame count: {}", n))?);
+           }
+           "--out" => out = Some(PathBuf::from(value()?)),
+           "--state" => state = Some(PathBuf::from(value()?)),
+           "--record" => record = Some(PathBuf::from(value()?)),
+           "--play" => play = Some(PathBuf::from(value()?)),
+           "--video" => video = Some(PathBuf::from(value()?)),
+           "--sample-rate" => {
+               let hz = value()?;
+               sample_rate = Some(hz.parse().ok().filter(|&hz| hz > 0).ok_or(format!("invalid sample rate: {}", hz))?);
+           }
+           "--screenshot" => screenshot = Some(PathBuf::from(value()?)),
+           "--trace" => {
+               tracer.get_or_insert_with(Tracer::new);
+           }
+           "--trace-range" => {
+               tracer.get_or_insert_with(Tracer::new).add_range_text(value()?)?;
+           }
+           _ => unreachable!(),
+       }
+   }
 
-   //println!("reading file: {}", filename);
+   for flag in given {
+       let (_, commands) = APPLIES.iter().find(|(name, _)| *name == flag).unwrap();
+       if !commands.contains(&command) {
+           return Err(format!("{} doesn't apply to {}", flag, command.name()));
+       }
+   }
+   if positional.len() != 1 {
+       return Err("provide one ROM".to_string());
+   }
+   if command == Command::Headless && frames.is_none() && play.is_none() {
+       return Err("headless needs --frames N".to_string());
+   }
+   if video.as_ref().is_some_and(|path| VideoFormat::from_path(path).is_none()) {
+       return Err("--video needs a file ending in .gif, .y4m or .avi".to_string());
+   }
+   if record.is_some() && play.is_some() {
+       return Err("--record and --play can't be combined".to_string());
+   }
+   if play.is_some() && state.is_some() {
+       return Err("--play starts where the movie does, so it can't be combined with --state".to_string());
+   }
 
-   let rom = rom_read::get_file_as_byte_vec(filename);
-   let atari : atari::Atari = atari::Atari::new(mem_load::write_rom_to_mem(rom), 0x1000);
-   main_loop(atari).unwrap();
+   Ok(Options {
+       command: command,
+       rom: positional[0].clone(),
+       config: config,
+       settings: settings,
+       paused: paused,
+       frames: frames,
+       out: out,
+       tracer: tracer,
+       state: state,
+       record: record,
+       play: play,
+       video: video,
+       sample_rate: sample_rate,
+       screenshot: screenshot,
+   })
 }
 
-const WIDTH: u32 = 228;
-const HEIGHT: u32 = 262;
-const BOX_SIZE: i16 = 64;
+/// Frames `info` runs to detect the TV standard from the scanline count.
+const INFO_FRAMES: usize = 60;
+
+/// Print what was found out about a cartridge: its size, MD5, properties and
+/// TV standard, detected by running it unless its properties give one.
+fn print_info(path: &str, cart: Cartridge) {
+   let properties = cart.properties.clone();
+   println!("file          {}", path);
+   println!("size          {} bytes", cart.rom().len());
+   println!("md5           {}", properties.md5);
+   if !properties.name.is_empty() {
+       println!("name          {}", properties.name);
+   }
+   if !properties.manufacturer.is_empty() {
+       println!("manufacturer  {}", properties.manufacturer);
+   }
+   println!("bank type     {}", cart.bank_type());
+   println!("controllers   {}, {}", properties.left_controller, properties.right_controller);
+   if let Some(standard) = TvStandard::from_format(properties.format) {
+       println!("tv standard   {} (from its properties)", standard);
+       return;
+   }
+   let mut machine = Machine::new(cart);
+   match headless::run(&mut machine, INFO_FRAMES, None) {
+       Ok(output) => println!("tv standard   {} ({} scanlines)", output.standard, machine.tia().frame_lines),
+       Err(fault) => println!("tv standard   unknown: {}", rustari_core::Error::Cpu(fault)),
+   }
+}
 
-fn main_loop(mut atari : atari::Atari) -> Result<(), Error> {
+fn main() {
+
+   let args: Vec<String> = env::args().skip(1).collect();
+   if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+       print!("{}", HELP);
+       return;
+   }
+   let options = match parse_args(&args) {
+       Ok(options) => options,
+       Err(e) => {
+           eprintln!("{}\n{}", e, USAGE);
+           process::exit(2);
+       }
+   };
    env_logger::init();
+
+   if options.command == Command::Disasm {
+       let rom = match rom_read::get_file_as_byte_vec(&options.rom) {
+           Ok(rom) => rom,
+           Err(e) => {
+               eprintln!("{}", e);
+               process::exit(1);
+           }
+       };
+       // a closed pipe, as when piping into head, isn't worth reporting
+       let _ = io::stdout().write_all(disasm::listing(&rom).as_bytes());
+       return;
+   }
+
+   let config = match load_config(&options) {
+       Ok(config) => config,
+       Err(e) => {
+           eprintln!("{}", e);
+           process::exit(1);
+       }
+   };
+   let rom = match rom_read::get_file_as_byte_vec(&options.rom) {
+       Ok(rom) => rom,
+       Err(e) => {
+           eprintln!("{}", e);
+           process::exit(1);
+       }
+   };
+   let mut settings = config.for_rom(&properties::rom_md5(&rom));
+   settings.merge(&options.settings);
+
+   let cart = match rom_read::cartridge_from_file(&options.rom, rom, &settings.overrides()) {
+       Ok(cart) => cart,
+       Err(e) => {
+           eprintln!("{}", e);
+           process::exit(1);
+       }
+   };
+   if options.command == Command::Info {
+       print_info(&options.rom, cart);
+       return;
+   }
+   for controller in &[cart.properties.left_controller, cart.properties.right_controller] {
+       if *controller != Controller::JOYSTICK {
+           eprintln!("only joysticks are emulated; the {} controller will read as one", controller);
+       }
+   }
+   let standard = TvStandard::from_format(cart.properties.format);
+   let mut machine = Machine::new(cart);
+   machine.tracer = options.tracer.clone();
+
+   if let Some(path) = &options.state {
+       let result = std::fs::read(path)
+           .map_err(|e| e.to_string())
+           .and_then(|state| machine.load_state(&state).map_err(|e| e.to_string()));
+       if let Err(e) = result {
+           eprintln!("failed to load state from {}: {}", path.display(), e);
+           process::exit(1);
+       }
+   }
+   let session = match Session::start(&options, &mut machine) {
+       Ok(session) => session,
+       Err(e) => {
+           eprintln!("{}", e);
+           process::exit(1);
+       }
+   };
+
+   if options.command == Command::Headless {
+       let mut session = session;
+       let frames = match (&session, options.frames) {
+           (_, Some(frames)) => frames,
+           (Some(Session::Playing(player)), None) => player.frames(),
+           _ => 0,
+       };
+       let mut capture = options.video.clone().map(|path| VideoCapture::new(path, options.sample_rate, settings.volume));
+       let palettes = settings.palette.as_deref().and_then(load_palettes);
+       let output = headless::run_with(&mut machine, frames, standard, palettes.as_ref(), &mut |machine| {
+           match &mut session {
+               Some(session) => session.run_frame(machine)?,
+               None => machine.run_frame()?,
+           }
+           let tia = machine.tia();
+           if let (Some(capture), true) = (&mut capture, standard.is_some() || tia.frame_count > DETECT_AFTER_FRAMES) {
+               let frame_standard = standard.unwrap_or(TvStandard::detect(tia.frame_lines));
+               let palette = palettes.as_ref().map_or(frame_standard.palette(), |palettes| &palettes[frame_standard as usize]);
+               let colors = ColorTable::new(palette, frame_standard, &Adjustments::default());
+               let mut rgba = vec![0; WIDTH as usize * frame_standard.scanlines() * 4];
+               colors.render(tia, frame_standard.color_loss(tia.frame_lines), &mut rgba);
+               capture.frame(frame_standard, &rgba, &tia.audio.samples);
+           }
+           Ok(())
+       });
+       let mut output = match output {
+           Ok(output) => output,
+           Err(fault) => {
+               eprintln!("{}", rustari_core::Error::Cpu(fault));
+               process::exit(1);
+           }
+       };
+       if let Some(volume) = settings.volume {
+           audio::set_volume(&mut output.audio, volume);
+       }
+       if let Some(rate) = options.sample_rate {
+           output.resample(rate);
+       }
+       let rom = PathBuf::from(&options.rom);
+       let prefix = options.out.unwrap_or_else(|| PathBuf::from(rom.file_stem().unwrap_or_default()));
+       match output.write_files(&prefix) {
+           Ok(paths) => {
+               for path in paths {
+                   println!("wrote {}", path.display());
+               }
+           }
+           Err(e) => {
+               eprintln!("failed to write output: {}", e);
+               process::exit(1);
+           }
+       }
+       if let Some(path) = &options.screenshot {
+           match capture::write_png(path, output.width, &output.frame) {
+               Ok(()) => println!("wrote {}", path.display()),
+               Err(e) => {
+                   eprintln!("failed to write {}: {}", path.display(), e);
+                   process::exit(1);
+               }
+           }
+       }
+       let video_ok = capture.is_none_or(VideoCapture::finish);
+       let session_ok = session.is_none_or(Session::finish);
+       if !video_ok || !session_ok {
+           process::exit(1);
+       }
+       return;
+   }
+
+   if options.command == Command::Debug {
+       let stdin = io::stdin();
+       let result = Debugger::new().repl(&mut machine, &mut stdin.lock(), &mut io::stdout());
+       if let Err(e) = result {
+           eprintln!("debugger: {}", e);
+           process::exit(1);
+       }
+       return;
+   }
+
+   let bindings = match Bindings::new(&config.keys) {
+       Ok(bindings) => bindings,
+       Err(e) => {
+           eprintln!("{}", e);
+           process::exit(1);
+       }
+   };
+   if let Err(e) = main_loop(machine, standard, &settings, bindings, &options, session) {
+       eprintln!("failed to open a window: {}", e);
+       process::exit(1);
+   }
+}
+
+const WIDTH: u32 = tia::CLOCKS_PER_LINE as u32;
+
+/// Where the frontend keeps what it remembers between runs: `rust_ari` in
+/// `$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`.
+fn config_dir() -> Option<PathBuf> {
+   let base = env::var_os("XDG_CONFIG_HOME")
+       .or_else(|| env::var_os("APPDATA"))
+       .map(PathBuf::from)
+       .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+   Some(base.join("rust_ari"))
+}
+
+/// The config file given with `--config`, or else the one in the config
+/// directory if there is one.
+fn load_config(options: &Options) -> Result<Config, String> {
+   let path = match &options.config {
+       Some(path) => path.clone(),
+       None => match config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+           Some(path) if path.is_file() => path,
+           _ => return Ok(Config::default()),
+       },
+   };
+   Config::load(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The size the window was last left at, if it was remembered.
+fn load_window_size() -> Option<LogicalSize<f64>> {
+   let text = std::fs::read_to_string(config_dir()?.join(WINDOW_SIZE_FILE)).ok()?;
+   let mut numbers = text.split_whitespace().map(|n| n.parse::<f64>().ok().filter(|&n| n >= 1.0));
+   Some(LogicalSize::new(numbers.next()??, numbers.next()??))
+}
+
+/// Remember the window's size for the next run, unless it fills the screen.
+fn save_window_size(window: &Window) {
+   if window.fullscreen().is_some() {
+       return;
+   }
+   let size: LogicalSize<f64> = window.inner_size().to_logical(window.scale_factor());
+   let result = config_dir()
+       .ok_or_else(|| "there's no config directory".to_string())
+       .and_then(|dir| {
+           std::fs::create_dir_all(&dir)
+               .and_then(|()| std::fs::write(dir.join(WINDOW_SIZE_FILE), format!("{} {}\n", size.width.round(), size.height.round())))
+               .map_err(|e| e.to_string())
+       });
+   if let Err(e) = result {
+       error!("failed to remember the window size: {}", e);
+   }
+}
+
+/// A frame buffer the size of the window, so the picture is scaled to it
+/// pixel for pixel.
+fn window_pixels(window: &Window) -> Result<(Pixels, usize), Error> {
+   let size = window.inner_size();
+   let (width, height) = (size.width.max(1), size.height.max(1));
+   let surface = Surface::create(window);
+   let surface_texture = SurfaceTexture::new(width, height, surface);
+   Ok((Pixels::new(width, height, surface_texture)?, width as usize))
+}
+
+fn main_loop(mut machine : Machine, standard: Option<TvStandard>, settings: &Settings, keys: Bindings, options: &Options, session: Option<Session>) -> Result<(), Error> {
    let event_loop = EventLoop::new();
    let mut input = WinitInputHelper::new();
-   let mut timer = 0;
+   let properties = machine.board.cartridge.properties.clone();
+   let mut world = World::new(standard, properties, settings, options, session);
+   let mut title = world.title();
    let window = {
-       let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+       let size = world.shown_size();
+       let initial = match (settings.scale, load_window_size()) {
+           (Some(scale), _) => LogicalSize::new(size.width * scale as f64, size.height * scale as f64),
+           (None, Some(saved)) => saved,
+           (None, None) => LogicalSize::new(size.width * DEFAULT_SCALE as f64, size.height * DEFAULT_SCALE as f64),
+       };
+       let fullscreen = match settings.fullscreen.unwrap_or(false) {
+           true => Some(Fullscreen::Borderless(event_loop.primary_monitor())),
+           false => None,
+       };
        WindowBuilder::new()
-           .with_title("RUSTARI")
-           .with_inner_size(size)
+           .with_title(&title)
+           .with_inner_size(initial)
            .with_min_inner_size(size)
+           .with_fullscreen(fullscreen)
            .build(&event_loop)
            .unwrap()
    };
 
-   let mut pixels = {
-       let window_size = window.inner_size();
-       let surface = Surface::create(&window);
-       let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, surface);
-       Pixels::new(WIDTH, HEIGHT, surface_texture)?
-   };
-   let mut world = World::new();
+   let (mut pixels, mut buffer_width) = window_pixels(&window)?;
 
 
    event_loop.run(move |event, _, control_flow| {
-       let start_time = Instant::now();
        // Draw the current frame
        if let Event::RedrawRequested(_) = event {
          
-           world.draw(pixels.get_frame(), &mut atari, &mut timer);
+           world.draw(pixels.get_frame(), buffer_width, &mut machine);
            if pixels
                .render()
                .map_err(|e| error!("pixels.render() failed: {}", e))
@@ -140,31 +1175,145 @@ fn main_loop(mut atari : atari::Atari) -> Result<(), Error> {
                *control_flow = ControlFlow::Exit;
                return;
            }
-           let elapsed_time = Instant::now().duration_since(start_time).as_millis() as u64;
- 
-           let wait_millis = match 1000 / TARGET_FPS >= elapsed_time {
-               true => 1000 / TARGET_FPS - elapsed_time,
-               false => 0
-           };
-           let new_inst = start_time + std::time::Duration::from_millis(wait_millis);
-           *control_flow = ControlFlow::WaitUntil(new_inst);
+
+           // wake up when the next frame is due on the real console
+           *control_flow = world.control_flow();
        }
 
        // Handle input events
        if input.update(event) {
            // Close events
-           if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+           if input.key_pressed(keys.quit) || input.quit() {
+               if let Some(session) = world.session.take() {
+                   session.finish();
+               }
+               if let Some(capture) = world.capture.take() {
+                   capture.finish();
+               }
+               save_window_size(&window);
                *control_flow = ControlFlow::Exit;
                return;
            }
 
-           // Resize the window
-           if let Some(size) = input.window_resized() {
-               pixels.resize(size.width, size.height);
+           // Left joystick on the arrow keys, fire on Space
+           machine.set_joystick(0, Joystick {
+               up: input.key_held(keys.up),
+               down: input.key_held(keys.down),
+               left: input.key_held(keys.left),
+               right: input.key_held(keys.right),
+           });
+           machine.set_fire(0, input.key_held(keys.fire));
+
+           // Console switches: F1 select, F2 reset, F3/F4 Color/B&W
+           machine.set_select_switch(input.key_held(keys.select));
+           machine.set_reset_switch(input.key_held(keys.reset));
+           if input.key_pressed(keys.color) {
+               machine.set_color_switch(true);
+           }
+           if input.key_pressed(keys.bw) {
+               machine.set_color_switch(false);
+           }
+
+           // Palette adjustments: Tab picks the setting, Page Up/Down change it
+           if input.key_pressed(keys.adjust) {
+               world.adjusting = world.adjusting.next();
+               world.overlay.show(format!("adjusting {}", world.adjusting));
+           }
+           if input.key_pressed(keys.adjust_up) {
+               world.adjust(1);
+           }
+           if input.key_pressed(keys.adjust_down) {
+               world.adjust(-1);
+           }
+
+           // P pauses and resumes, Period runs one frame and pauses
+           if input.key_pressed(keys.pause) {
+               world.toggle_pause();
+           }
+           if input.key_pressed(keys.advance) {
+               world.paused = true;
+               world.advance = true;
+           }
+
+           // Speed: [ and ] slow down and speed up, \ goes back to normal,
+           // ` fast forwards while held
+           if input.key_pressed(keys.slower) {
+               world.change_speed(-1);
+           }
+           if input.key_pressed(keys.faster) {
+               world.change_speed(1);
+           }
+           if input.key_pressed(keys.normal_speed) {
+               world.change_speed(NORMAL_SPEED as i32 - world.speed as i32);
+           }
+           let fast_forward = input.key_held(keys.fast_forward);
+           if fast_forward != world.fast_forward {
+               world.fast_forward = fast_forward;
+               world.update_speed();
+           }
+
+           // F12 saves a screenshot
+           if input.key_pressed(keys.screenshot) {
+               world.screenshot = true;
+           }
+
+           // F9 shows or hides the debug overlay
+           if input.key_pressed(keys.overlay) {
+               world.overlay.toggle();
+           }
+
+           // Save states: F5 saves, F6 picks the next slot, F7 loads
+           if input.key_pressed(keys.save_state) {
+               world.save_state(&machine);
+           }
+           if input.key_pressed(keys.next_slot) {
+               world.next_slot();
+           }
+           // loading a state or rewinding would break a movie
+           if input.key_pressed(keys.load_state) && world.session.is_none() {
+               world.load_state(&mut machine);
+           }
+
+           // Rewind while Backspace is held
+           world.rewinding = input.key_held(keys.rewind) && world.session.is_none();
+
+           // F10 switches the CRT effects off and on
+           if input.key_pressed(keys.effects) {
+               world.effects = !world.effects;
+               world.dirty = true;
+               world.overlay.show(format!("CRT effects {}", if world.effects { "on" } else { "off" }));
+           }
+
+           // F11 fills the screen or goes back to a window
+           if input.key_pressed(keys.fullscreen) {
+               window.set_fullscreen(match window.fullscreen() {
+                   Some(_) => None,
+                   None => Some(Fullscreen::Borderless(window.current_monitor())),
+               });
+           }
+
+           // A new frame buffer for the new size of the window
+           if input.window_resized().is_some() {
+               match window_pixels(&window) {
+                   Ok((p, width)) => {
+                       pixels = p;
+                       buffer_width = width;
+                   }
+                   Err(e) => {
+                       error!("Pixels::new() failed: {}", e);
+                       *control_flow = ControlFlow::Exit;
+                       return;
+                   }
+               }
+           }
+
+           // The title says when the machine is paused or at another speed
+           if world.title() != title {
+               title = world.title();
+               window.set_title(&title);
            }
 
-           // Update internal state and request a redraw
-           world.update();
+           // Request a redraw
            window.request_redraw();
        }
 
@@ -0,0 +1,132 @@
+//! A debug overlay drawn into the frame buffer over the picture: the CPU
+//! registers and flags, where the TIA's beam is, the RIOT timer and the
+//! cartridge banks that are mapped in. It also shows status messages, such
+//! as a palette adjustment's new value, for a moment in the bottom left
+//! corner whether or not the debug lines are showing.
+
+use std::time::{Duration, Instant};
+
+use rusttype::{point, Font, Scale};
+
+use rustari_core::debugger;
+use rustari_core::riot;
+use rustari_core::Machine;
+
+const FONT: &[u8] = include_bytes!("../assets/consola.ttf");
+
+/// Text height in frame buffer pixels.
+const TEXT_HEIGHT: f32 = 10.0;
+/// Space around the text, in pixels.
+const MARGIN: usize = 2;
+const TEXT_COLOR: [u8; 3] = [0x00, 0xFF, 0x00];
+/// How much of the picture shows through the box behind the text, out of 256.
+const SHOW_THROUGH: u16 = 64;
+/// How long a status message stays up.
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+pub struct Overlay {
+   font: Font<'static>,
+   pub visible: bool,
+   /// The status message showing and when it was shown.
+   message: Option<(String, Instant)>,
+}
+
+impl Overlay {
+   pub fn new() -> Overlay {
+       let font = Font::from_bytes(FONT).expect("the bundled font is valid");
+       Overlay { font, visible: false, message: None }
+   }
+
+   pub fn toggle(&mut self) {
+       self.visible = !self.visible;
+   }
+
+   /// Show `message` for a moment, in place of any already showing.
+   pub fn show(&mut self, message: String) {
+       self.message = Some((message, Instant::now()));
+   }
+
+   /// When the status message showing should be taken down, for the event
+   /// loop to draw again then.
+   pub fn message_ends(&self) -> Option<Instant> {
+       self.message.as_ref().map(|(_, shown)| *shown + MESSAGE_TIME)
+   }
+
+   /// What the overlay shows for the state of `machine`.
+   fn lines(machine: &Machine) -> Vec<String> {
+       let cpu = &machine.cpu;
+       let tia = machine.tia();
+       let riot = &machine.board.riot;
+       let banks = machine.board.cartridge.banks();
+       let banks: Vec<String> = banks.iter().map(|bank| bank.to_string()).collect();
+       vec![
+           format!("PC {:04X} A {:02X} X {:02X} Y {:02X} SP {:02X}", cpu.pc, cpu.a_reg, cpu.x_reg, cpu.y_reg, cpu.s_pnt),
+           format!("P  {:02X} {}", cpu.flags, debugger::flags(cpu.flags)),
+           format!("line {} clock {}", tia.scanline, tia.clock),
+           format!("INTIM {:02X} /{}", riot.read(riot::INTIM), riot.interval()),
+           format!("bank {}", banks.join(" ")),
+       ]
+   }
+
+   /// Draw the overlay, if it's visible, and any status message into
+   /// `frame`, an RGBA buffer `width` pixels wide, each over a darkened box:
+   /// the overlay in the top left corner and the message in the bottom left.
+   pub fn draw(&mut self, machine: &Machine, frame: &mut [u8], width: usize) {
+       if self.visible {
+           self.draw_text(&Overlay::lines(machine), frame, width, false);
+       }
+       if let Some((message, shown)) = &self.message {
+           if shown.elapsed() < MESSAGE_TIME {
+               self.draw_text(&[message.clone()], frame, width, true);
+           } else {
+               self.message = None;
+           }
+       }
+   }
+
+   /// Draw `lines` over a darkened box in the top left corner of `frame`,
+   /// or the bottom left if `bottom` is set.
+   fn draw_text(&self, lines: &[String], frame: &mut [u8], width: usize, bottom: bool) {
+       let height = frame.len() / 4 / width;
+       let scale = Scale::uniform(TEXT_HEIGHT);
+       let advance = self.font.glyph('0').scaled(scale).h_metrics().advance_width;
+       let ascent = self.font.v_metrics(scale).ascent;
+
+       let longest = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+       let box_width = (longest as f32 * advance).ceil() as usize + 2 * MARGIN;
+       let box_height = lines.len() * TEXT_HEIGHT as usize + 2 * MARGIN;
+       let top = match bottom {
+           true => height.saturating_sub(box_height),
+           false => 0,
+       };
+       for y in top..(top + box_height).min(height) {
+           for x in 0..box_width.min(width) {
+               let i = (y * width + x) * 4;
+               for channel in &mut frame[i..i + 3] {
+                   *channel = (*channel as u16 * SHOW_THROUGH / 256) as u8;
+               }
+           }
+       }
+
+       for (row, line) in lines.iter().enumerate() {
+           let baseline = (top + MARGIN) as f32 + row as f32 * TEXT_HEIGHT + ascent;
+           for glyph in self.font.layout(line, scale, point(MARGIN as f32, baseline)) {
+               let bounds = match glyph.pixel_bounding_box() {
+                   Some(bounds) => bounds,
+                   None => continue,
+               };
+               glyph.draw(|gx, gy, coverage| {
+                   let x = bounds.min.x + gx as i32;
+                   let y = bounds.min.y + gy as i32;
+                   if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                       return;
+                   }
+                   let i = (y as usize * width + x as usize) * 4;
+                   for (channel, &color) in frame[i..i + 3].iter_mut().zip(TEXT_COLOR.iter()) {
+                       *channel = (*channel as f32 * (1.0 - coverage) + color as f32 * coverage) as u8;
+                   }
+               });
+           }
+       }
+   }
+}
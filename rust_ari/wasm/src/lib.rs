@@ -0,0 +1,130 @@
+//! The emulator for web pages: the core built for `wasm32-unknown-unknown`
+//! with a small API for JavaScript, generated by `wasm-bindgen`.
+//!
+//! The page hands over the ROM as bytes, sets the input and runs a frame at
+//! a time, at `frameRate()` frames a second, taking the picture as RGBA to
+//! put in an `ImageData` and the audio as samples to queue for Web Audio.
+//! Nothing here touches files; the core is built without them.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+
+use rustari_core::emulator::Emulator;
+use rustari_core::properties::{Overrides, Properties};
+use rustari_core::riot::Joystick;
+use rustari_core::rom_read;
+use rustari_core::viewport::Crop;
+
+#[wasm_bindgen]
+pub struct Rustari {
+    emulator: Emulator,
+    properties: Properties,
+}
+
+#[wasm_bindgen]
+impl Rustari {
+    /// Power on with `rom` inserted, its bank switching scheme, TV standard
+    /// and controllers found from the built-in properties or the ROM.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Rustari, JsError> {
+        let cartridge = rom_read::cartridge_from_bytes(rom.to_vec(), &Overrides::default())?;
+        let properties = cartridge.properties.clone();
+        return Ok(Rustari { emulator: Emulator::new(cartridge), properties });
+    }
+
+    /// Run the machine for a frame. A CPU fault throws, and the machine
+    /// stays at the faulting instruction until reset.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) -> Result<(), JsError> {
+        self.emulator.run_frame()?;
+        return Ok(());
+    }
+
+    /// The picture's width in pixels, one per color clock.
+    pub fn width(&self) -> usize {
+        return self.crop().width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.crop().height;
+    }
+
+    /// How many times as wide as they're tall the pixels are on a TV.
+    #[wasm_bindgen(js_name = pixelAspect)]
+    pub fn pixel_aspect(&self) -> f64 {
+        return self.emulator.standard().pixel_aspect();
+    }
+
+    /// The last frame as a TV shows it, RGBA `width()` by `height()`
+    /// pixels, ready for `new ImageData(frame, width, height)`.
+    pub fn frame(&self) -> Clamped<Vec<u8>> {
+        return Clamped(self.crop().apply(self.emulator.frame()));
+    }
+
+    /// The audio generated since it was last taken, mono at `sampleRate()`,
+    /// from 0 for silence to just under 1.
+    #[wasm_bindgen(js_name = takeAudio)]
+    pub fn take_audio(&mut self) -> Vec<f32> {
+        return self.emulator.take_audio().iter().map(|&sample| sample as f32 / 256.0).collect();
+    }
+
+    #[wasm_bindgen(js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        return self.emulator.sample_rate();
+    }
+
+    /// Frames a second on the real console.
+    #[wasm_bindgen(js_name = frameRate)]
+    pub fn frame_rate(&self) -> f64 {
+        let standard = self.emulator.standard();
+        return 1.0 / standard.frame_duration(standard.scanlines()).as_secs_f64();
+    }
+
+    /// `NTSC`, `PAL` or `SECAM`, detected from the frames drawn unless the
+    /// cartridge's properties give it.
+    pub fn standard(&self) -> String {
+        return self.emulator.standard().to_string();
+    }
+
+    /// Move the joystick of `player`, 0 for the left and 1 for the right.
+    #[wasm_bindgen(js_name = setJoystick)]
+    pub fn set_joystick(&mut self, player: usize, up: bool, down: bool, left: bool, right: bool, fire: bool) {
+        let machine = &mut self.emulator.machine;
+        machine.set_joystick(player.min(1), Joystick { up, down, left, right });
+        machine.set_fire(player.min(1), fire);
+    }
+
+    /// Hold or let go of the console's Select and Reset switches.
+    #[wasm_bindgen(js_name = setSwitches)]
+    pub fn set_switches(&mut self, select: bool, reset: bool) {
+        self.emulator.machine.set_select_switch(select);
+        self.emulator.machine.set_reset_switch(reset);
+    }
+
+    /// Set the Color/B&W switch.
+    #[wasm_bindgen(js_name = setColor)]
+    pub fn set_color(&mut self, color: bool) {
+        self.emulator.machine.set_color_switch(color);
+    }
+
+    /// Power cycle the console.
+    pub fn reset(&mut self) {
+        self.emulator.machine.reset();
+    }
+
+    #[wasm_bindgen(js_name = saveState)]
+    pub fn save_state(&self) -> Vec<u8> {
+        return self.emulator.machine.save_state();
+    }
+
+    /// Restore a state from `saveState()`, throwing if it can't be loaded.
+    #[wasm_bindgen(js_name = loadState)]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        self.emulator.machine.load_state(state)?;
+        return Ok(());
+    }
+
+    fn crop(&self) -> Crop {
+        return Crop::visible(self.emulator.standard(), &self.properties);
+    }
+}
@@ -0,0 +1,62 @@
+//! The JavaScript API, run under Node by `wasm-bindgen-test`:
+//!
+//! cargo test -p rustari-wasm --target wasm32-unknown-unknown
+
+use wasm_bindgen_test::*;
+
+use rustari_wasm::Rustari;
+
+const SPACE_INVADERS: &[u8] = include_bytes!("../../spaceInvaders.a26");
+
+fn run(rustari: &mut Rustari, frames: usize) {
+    for _ in 0..frames {
+        assert!(rustari.run_frame().is_ok());
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_runs_frames() {
+    let mut rustari = Rustari::new(SPACE_INVADERS).unwrap();
+    run(&mut rustari, 60);
+    assert_eq!(rustari.standard(), "NTSC");
+    assert_eq!((rustari.width(), rustari.height()), (160, 210));
+    assert!((rustari.frame_rate() - 59.92).abs() < 0.01);
+
+    let frame = rustari.frame().0;
+    assert_eq!(frame.len(), 160 * 210 * 4);
+    assert!(frame.chunks(4).any(|pixel| pixel != &frame[..4]));
+    assert!(frame.chunks(4).all(|pixel| pixel[3] == 0xFF));
+
+    // two samples a line
+    let audio = rustari.take_audio();
+    assert!((59 * 2 * 262..=61 * 2 * 262).contains(&audio.len()));
+    assert!(audio.iter().all(|&sample| (0.0..1.0).contains(&sample)));
+    assert!(rustari.take_audio().is_empty());
+    assert_eq!(rustari.sample_rate(), 31399);
+}
+
+#[wasm_bindgen_test]
+fn test_input_and_states() {
+    let mut rustari = Rustari::new(SPACE_INVADERS).unwrap();
+    run(&mut rustari, 30);
+    let state = rustari.save_state();
+
+    rustari.set_switches(false, true);
+    run(&mut rustari, 2);
+    rustari.set_switches(false, false);
+    rustari.set_joystick(0, false, false, true, false, true);
+    run(&mut rustari, 30);
+    let moved = rustari.frame().0;
+
+    assert!(rustari.load_state(&state).is_ok());
+    rustari.set_joystick(0, false, false, false, false, false);
+    run(&mut rustari, 32);
+    assert_ne!(rustari.frame().0, moved);
+
+    assert!(rustari.load_state(&[1, 2, 3]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_rejects_an_empty_rom() {
+    assert!(Rustari::new(&[]).is_err());
+}